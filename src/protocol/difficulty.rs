@@ -0,0 +1,293 @@
+//! Compact difficulty targets, chain work and Zcash's difficulty adjustment, see the [Zcash
+//! protocol spec](https://zips.z.cash/protocol/protocol.pdf#diffadjustment).
+
+pub use primitive_types::U256;
+
+use crate::protocol::{network::Network, payload::block::Header};
+
+/// The number of headers whose timestamps make up the median time past.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// A 256-bit target in the compact `nBits` encoding of block headers.
+///
+/// The high byte is the target's length in bytes, the low 23 bits its most significant bits and
+/// bit 23 its sign.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompactTarget(pub u32);
+
+impl CompactTarget {
+    /// Expands the compact encoding into the 256-bit target, `None` if it's negative or doesn't
+    /// fit into 256 bits.
+    pub fn target(&self) -> Option<U256> {
+        let exponent = self.0 >> 24;
+        let mantissa = self.0 & 0x007f_ffff;
+
+        if mantissa == 0 {
+            return Some(U256::zero());
+        }
+
+        if self.0 & 0x0080_0000 != 0 {
+            return None;
+        }
+
+        let target = if exponent <= 3 {
+            U256::from(mantissa >> (8 * (3 - exponent)))
+        } else {
+            let shift = 8 * (exponent - 3) as usize;
+            // The mantissa's set bits mustn't be shifted out at the top.
+            if shift + 32 - mantissa.leading_zeros() as usize > 256 {
+                return None;
+            }
+
+            U256::from(mantissa) << shift
+        };
+
+        Some(target)
+    }
+
+    /// Encodes the target, the lowest bits beyond the 23-bit mantissa are truncated.
+    pub fn from_target(target: U256) -> Self {
+        let mut size = target.bits().div_ceil(8) as u32;
+        let mut mantissa = if size <= 3 {
+            target.low_u32() << (8 * (3 - size))
+        } else {
+            (target >> (8 * (size - 3) as usize)).low_u32()
+        };
+
+        // Bit 23 would flip the sign, move the mantissa down a byte instead.
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+
+        Self(size << 24 | mantissa)
+    }
+
+    /// Returns the expected number of hashes needed to meet the target, `2^256 / (target + 1)`.
+    ///
+    /// Invalid and zero targets have no work.
+    pub fn work(&self) -> U256 {
+        match self.target() {
+            Some(target) if !target.is_zero() => {
+                // 2^256 doesn't fit, but `(2^256 - target - 1) / (target + 1) + 1` is equal.
+                (!target / (target + 1)) + 1
+            }
+            _ => U256::zero(),
+        }
+    }
+}
+
+impl From<u32> for CompactTarget {
+    fn from(bits: u32) -> Self {
+        Self(bits)
+    }
+}
+
+/// Returns the total work of the headers, the sum of each header's work.
+pub fn chain_work(headers: &[Header]) -> U256 {
+    headers
+        .iter()
+        .fold(U256::zero(), |total, header| total + header.work())
+}
+
+/// Returns the median of the timestamps of the last (up to) [`MEDIAN_TIME_SPAN`] headers.
+pub fn median_time_past(headers: &[Header]) -> Option<u32> {
+    let start = headers.len().saturating_sub(MEDIAN_TIME_SPAN);
+    let mut timestamps: Vec<u32> = headers[start..].iter().map(|h| h.timestamp).collect();
+    timestamps.sort_unstable();
+
+    timestamps.get(timestamps.len() / 2).copied()
+}
+
+/// The parameters of the difficulty adjustment algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DifficultyParams {
+    /// The easiest allowed target.
+    pub pow_limit: U256,
+    /// The number of preceding headers whose targets are averaged.
+    pub averaging_window: usize,
+    /// The target block spacing in seconds.
+    pub target_spacing: i64,
+    /// The factor the deviation from the expected timespan is divided by.
+    pub damping_factor: i64,
+    /// The largest per-block decrease of the target (difficulty increase), in percent.
+    pub max_adjust_up: i64,
+    /// The largest per-block increase of the target (difficulty decrease), in percent.
+    pub max_adjust_down: i64,
+}
+
+impl DifficultyParams {
+    /// Returns the network's parameters, with the post-Blossom target spacing of 75 seconds.
+    ///
+    /// Blocks before the Blossom upgrade were spaced 150 seconds apart, set
+    /// [`DifficultyParams::target_spacing`] accordingly when working with them.
+    pub fn for_network(network: Network) -> Self {
+        let pow_limit = match network {
+            Network::Mainnet => CompactTarget(0x1f07_ffff),
+            Network::Regtest => CompactTarget(0x200f_0f0f),
+            _ => CompactTarget(0x2007_ffff),
+        };
+
+        Self {
+            pow_limit: pow_limit.target().unwrap(),
+            averaging_window: 17,
+            target_spacing: 75,
+            damping_factor: 4,
+            max_adjust_up: 16,
+            max_adjust_down: 32,
+        }
+    }
+
+    /// Returns the compact target required of the header following `headers`, which are ordered
+    /// oldest first and end with the new header's parent.
+    ///
+    /// Chains shorter than the averaging window (plus the header preceding it) are at the
+    /// `pow_limit`. Testnet's minimum difficulty exception for slow blocks isn't applied.
+    pub fn next_bits(&self, headers: &[Header]) -> CompactTarget {
+        if headers.len() <= self.averaging_window {
+            return CompactTarget::from_target(self.pow_limit);
+        }
+
+        let window = &headers[headers.len() - self.averaging_window..];
+        let total = window.iter().fold(U256::zero(), |total, header| {
+            total + header.compact_target().target().unwrap_or_default()
+        });
+        let average = total / U256::from(self.averaging_window);
+
+        // The timespan is measured between the medians ending at the window's bounds.
+        let first = headers.len() - self.averaging_window;
+        let last_time = median_time_past(headers).unwrap();
+        let first_time = median_time_past(&headers[..first]).unwrap();
+
+        self.next_target(average, i64::from(last_time) - i64::from(first_time))
+    }
+
+    /// Scales the average target by the damped and clamped actual timespan of the window.
+    fn next_target(&self, average: U256, actual_timespan: i64) -> CompactTarget {
+        let expected = self.averaging_window_timespan();
+        let min_timespan = expected * (100 - self.max_adjust_up) / 100;
+        let max_timespan = expected * (100 + self.max_adjust_down) / 100;
+
+        let damped = expected + (actual_timespan - expected) / self.damping_factor;
+        let timespan = damped.clamp(min_timespan, max_timespan);
+
+        // Divide first to avoid overflowing, as zcashd does.
+        let target = average / U256::from(expected) * U256::from(timespan);
+
+        CompactTarget::from_target(target.min(self.pow_limit))
+    }
+
+    fn averaging_window_timespan(&self) -> i64 {
+        self.averaging_window as i64 * self.target_spacing
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::payload::block::Block;
+
+    #[test]
+    fn compact_round_trip() {
+        for bits in [
+            0x1d00_ffff,
+            0x1f07_ffff,
+            0x2007_ffff,
+            0x200f_0f0f,
+            0x0312_3456,
+        ] {
+            let target = CompactTarget(bits).target().unwrap();
+            assert_eq!(CompactTarget::from_target(target), CompactTarget(bits));
+        }
+
+        assert_eq!(
+            CompactTarget(0x2007_ffff).target(),
+            Some(U256::from(0x07_ffff) << (8 * 29))
+        );
+        // Mantissa bytes below the exponent are shifted out.
+        assert_eq!(CompactTarget(0x0112_3456).target(), Some(U256::from(0x12)));
+        // A set top bit moves into the exponent.
+        assert_eq!(
+            CompactTarget::from_target(U256::from(0x80)),
+            CompactTarget(0x0200_8000)
+        );
+    }
+
+    #[test]
+    fn invalid_compact_targets() {
+        // Negative.
+        assert_eq!(CompactTarget(0x0480_0001).target(), None);
+        // Overflowing.
+        assert_eq!(CompactTarget(0x2301_0000).target(), None);
+        assert_eq!(CompactTarget(0x2301_0000).work(), U256::zero());
+        // A zero mantissa is zero regardless of the exponent or sign.
+        assert_eq!(CompactTarget(0x2580_0000).target(), Some(U256::zero()));
+    }
+
+    #[test]
+    fn work() {
+        // The well known work of Bitcoin's genesis block.
+        assert_eq!(
+            CompactTarget(0x1d00_ffff).work(),
+            U256::from(0x0001_0001_0001u64)
+        );
+
+        let headers: Vec<_> = Block::initial_testnet_blocks()
+            .into_iter()
+            .map(|block| block.header)
+            .collect();
+        let single = CompactTarget(headers[0].bits).work();
+        assert!(headers.iter().all(|header| header.bits == headers[0].bits));
+        assert_eq!(chain_work(&headers), single * headers.len());
+    }
+
+    fn chain(bits: u32, spacing: u32, len: usize) -> Vec<Header> {
+        let mut header = Block::testnet_genesis().header;
+        header.bits = bits;
+
+        (0..len as u32)
+            .map(|i| {
+                let mut header = header.clone();
+                header.timestamp = 1_000_000 + i * spacing;
+                header
+            })
+            .collect()
+    }
+
+    #[test]
+    fn difficulty_adjustment() {
+        let params = DifficultyParams::for_network(Network::Mainnet);
+        // A multiple of the 1275s window timespan, which the average is divided by first.
+        let bits = 0x1c04_fb00;
+
+        // Too short for the averaging window.
+        assert_eq!(
+            params.next_bits(&chain(bits, 75, 17)),
+            CompactTarget(0x1f07_ffff)
+        );
+
+        // On schedule.
+        assert_eq!(params.next_bits(&chain(bits, 75, 30)), CompactTarget(bits));
+
+        // Fast blocks are clamped to a 16% lower target.
+        let target = CompactTarget(bits).target().unwrap();
+        let expected = target / 1275 * 1071;
+        assert_eq!(
+            params.next_bits(&chain(bits, 1, 30)),
+            CompactTarget::from_target(expected)
+        );
+
+        // Slow blocks are damped: 17 * 150s is 1275s late, of which a quarter is applied.
+        let expected = target / 1275 * (1275 + 1275 / 4);
+        assert_eq!(
+            params.next_bits(&chain(bits, 150, 30)),
+            CompactTarget::from_target(expected)
+        );
+
+        // The target never exceeds the limit.
+        assert_eq!(
+            params.next_bits(&chain(0x1f07_ffff, 1000, 30)),
+            CompactTarget(0x1f07_ffff)
+        );
+    }
+}
@@ -0,0 +1,796 @@
+//! An annotated dissector for raw message bytes.
+//!
+//! [`dissect`] splits the bytes of an encoded message into a tree of [`Field`]s, each with its
+//! offset, raw bytes and decoded value. Unlike [`Message::decode`](crate::protocol::message::Message::decode),
+//! it doesn't stop at the first invalid field: a bad magic, command, body length or checksum is
+//! marked and the payload is dissected regardless, and so is every fixed size field following a
+//! corrupt one. Only a corrupt length prefix ends a collection early, as the position of what
+//! follows can't be known; the remaining bytes are then kept as a single marked field.
+
+use std::{fmt, net::Ipv6Addr};
+
+use time::OffsetDateTime;
+
+use crate::protocol::{
+    message::{checksum, constants::*},
+    network::Network,
+    payload::{
+        addr::{NetAddr, MAX_ADDRV2_ADDR_LEN},
+        codec::Codec,
+        inv::ObjectKind,
+        reject::CCode,
+        Hash, Services, Tx,
+    },
+};
+
+/// The number of raw bytes shown per field by [`Field`]'s `Display` implementation.
+const MAX_DISPLAYED_BYTES: usize = 32;
+
+/// A dissected field, see [`dissect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// The name of the field, e.g. `checksum` or `[3]` for collection elements.
+    pub name: String,
+    /// The offset of the field from the start of the dissected bytes.
+    pub offset: usize,
+    /// The bytes of the field, including those of its children.
+    pub raw: Vec<u8>,
+    /// The decoded value, empty for fields with children or which failed to decode.
+    pub value: String,
+    /// Why the field is invalid, if it is.
+    pub error: Option<String>,
+    /// The fields this one is made of.
+    pub children: Vec<Field>,
+}
+
+impl Field {
+    /// Returns the raw bytes as hex.
+    pub fn raw_hex(&self) -> String {
+        hex::encode(&self.raw)
+    }
+
+    /// Returns the field at the `.` separated `path` of names below this one, e.g.
+    /// `header.checksum`.
+    pub fn get(&self, path: &str) -> Option<&Field> {
+        path.split('.').try_fold(self, |field, name| {
+            field.children.iter().find(|child| child.name == name)
+        })
+    }
+
+    /// Returns the invalid fields in this tree, in the order they appear in the bytes.
+    pub fn errors(&self) -> Vec<&Field> {
+        let mut errors = Vec::new();
+        self.collect_errors(&mut errors);
+        errors
+    }
+
+    fn collect_errors<'a>(&'a self, errors: &mut Vec<&'a Field>) {
+        if self.error.is_some() {
+            errors.push(self);
+        }
+
+        for child in &self.children {
+            child.collect_errors(errors);
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        write!(
+            f,
+            "{:06} {:>6}  {:indent$}{}",
+            self.offset,
+            self.raw.len(),
+            "",
+            self.name,
+            indent = depth * 2
+        )?;
+
+        if self.children.is_empty() {
+            if !self.value.is_empty() {
+                write!(f, " = {}", self.value)?;
+            }
+
+            if self.raw.len() > MAX_DISPLAYED_BYTES {
+                write!(f, " [{}..]", hex::encode(&self.raw[..MAX_DISPLAYED_BYTES]))?;
+            } else {
+                write!(f, " [{}]", self.raw_hex())?;
+            }
+        }
+
+        if let Some(error) = &self.error {
+            write!(f, " !! {}", error)?;
+        }
+        writeln!(f)?;
+
+        for child in &self.children {
+            child.fmt_indented(f, depth + 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Field {
+    /// Writes the tree with one field per line: its offset, length, name, value and raw bytes,
+    /// followed by `!!` and the error for invalid fields.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// Dissects the bytes of a message, header included, sent on the `network`.
+pub fn dissect(network: Network, bytes: &[u8]) -> Field {
+    let mut d = Dissector::new(bytes);
+
+    let (command, body_length, expected_checksum) = d.group("header", |d| {
+        d.field("magic", 4, |raw| {
+            let magic: [u8; 4] = raw.try_into().unwrap();
+            match Network::from_magic(magic) {
+                actual if actual == network => Ok(((), actual.to_string())),
+                actual => Err(format!("expected {} magic, got {}", network, actual)),
+            }
+        });
+        let command = d.field("command", 12, |raw| {
+            let command: [u8; 12] = raw.try_into().unwrap();
+            command_name(&command).map(|name| (command, name))
+        });
+        let body_length = d.u32("body_length");
+        let checksum = d.field("checksum", 4, |raw| {
+            let checksum = u32::from_le_bytes(raw.try_into().unwrap());
+            Ok((checksum, format!("{:#010x}", checksum)))
+        });
+
+        (command, body_length, checksum)
+    });
+
+    // Dissect what's there if the body length is corrupt, marking any difference.
+    let body_start = d.pos;
+    let available = bytes.len() - body_start;
+    let body_len = body_length.map_or(available, |len| (len as usize).min(available));
+    let body = &bytes[body_start..][..body_len];
+
+    let header = d.fields.last_mut().unwrap();
+    if let Some(body_length) = body_length {
+        if body_length as usize != available {
+            header.children[2].error = Some(format!(
+                "body length is {}, but {} bytes follow the header",
+                body_length, available
+            ));
+        }
+    }
+    if let Some(expected) = expected_checksum {
+        let actual = checksum(body);
+        if expected != actual {
+            header.children[3].error = Some(format!(
+                "checksum doesn't match the body's {:#010x}",
+                actual
+            ));
+        }
+    }
+
+    let mut body_d = Dissector {
+        bytes: &bytes[..body_start + body_len],
+        pos: body_start,
+        fields: Vec::new(),
+    };
+    body_d.group("payload", |d| dissect_body(d, command));
+    d.fields.append(&mut body_d.fields);
+    d.pos = body_start + body_len;
+
+    if d.remaining() > 0 {
+        d.rest("trailing", Some("bytes beyond the body length".to_string()));
+    }
+
+    d.into_root("message")
+}
+
+/// Dissects a message payload, without a header, of the message with the `command`.
+pub fn dissect_payload(command: [u8; 12], bytes: &[u8]) -> Field {
+    let mut d = Dissector::new(bytes);
+    dissect_body(&mut d, Some(command));
+
+    d.into_root("payload")
+}
+
+/// Returns the command as a string, if it's ASCII padded with `NUL`s.
+fn command_name(command: &[u8; 12]) -> Result<String, String> {
+    let len = command.iter().position(|b| *b == 0).unwrap_or(12);
+    let (name, padding) = command.split_at(len);
+
+    if !name.is_ascii() || padding.iter().any(|b| *b != 0) {
+        return Err(format!(
+            "malformed command {:?}",
+            String::from_utf8_lossy(command)
+        ));
+    }
+
+    Ok(String::from_utf8(name.to_vec()).unwrap())
+}
+
+fn dissect_body(d: &mut Dissector, command: Option<[u8; 12]>) {
+    match command {
+        Some(VERSION_COMMAND) => version(d),
+        Some(
+            VERACK_COMMAND | GETADDR_COMMAND | MEMPOOL_COMMAND | SENDADDRV2_COMMAND
+            | FILTERCLEAR_COMMAND,
+        ) => {}
+        Some(PING_COMMAND | PONG_COMMAND) => {
+            d.u64("nonce");
+        }
+        Some(ADDR_COMMAND) => d.list("addrs", |d| {
+            d.timestamp_u32("last_seen");
+            network_addr(d);
+        }),
+        Some(ADDRV2_COMMAND) => d.list("addrs", addr_v2),
+        Some(GETHEADERS_COMMAND | GETBLOCKS_COMMAND) => {
+            d.u32("version");
+            d.list("block_locator_hashes", |d| {
+                d.hash("hash");
+            });
+            d.hash("hash_stop");
+        }
+        Some(HEADERS_COMMAND) => d.list("headers", |d| {
+            block_header(d);
+            d.compact_size("tx_count");
+        }),
+        Some(BLOCK_COMMAND) => {
+            d.group("header", block_header);
+            d.list("txs", |d| d.tx("tx"));
+        }
+        Some(GETDATA_COMMAND | INV_COMMAND | NOTFOUND_COMMAND) => d.list("inventory", inv_hash),
+        Some(TX_COMMAND) => d.tx("tx"),
+        Some(REJECT_COMMAND) => {
+            d.var_str("message");
+            d.field("ccode", 1, |raw| {
+                CCode::decode(&mut &raw[..])
+                    .map(|ccode| ((), format!("{:?}", ccode)))
+                    .map_err(|err| err.to_string())
+            });
+            d.var_str("reason");
+            if d.remaining() > 0 {
+                d.rest("data", None);
+            }
+        }
+        Some(FILTERLOAD_COMMAND) => {
+            d.var_bytes("filter");
+            d.u32("hash_fn_count");
+            d.u32("tweak");
+            d.u8("flags");
+        }
+        Some(FILTERADD_COMMAND) => d.var_bytes("data"),
+        Some(MERKLEBLOCK_COMMAND) => {
+            d.group("header", block_header);
+            d.u32("tx_count");
+            d.list("hashes", |d| {
+                d.hash("hash");
+            });
+            d.var_bytes("flags");
+        }
+        // Unknown and malformed commands.
+        _ => {
+            if d.remaining() > 0 {
+                d.rest("data", None);
+            }
+        }
+    }
+
+    if d.remaining() > 0 {
+        d.rest(
+            "unparsed",
+            Some("bytes left over after the payload".to_string()),
+        );
+    }
+}
+
+fn version(d: &mut Dissector) {
+    d.u32("version");
+    d.services("services");
+    d.field("timestamp", 8, |raw| {
+        let timestamp = i64::from_le_bytes(raw.try_into().unwrap());
+        Ok(((), describe_timestamp(timestamp)))
+    });
+    d.group("addr_recv", network_addr);
+    d.group("addr_from", network_addr);
+    d.u64("nonce");
+    d.var_str("user_agent");
+    d.u32("start_height");
+
+    // Legacy peers omit the relay flag.
+    if d.remaining() > 0 {
+        d.field("relay", 1, |raw| Ok(((), (raw[0] != 0).to_string())));
+    }
+}
+
+fn network_addr(d: &mut Dissector) {
+    d.services("services");
+    d.field("addr", 16, |raw| {
+        let addr = Ipv6Addr::from(<[u8; 16]>::try_from(raw).unwrap());
+        let value = match addr.to_ipv4() {
+            Some(addr) => addr.to_string(),
+            None => addr.to_string(),
+        };
+        Ok(((), value))
+    });
+    d.field("port", 2, |raw| {
+        Ok(((), u16::from_be_bytes(raw.try_into().unwrap()).to_string()))
+    });
+}
+
+fn addr_v2(d: &mut Dissector) {
+    d.timestamp_u32("last_seen");
+    d.compact_size("services");
+    let network_id = d.u8("network_id");
+
+    let addr_len = d.compact_size("addr_len");
+    if let Some(len) = addr_len {
+        if len > MAX_ADDRV2_ADDR_LEN as u64 {
+            d.fields.last_mut().unwrap().error = Some(format!(
+                "length exceeds the limit of {}",
+                MAX_ADDRV2_ADDR_LEN
+            ));
+            return d.abandon();
+        }
+
+        d.field("addr", len as usize, |raw| {
+            let network_id = network_id.ok_or("unknown network")?;
+            NetAddr::from_bytes(network_id, raw.to_vec()).map(|addr| ((), addr.to_string()))
+        });
+        d.field("port", 2, |raw| {
+            Ok(((), u16::from_be_bytes(raw.try_into().unwrap()).to_string()))
+        });
+    }
+}
+
+fn block_header(d: &mut Dissector) {
+    d.u32("version");
+    d.hash("prev_block");
+    d.hash("merkle_root");
+    d.hash("light_client_root");
+    d.timestamp_u32("timestamp");
+    d.field("bits", 4, |raw| {
+        Ok((
+            (),
+            format!("{:#010x}", u32::from_le_bytes(raw.try_into().unwrap())),
+        ))
+    });
+    d.bytes("nonce", 32);
+    d.var_bytes("solution");
+}
+
+fn inv_hash(d: &mut Dissector) {
+    let kind = d.field("kind", 4, |raw| {
+        ObjectKind::decode(&mut &raw[..])
+            .map(|kind| (kind, format!("{:?}", kind)))
+            .map_err(|err| err.to_string())
+    });
+    d.hash("hash");
+    if kind == Some(ObjectKind::WTx) {
+        d.hash("auth_digest");
+    }
+}
+
+fn describe_timestamp(timestamp: i64) -> String {
+    match OffsetDateTime::from_unix_timestamp(timestamp) {
+        Ok(date) => format!("{} ({})", timestamp, date),
+        Err(_) => format!("{} (not a valid date)", timestamp),
+    }
+}
+
+/// Reads fields from the bytes, recording them as it goes.
+struct Dissector<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    fields: Vec<Field>,
+}
+
+impl<'a> Dissector<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+            fields: Vec::new(),
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn into_root(self, name: &str) -> Field {
+        Field {
+            name: name.to_string(),
+            offset: 0,
+            raw: self.bytes.to_vec(),
+            value: String::new(),
+            error: None,
+            children: self.fields,
+        }
+    }
+
+    /// Records a field of `len` bytes, decoded by `decode` into a value and its description. A
+    /// truncated field takes up the remaining bytes.
+    fn field<T>(
+        &mut self,
+        name: impl Into<String>,
+        len: usize,
+        decode: impl FnOnce(&[u8]) -> Result<(T, String), String>,
+    ) -> Option<T> {
+        let start = self.pos;
+        let (raw, result) = if len > self.remaining() {
+            let err = format!(
+                "truncated, needed {} bytes but {} remain",
+                len,
+                self.remaining()
+            );
+            (&self.bytes[start..], Err(err))
+        } else {
+            let raw = &self.bytes[start..][..len];
+            (raw, decode(raw))
+        };
+        self.pos += raw.len();
+
+        let (decoded, value, error) = match result {
+            Ok((decoded, value)) => (Some(decoded), value, None),
+            Err(err) => (None, String::new(), Some(err)),
+        };
+        self.fields.push(Field {
+            name: name.into(),
+            offset: start,
+            raw: raw.to_vec(),
+            value,
+            error,
+            children: Vec::new(),
+        });
+
+        decoded
+    }
+
+    /// Records the fields read by `f` as the children of a single field.
+    fn group<T>(&mut self, name: impl Into<String>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let start = self.pos;
+        let parent_fields = std::mem::take(&mut self.fields);
+        let result = f(self);
+        let children = std::mem::replace(&mut self.fields, parent_fields);
+
+        self.fields.push(Field {
+            name: name.into(),
+            offset: start,
+            raw: self.bytes[start..self.pos].to_vec(),
+            value: String::new(),
+            error: None,
+            children,
+        });
+
+        result
+    }
+
+    /// Records the remaining bytes as a single field.
+    fn rest(&mut self, name: &str, error: Option<String>) {
+        let len = self.remaining();
+        self.field(name, len, |raw| Ok(((), hex::encode(raw))));
+        self.fields.last_mut().unwrap().error = error;
+    }
+
+    fn u8(&mut self, name: &str) -> Option<u8> {
+        self.field(name, 1, |raw| Ok((raw[0], raw[0].to_string())))
+    }
+
+    fn u32(&mut self, name: &str) -> Option<u32> {
+        self.field(name, 4, |raw| {
+            let value = u32::from_le_bytes(raw.try_into().unwrap());
+            Ok((value, value.to_string()))
+        })
+    }
+
+    fn u64(&mut self, name: &str) -> Option<u64> {
+        self.field(name, 8, |raw| {
+            let value = u64::from_le_bytes(raw.try_into().unwrap());
+            Ok((value, value.to_string()))
+        })
+    }
+
+    fn services(&mut self, name: &str) {
+        self.field(name, 8, |raw| {
+            let services = Services::from_bits_retain(u64::from_le_bytes(raw.try_into().unwrap()));
+            Ok(((), services.to_string()))
+        });
+    }
+
+    fn timestamp_u32(&mut self, name: &str) {
+        self.field(name, 4, |raw| {
+            let timestamp = u32::from_le_bytes(raw.try_into().unwrap());
+            Ok(((), describe_timestamp(timestamp.into())))
+        });
+    }
+
+    fn hash(&mut self, name: &str) {
+        self.field(name, 32, |raw| {
+            let mut hash = raw.to_vec();
+            hash.reverse();
+            Ok(((), hex::encode(hash)))
+        });
+    }
+
+    fn bytes(&mut self, name: &str, len: usize) {
+        self.field(name, len, |raw| Ok(((), hex::encode(raw))));
+    }
+
+    /// Reads a compact size, whose length depends on its first byte.
+    fn compact_size(&mut self, name: &str) -> Option<u64> {
+        let len = match self.bytes.get(self.pos) {
+            Some(0xfd) => 3,
+            Some(0xfe) => 5,
+            Some(0xff) => 9,
+            _ => 1,
+        };
+
+        let value = self.field(name, len, |raw| {
+            let mut value_bytes = [0u8; 8];
+            value_bytes[..len - 1].copy_from_slice(&raw[1..]);
+            let value = match len {
+                1 => raw[0].into(),
+                _ => u64::from_le_bytes(value_bytes),
+            };
+
+            if value > MAX_MESSAGE_LEN as u64 {
+                return Err(format!("{} exceeds the maximum message length", value));
+            }
+
+            Ok((value, value.to_string()))
+        })?;
+
+        // The value is still usable if it isn't minimally encoded, so it's only marked.
+        let minimal_len = match value {
+            0..=0xfc => 1,
+            0xfd..=0xffff => 3,
+            0x1_0000..=0xffff_ffff => 5,
+            _ => 9,
+        };
+        if len != minimal_len {
+            self.fields.last_mut().unwrap().error = Some("not minimally encoded".to_string());
+        }
+
+        Some(value)
+    }
+
+    /// Reads a compact size `count` followed by as many elements read by `element`.
+    fn list(&mut self, name: &str, mut element: impl FnMut(&mut Self)) {
+        self.group(name, |d| {
+            let count = match d.compact_size("count") {
+                Some(count) => count,
+                None => return d.abandon(),
+            };
+
+            for i in 0..count {
+                // A corrupt element may have taken up the remaining bytes already.
+                if d.remaining() == 0 {
+                    if d.errors() == 0 {
+                        d.fields[0].error = Some(format!(
+                            "count is {}, but the bytes ran out after {} element(s)",
+                            count, i
+                        ));
+                    }
+                    break;
+                }
+
+                d.group(format!("[{}]", i), &mut element);
+            }
+        });
+    }
+
+    fn var_bytes(&mut self, name: &str) {
+        self.group(name, |d| match d.compact_size("length") {
+            Some(len) => d.bytes("bytes", len as usize),
+            None => d.abandon(),
+        });
+    }
+
+    fn var_str(&mut self, name: &str) {
+        self.group(name, |d| match d.compact_size("length") {
+            Some(len) => {
+                d.field("string", len as usize, |raw| {
+                    String::from_utf8(raw.to_vec())
+                        .map(|string| ((), format!("{:?}", string)))
+                        .map_err(|_| format!("invalid UTF-8: {:?}", String::from_utf8_lossy(raw)))
+                });
+            }
+            None => d.abandon(),
+        });
+    }
+
+    /// Decodes a transaction with [`Tx`]'s codec, which can't recover from errors.
+    fn tx(&mut self, name: &str) {
+        let start = self.pos;
+        let mut tx_bytes = &self.bytes[start..];
+        match Tx::decode_detailed(&mut tx_bytes) {
+            Ok(tx) => {
+                let len = self.remaining() - tx_bytes.len();
+                self.field(name, len, |_| {
+                    Ok((
+                        (),
+                        format!("v{} txid {}", tx.version(), describe_hash(tx.txid())),
+                    ))
+                });
+            }
+            Err(err) => {
+                let error = match err.offset() {
+                    Some(offset) => format!(
+                        "{}: {} at offset {}",
+                        err.path(),
+                        err.kind(),
+                        start + offset
+                    ),
+                    None => err.to_string(),
+                };
+                self.rest(name, Some(error));
+            }
+        }
+    }
+
+    /// Returns the number of invalid fields read so far, at this level of the tree.
+    fn errors(&self) -> usize {
+        self.fields.iter().map(|field| field.errors().len()).sum()
+    }
+
+    /// Gives up on the current collection, keeping the remaining bytes as a marked field.
+    fn abandon(&mut self) {
+        if self.remaining() > 0 {
+            self.rest(
+                "undecoded",
+                Some("position unknown after a corrupt field".to_string()),
+            );
+        }
+    }
+}
+
+fn describe_hash(hash: Hash) -> String {
+    let mut bytes = Vec::new();
+    hash.encode(&mut bytes).unwrap();
+    bytes.reverse();
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::{
+        protocol::{
+            message::Message,
+            payload::{block::Block, Reject, VarStr, VersionBuilder},
+        },
+        tools::fuzzing::*,
+        vectors::*,
+    };
+
+    fn encoded(message: &Message) -> Vec<u8> {
+        let mut bytes = bytes::BytesMut::new();
+        message.encode(Network::Testnet, &mut bytes).unwrap();
+        bytes.to_vec()
+    }
+
+    fn version() -> Message {
+        let addr = "127.0.0.1:8233".parse().unwrap();
+        Message::Version(
+            VersionBuilder::new(addr, addr)
+                .user_agent("/test:1.0/")
+                .build(),
+        )
+    }
+
+    /// Checks every field's children cover its bytes exactly, in order.
+    fn assert_contiguous(field: &Field) {
+        if field.children.is_empty() {
+            return;
+        }
+
+        let mut offset = field.offset;
+        for child in &field.children {
+            assert_eq!(child.offset, offset, "{}", field);
+            offset += child.raw.len();
+            assert_contiguous(child);
+        }
+        assert_eq!(offset, field.offset + field.raw.len(), "{}", field);
+    }
+
+    #[test]
+    fn valid_version() {
+        let field = dissect(Network::Testnet, &encoded(&version()));
+
+        assert!(field.errors().is_empty(), "{}", field);
+        assert_contiguous(&field);
+        assert_eq!(field.get("header.command").unwrap().value, "version");
+        assert_eq!(
+            field.get("payload.user_agent.string").unwrap().value,
+            "\"/test:1.0/\""
+        );
+        assert_eq!(field.get("payload.relay").unwrap().value, "false");
+    }
+
+    #[test]
+    fn corrupt_checksum_is_marked() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let bytes = encode_message_with_corrupt_checksum(&mut rng, Network::Testnet, &version());
+        let field = dissect(Network::Testnet, &bytes);
+
+        let errors = field.errors();
+        assert_eq!(errors.len(), 1, "{}", field);
+        assert_eq!(errors[0].name, "checksum");
+        // The payload is dissected regardless.
+        assert!(field.get("payload.start_height").is_some());
+    }
+
+    #[test]
+    fn decoding_continues_after_a_corrupt_field() {
+        let reject = Reject {
+            message: VarStr(String::from("tx")),
+            ccode: CCode::Invalid,
+            reason: VarStr(String::from("bad-txns")),
+            data: vec![0xaa; 32],
+        };
+        let mut bytes = encoded(&Message::Reject(reject));
+        // Replace the ccode with an unknown one and fix up the checksum.
+        bytes[HEADER_LEN + 3] = 0xff;
+        let checksum = checksum(&bytes[HEADER_LEN..]);
+        bytes[20..24].copy_from_slice(&checksum.to_le_bytes());
+
+        let field = dissect(Network::Testnet, &bytes);
+        let errors = field.errors();
+        assert_eq!(errors.len(), 1, "{}", field);
+        assert_eq!(errors[0].name, "ccode");
+        assert_eq!(
+            field.get("payload.reason.string").unwrap().value,
+            "\"bad-txns\""
+        );
+        assert_eq!(field.get("payload.data").unwrap().raw, vec![0xaa; 32]);
+    }
+
+    #[test]
+    fn corrupt_length_ends_the_collection() {
+        let mut bytes = encoded(&version());
+        // The user agent's length, after 80 bytes of fixed size fields.
+        bytes[HEADER_LEN + 80] = 0xfc;
+
+        let field = dissect(Network::Testnet, &bytes);
+        let user_agent = field.get("payload.user_agent").unwrap();
+        assert!(user_agent.get("string").unwrap().error.is_some());
+        // The string took up the remaining bytes, so what follows is missing.
+        let start_height = field.get("payload.start_height").unwrap();
+        assert!(start_height.raw.is_empty() && start_height.error.is_some());
+        assert_contiguous(&field);
+    }
+
+    #[test]
+    fn block_transactions() {
+        let block = Block::decode(&mut &BLOCK_TESTNET_1_599_200_BYTES[..]).unwrap();
+        let txids: Vec<_> = block
+            .txs
+            .iter()
+            .map(|tx| describe_hash(tx.txid()))
+            .collect();
+        let field = dissect(Network::Testnet, &encoded(&Message::Block(Box::new(block))));
+
+        assert!(field.errors().is_empty(), "{}", field);
+        assert_contiguous(&field);
+        let txs = field.get("payload.txs").unwrap();
+        for (i, txid) in txids.iter().enumerate() {
+            let tx = txs.get(&format!("[{}].tx", i)).unwrap();
+            assert!(tx.value.ends_with(txid.as_str()));
+        }
+    }
+
+    #[test]
+    fn corrupted_messages_are_dissected() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let messages = default_fuzz_messages();
+
+        for bytes in encode_slightly_corrupted_messages(&mut rng, Network::Testnet, 100, &messages)
+        {
+            let field = dissect(Network::Testnet, &bytes);
+            assert_eq!(field.raw, bytes);
+            assert_contiguous(&field);
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Equihash proof of work verification, see the [Zcash protocol
+//! spec](https://zips.z.cash/protocol/protocol.pdf#equihash).
+
+use std::{fmt, io};
+
+use blake2b_simd::{Params as Blake2bParams, State};
+
+use crate::protocol::network::Network;
+
+/// Equihash parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    /// The hash output length in bits.
+    pub n: u32,
+    /// The number of collision rounds, a solution consists of `2^k` indices.
+    pub k: u32,
+}
+
+impl Params {
+    /// The parameters used on mainnet and testnet.
+    pub const MAINNET: Self = Self { n: 200, k: 9 };
+
+    /// The parameters used on regtest.
+    pub const REGTEST: Self = Self { n: 48, k: 5 };
+
+    /// Returns the parameters used on the network.
+    pub fn for_network(network: Network) -> Self {
+        match network {
+            Network::Regtest => Self::REGTEST,
+            _ => Self::MAINNET,
+        }
+    }
+
+    /// Returns the length of a minimally encoded solution in bytes.
+    pub fn solution_size(&self) -> usize {
+        (1 << self.k) * (self.collision_bit_length() + 1) / 8
+    }
+
+    fn is_valid(&self) -> bool {
+        self.k >= 3
+            && self.k < self.n
+            && self.n.is_multiple_of(8)
+            && self.n.is_multiple_of(self.k + 1)
+            && self.collision_bit_length() < 25
+    }
+
+    fn collision_bit_length(&self) -> usize {
+        (self.n / (self.k + 1)) as usize
+    }
+
+    fn collision_byte_length(&self) -> usize {
+        self.collision_bit_length().div_ceil(8)
+    }
+
+    fn indices_per_hash_output(&self) -> u32 {
+        512 / self.n
+    }
+
+    fn hash_output(&self) -> usize {
+        (self.indices_per_hash_output() * self.n / 8) as usize
+    }
+}
+
+/// The reason an Equihash solution is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The parameters aren't supported.
+    InvalidParams(Params),
+    /// The solution's length doesn't match the parameters.
+    SolutionSize { expected: usize, actual: usize },
+    /// The hashes of two sibling subtrees don't collide.
+    Collision,
+    /// The indices of two sibling subtrees aren't ordered.
+    OutOfOrder,
+    /// Two sibling subtrees share an index.
+    DuplicateIndices,
+    /// The hashes of the solution's indices don't XOR to zero.
+    NonZeroRootHash,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidParams(params) => {
+                write!(
+                    f,
+                    "invalid Equihash parameters ({}, {})",
+                    params.n, params.k
+                )
+            }
+            Self::SolutionSize { expected, actual } => write!(
+                f,
+                "Equihash solution is {} bytes, expected {}",
+                actual, expected
+            ),
+            Self::Collision => f.write_str("Equihash subtree hashes don't collide"),
+            Self::OutOfOrder => f.write_str("Equihash subtree indices are out of order"),
+            Self::DuplicateIndices => f.write_str("Equihash subtrees share an index"),
+            Self::NonZeroRootHash => f.write_str("Equihash root hash isn't zero"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+/// Verifies the minimally encoded Equihash `solution` for the `input` and `nonce`.
+///
+/// For block headers, the input is the header up to and excluding the nonce.
+pub fn verify(params: Params, input: &[u8], nonce: &[u8], solution: &[u8]) -> Result<(), Error> {
+    if !params.is_valid() {
+        return Err(Error::InvalidParams(params));
+    }
+
+    if solution.len() != params.solution_size() {
+        return Err(Error::SolutionSize {
+            expected: params.solution_size(),
+            actual: solution.len(),
+        });
+    }
+
+    let mut state = hasher(params);
+    state.update(input);
+    state.update(nonce);
+
+    let indices = indices_from_minimal(params, solution);
+    let root = validate_tree(params, &state, &indices)?;
+
+    // The hashes were trimmed by each level, only the last collision's bytes are left.
+    if root.hash.iter().any(|byte| *byte != 0) {
+        return Err(Error::NonZeroRootHash);
+    }
+
+    Ok(())
+}
+
+/// A node of the solution tree.
+struct Node {
+    hash: Vec<u8>,
+    indices: Vec<u32>,
+}
+
+impl Node {
+    fn leaf(params: Params, state: &State, index: u32) -> Self {
+        Self {
+            hash: leaf_hash(params, state, index),
+            indices: vec![index],
+        }
+    }
+
+    fn from_children(a: Node, b: Node, trim: usize) -> Self {
+        let hash = a
+            .hash
+            .iter()
+            .zip(&b.hash)
+            .skip(trim)
+            .map(|(a, b)| a ^ b)
+            .collect();
+
+        let indices = if a.indices[0] < b.indices[0] {
+            [a.indices, b.indices].concat()
+        } else {
+            [b.indices, a.indices].concat()
+        };
+
+        Self { hash, indices }
+    }
+}
+
+fn validate_tree(params: Params, state: &State, indices: &[u32]) -> Result<Node, Error> {
+    if indices.len() == 1 {
+        return Ok(Node::leaf(params, state, indices[0]));
+    }
+
+    let (left, right) = indices.split_at(indices.len() / 2);
+    let a = validate_tree(params, state, left)?;
+    let b = validate_tree(params, state, right)?;
+
+    let trim = params.collision_byte_length();
+    if a.hash[..trim] != b.hash[..trim] {
+        return Err(Error::Collision);
+    }
+
+    if b.indices[0] < a.indices[0] {
+        return Err(Error::OutOfOrder);
+    }
+
+    if a.indices.iter().any(|index| b.indices.contains(index)) {
+        return Err(Error::DuplicateIndices);
+    }
+
+    Ok(Node::from_children(a, b, trim))
+}
+
+/// Returns a BLAKE2b state personalized for the parameters.
+fn hasher(params: Params) -> State {
+    let mut personalization = [0u8; 16];
+    personalization[..8].copy_from_slice(b"ZcashPoW");
+    personalization[8..12].copy_from_slice(&params.n.to_le_bytes());
+    personalization[12..].copy_from_slice(&params.k.to_le_bytes());
+
+    Blake2bParams::new()
+        .hash_length(params.hash_output())
+        .personal(&personalization)
+        .to_state()
+}
+
+/// Computes the expanded hash of the index, a hash output contains several indices' hashes.
+fn leaf_hash(params: Params, state: &State, index: u32) -> Vec<u8> {
+    let per_output = params.indices_per_hash_output();
+    let hash = state
+        .clone()
+        .update(&(index / per_output).to_le_bytes())
+        .finalize();
+
+    let len = (params.n / 8) as usize;
+    let start = (index % per_output) as usize * len;
+
+    expand_array(
+        &hash.as_bytes()[start..start + len],
+        params.collision_bit_length(),
+        0,
+    )
+}
+
+/// Decodes the big-endian `collision_bit_length + 1` bit indices packed in the solution.
+fn indices_from_minimal(params: Params, solution: &[u8]) -> Vec<u32> {
+    let bit_len = params.collision_bit_length() + 1;
+    let byte_pad = 4 - bit_len.div_ceil(8);
+
+    expand_array(solution, bit_len, byte_pad)
+        .chunks_exact(4)
+        .map(|chunk| u32::from_be_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+/// Splits the input into `bit_len` bit big-endian values, each written to `(bit_len + 7) / 8`
+/// bytes preceded by `byte_pad` zero bytes.
+fn expand_array(input: &[u8], bit_len: usize, byte_pad: usize) -> Vec<u8> {
+    let out_width = bit_len.div_ceil(8) + byte_pad;
+    let out_len = 8 * out_width * input.len() / bit_len;
+
+    // Expansion is a no-op for byte aligned values.
+    if out_len == input.len() {
+        return input.to_vec();
+    }
+
+    let mut output = vec![0u8; out_len];
+    let bit_len_mask: u32 = (1 << bit_len) - 1;
+
+    // The `acc_bits` least significant bits of `acc_value` hold the unread input bits.
+    let mut acc_bits = 0;
+    let mut acc_value: u32 = 0;
+    let mut j = 0;
+    for byte in input {
+        acc_value = (acc_value << 8) | u32::from(*byte);
+        acc_bits += 8;
+
+        if acc_bits >= bit_len {
+            acc_bits -= bit_len;
+            for x in byte_pad..out_width {
+                let shift = 8 * (out_width - x - 1);
+                output[j + x] =
+                    ((acc_value >> (acc_bits + shift)) & ((bit_len_mask >> shift) & 0xff)) as u8;
+            }
+            j += out_width;
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Packs the indices into `bit_len` bit big-endian values, the inverse of
+    /// [`indices_from_minimal`].
+    fn minimal_from_indices(params: Params, indices: &[u32]) -> Vec<u8> {
+        let bit_len = params.collision_bit_length() + 1;
+        let mut minimal = vec![0u8; indices.len() * bit_len / 8];
+
+        for (i, index) in indices.iter().enumerate() {
+            for bit in 0..bit_len {
+                if index >> (bit_len - bit - 1) & 1 == 1 {
+                    let pos = i * bit_len + bit;
+                    minimal[pos / 8] |= 0x80 >> (pos % 8);
+                }
+            }
+        }
+
+        minimal
+    }
+
+    /// A naive implementation of Wagner's algorithm, fast enough for small parameters.
+    fn solve(params: Params, input: &[u8], nonce: &[u8]) -> Vec<Vec<u32>> {
+        let mut state = hasher(params);
+        state.update(input);
+        state.update(nonce);
+
+        let trim = params.collision_byte_length();
+        let mut rows: Vec<Node> = (0..1 << (params.collision_bit_length() + 1))
+            .map(|index| Node::leaf(params, &state, index))
+            .collect();
+
+        for _ in 0..params.k {
+            rows.sort_by(|a, b| a.hash[..trim].cmp(&b.hash[..trim]));
+
+            let mut next = Vec::new();
+            for (i, a) in rows.iter().enumerate() {
+                for b in rows[i + 1..]
+                    .iter()
+                    .take_while(|b| a.hash[..trim] == b.hash[..trim])
+                {
+                    if a.indices.iter().all(|index| !b.indices.contains(index)) {
+                        let a = Node {
+                            hash: a.hash.clone(),
+                            indices: a.indices.clone(),
+                        };
+                        let b = Node {
+                            hash: b.hash.clone(),
+                            indices: b.indices.clone(),
+                        };
+                        next.push(Node::from_children(a, b, trim));
+                    }
+                }
+            }
+            rows = next;
+        }
+
+        rows.into_iter()
+            .filter(|row| row.hash.iter().all(|byte| *byte == 0))
+            .map(|row| row.indices)
+            .collect()
+    }
+
+    #[test]
+    fn regtest_solution() {
+        let params = Params::REGTEST;
+        let input = [7u8; 108];
+
+        let (nonce, indices) = (0u8..)
+            .find_map(|i| {
+                let nonce = [i; 32];
+                solve(params, &input, &nonce)
+                    .into_iter()
+                    .next()
+                    .map(|indices| (nonce, indices))
+            })
+            .unwrap();
+
+        let mut solution = minimal_from_indices(params, &indices);
+        assert_eq!(solution.len(), params.solution_size());
+        assert_eq!(indices_from_minimal(params, &solution), indices);
+        assert_eq!(verify(params, &input, &nonce, &solution), Ok(()));
+
+        // Swapping the two halves breaks the ordering.
+        let half = indices.len() / 2;
+        let swapped = [&indices[half..], &indices[..half]].concat();
+        let swapped = minimal_from_indices(params, &swapped);
+        assert_eq!(
+            verify(params, &input, &nonce, &swapped),
+            Err(Error::OutOfOrder)
+        );
+
+        solution[0] ^= 0x80;
+        assert!(verify(params, &input, &nonce, &solution).is_err());
+        assert!(verify(params, &input, &[0xff; 32], &solution).is_err());
+    }
+
+    #[test]
+    fn solution_size() {
+        assert_eq!(Params::MAINNET.solution_size(), 1344);
+        assert_eq!(Params::REGTEST.solution_size(), 36);
+
+        assert_eq!(
+            verify(Params::MAINNET, &[0; 108], &[0; 32], &[0; 36]),
+            Err(Error::SolutionSize {
+                expected: 1344,
+                actual: 36
+            })
+        );
+    }
+}
@@ -10,16 +10,10 @@ pub const MAX_MESSAGE_LEN: usize = 2 * 1024 * 1024;
 
 /// The current network protocol version number.
 pub const PROTOCOL_VERSION: u32 = 170_100;
-/// The current network version identifier.
+/// The network identifiers, see [`Network`](crate::protocol::network::Network).
 pub const MAGIC_TESTNET: [u8; 4] = [0xfa, 0x1a, 0xf9, 0xbf];
 pub const MAGIC_MAINNET: [u8; 4] = [0x24, 0xe9, 0x27, 0x64];
-
-#[cfg(test)]
-pub const MAGIC: [u8; 4] = MAGIC_TESTNET;
-#[cfg(all(not(test), not(feature = "crawler")))]
-pub const MAGIC: [u8; 4] = MAGIC_MAINNET;
-#[cfg(feature = "crawler")]
-pub const MAGIC: [u8; 4] = MAGIC_MAINNET;
+pub const MAGIC_REGTEST: [u8; 4] = [0xaa, 0xe8, 0x3f, 0x5f];
 
 // Message command bytes.
 pub const VERSION_COMMAND: [u8; 12] = *b"version\0\0\0\0\0";
@@ -28,6 +22,8 @@ pub const PING_COMMAND: [u8; 12] = *b"ping\0\0\0\0\0\0\0\0";
 pub const PONG_COMMAND: [u8; 12] = *b"pong\0\0\0\0\0\0\0\0";
 pub const GETADDR_COMMAND: [u8; 12] = *b"getaddr\0\0\0\0\0";
 pub const ADDR_COMMAND: [u8; 12] = *b"addr\0\0\0\0\0\0\0\0";
+pub const SENDADDRV2_COMMAND: [u8; 12] = *b"sendaddrv2\0\0";
+pub const ADDRV2_COMMAND: [u8; 12] = *b"addrv2\0\0\0\0\0\0";
 pub const GETHEADERS_COMMAND: [u8; 12] = *b"getheaders\0\0";
 pub const HEADERS_COMMAND: [u8; 12] = *b"headers\0\0\0\0\0";
 pub const GETBLOCKS_COMMAND: [u8; 12] = *b"getblocks\0\0\0";
@@ -41,3 +37,4 @@ pub const REJECT_COMMAND: [u8; 12] = *b"reject\0\0\0\0\0\0";
 pub const FILTERLOAD_COMMAND: [u8; 12] = *b"filterload\0\0";
 pub const FILTERADD_COMMAND: [u8; 12] = *b"filteradd\0\0\0";
 pub const FILTERCLEAR_COMMAND: [u8; 12] = *b"filterclear\0";
+pub const MERKLEBLOCK_COMMAND: [u8; 12] = *b"merkleblock\0";
@@ -4,24 +4,32 @@ pub mod constants;
 
 use std::io;
 
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use sha2::{Digest, Sha256};
 
 use crate::protocol::{
     message::constants::*,
+    network::Network,
     payload::{
         block::{Block, Headers, LocatorHashes},
-        codec::Codec,
-        Addr, FilterAdd, FilterLoad, Inv, Nonce, Reject, Tx, Version,
+        codec::{Codec, DecodeError},
+        Addr, AddrV2, FilterAdd, FilterLoad, Inv, LazyBlock, MerkleBlock, Nonce, Reject, Tx,
+        Version,
     },
 };
 
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::hex_bytes;
+
 /// The header of a network message.
 #[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MessageHeader {
     /// The network protocol version.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
     pub magic: [u8; 4],
     /// The message command, identifies the type of message being sent.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
     pub command: [u8; 12],
     /// The length of the message's body.
     pub body_length: u32,
@@ -39,9 +47,13 @@ impl Codec for MessageHeader {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        HEADER_LEN
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         if bytes.remaining() < HEADER_LEN {
-            return Err(io::ErrorKind::InvalidData.into());
+            return Err(DecodeError::truncated(HEADER_LEN, bytes).into());
         }
 
         let mut magic = [0u8; 4];
@@ -60,10 +72,10 @@ impl Codec for MessageHeader {
 }
 
 impl MessageHeader {
-    /// Returns a `MessageHeader` constructed from the message body.
-    pub fn new(command: [u8; 12], body: &[u8]) -> Self {
+    /// Returns a `MessageHeader` for the network, constructed from the message body.
+    pub fn new(network: Network, command: [u8; 12], body: &[u8]) -> Self {
         MessageHeader {
-            magic: MAGIC,
+            magic: network.magic(),
             command,
             body_length: body.len() as u32,
             checksum: checksum(body),
@@ -76,6 +88,7 @@ impl MessageHeader {
 /// All the message types and their payloads are documented by the [Bitcoin protocol
 /// documentation](https://en.bitcoin.it/wiki/Protocol_documentation#Message_types).
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Message {
     Version(Version),
     Verack,
@@ -83,10 +96,17 @@ pub enum Message {
     Pong(Nonce),
     GetAddr,
     Addr(Addr),
+    /// Signals support for [`Message::AddrV2`], sent before [`Message::Verack`].
+    SendAddrV2,
+    /// Addresses of any network, see [ZIP-155](https://zips.z.cash/zip-0155).
+    AddrV2(AddrV2),
     GetHeaders(LocatorHashes),
     Headers(Headers),
     GetBlocks(LocatorHashes),
     Block(Box<Block>),
+    /// A [`Message::Block`] which is only decoded on demand, see [`LazyBlock`]. Encoded with the
+    /// same command, it's only ever decoded by codecs which opt into it.
+    LazyBlock(LazyBlock),
     GetData(Inv),
     Inv(Inv),
     NotFound(Inv),
@@ -96,114 +116,227 @@ pub enum Message {
     FilterLoad(FilterLoad),
     FilterAdd(FilterAdd),
     FilterClear,
+    MerkleBlock(Box<MerkleBlock>),
+    /// A message with a command this crate doesn't model, its payload is kept as is.
+    Unknown {
+        #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+        command: [u8; 12],
+        #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+        payload: Bytes,
+    },
 }
 
 macro_rules! encode_with_header_prefix {
-    ($command:expr, $buffer:expr) => {{
-        let header = MessageHeader::new($command, &[]);
+    ($network:expr, $command:expr, $buffer:expr) => {{
+        $buffer.reserve(HEADER_LEN);
+        let header = MessageHeader::new($network, $command, &[]);
         header.encode($buffer)?;
     }};
 
-    ($command:expr, $buffer:expr, $payload:expr) => {{
-        $payload.encode($buffer)?;
-        let serialized_payload = $buffer.split_to($buffer.len()).freeze();
-        let header = MessageHeader::new($command, &serialized_payload);
+    ($network:expr, $command:expr, $buffer:expr, $payload:expr) => {{
+        let body_length = $payload.encoded_len();
+        $buffer.reserve(HEADER_LEN + body_length);
+
+        // The payload is encoded in place after the header, whose checksum is patched in once the
+        // payload is there to be hashed.
+        let body_start = $buffer.len() + HEADER_LEN;
+        let header = MessageHeader {
+            magic: $network.magic(),
+            command: $command,
+            body_length: body_length as u32,
+            checksum: 0,
+        };
         header.encode($buffer)?;
-        $buffer.put_slice(&serialized_payload);
+        $payload.encode($buffer)?;
+        debug_assert_eq!($buffer.len() - body_start, body_length);
+
+        let checksum = checksum(&$buffer[body_start..]);
+        $buffer[body_start - 4..body_start].copy_from_slice(&checksum.to_le_bytes());
     }};
 }
 
 impl Message {
-    /// Encodes a message into the supplied buffer and returns its header.
-    pub fn encode(&self, buffer: &mut BytesMut) -> io::Result<()> {
+    /// Encodes a message for the network into the supplied buffer, appending it to any bytes
+    /// already there.
+    ///
+    /// Reserves the exact [`Message::encoded_len`] up front and encodes the payload in a single
+    /// pass, straight after its header.
+    pub fn encode(&self, network: Network, buffer: &mut BytesMut) -> io::Result<()> {
         match self {
             Self::Version(version) => {
-                encode_with_header_prefix!(VERSION_COMMAND, buffer, version);
+                encode_with_header_prefix!(network, VERSION_COMMAND, buffer, version);
             }
             Self::Verack => {
-                encode_with_header_prefix!(VERACK_COMMAND, buffer);
+                encode_with_header_prefix!(network, VERACK_COMMAND, buffer);
             }
             Self::Ping(nonce) => {
-                encode_with_header_prefix!(PING_COMMAND, buffer, nonce);
+                encode_with_header_prefix!(network, PING_COMMAND, buffer, nonce);
             }
             Self::Pong(nonce) => {
-                encode_with_header_prefix!(PONG_COMMAND, buffer, nonce);
+                encode_with_header_prefix!(network, PONG_COMMAND, buffer, nonce);
             }
             Self::GetAddr => {
-                encode_with_header_prefix!(GETADDR_COMMAND, buffer);
+                encode_with_header_prefix!(network, GETADDR_COMMAND, buffer);
             }
             Self::Addr(addr) => {
-                encode_with_header_prefix!(ADDR_COMMAND, buffer, addr);
+                encode_with_header_prefix!(network, ADDR_COMMAND, buffer, addr);
+            }
+            Self::SendAddrV2 => {
+                encode_with_header_prefix!(network, SENDADDRV2_COMMAND, buffer);
+            }
+            Self::AddrV2(addr) => {
+                encode_with_header_prefix!(network, ADDRV2_COMMAND, buffer, addr);
             }
             Self::GetHeaders(locator_hashes) => {
-                encode_with_header_prefix!(GETHEADERS_COMMAND, buffer, locator_hashes);
+                encode_with_header_prefix!(network, GETHEADERS_COMMAND, buffer, locator_hashes);
             }
             Self::Headers(headers) => {
-                encode_with_header_prefix!(HEADERS_COMMAND, buffer, headers);
+                encode_with_header_prefix!(network, HEADERS_COMMAND, buffer, headers);
             }
             Self::GetBlocks(locator_hashes) => {
-                encode_with_header_prefix!(GETBLOCKS_COMMAND, buffer, locator_hashes);
+                encode_with_header_prefix!(network, GETBLOCKS_COMMAND, buffer, locator_hashes);
             }
             Self::Block(block) => {
-                encode_with_header_prefix!(BLOCK_COMMAND, buffer, block);
+                encode_with_header_prefix!(network, BLOCK_COMMAND, buffer, block);
+            }
+            Self::LazyBlock(block) => {
+                encode_with_header_prefix!(network, BLOCK_COMMAND, buffer, block);
             }
             Self::GetData(inv) => {
-                encode_with_header_prefix!(GETDATA_COMMAND, buffer, inv);
+                encode_with_header_prefix!(network, GETDATA_COMMAND, buffer, inv);
             }
             Self::Inv(inv) => {
-                encode_with_header_prefix!(INV_COMMAND, buffer, inv);
+                encode_with_header_prefix!(network, INV_COMMAND, buffer, inv);
             }
             Self::NotFound(inv) => {
-                encode_with_header_prefix!(NOTFOUND_COMMAND, buffer, inv);
+                encode_with_header_prefix!(network, NOTFOUND_COMMAND, buffer, inv);
             }
             Self::MemPool => {
-                encode_with_header_prefix!(MEMPOOL_COMMAND, buffer);
+                encode_with_header_prefix!(network, MEMPOOL_COMMAND, buffer);
             }
             Self::Tx(tx) => {
-                encode_with_header_prefix!(TX_COMMAND, buffer, tx);
+                encode_with_header_prefix!(network, TX_COMMAND, buffer, tx);
             }
             Self::Reject(reject) => {
-                encode_with_header_prefix!(REJECT_COMMAND, buffer, reject);
+                encode_with_header_prefix!(network, REJECT_COMMAND, buffer, reject);
             }
             Self::FilterLoad(filter_load) => {
-                encode_with_header_prefix!(FILTERLOAD_COMMAND, buffer, filter_load);
+                encode_with_header_prefix!(network, FILTERLOAD_COMMAND, buffer, filter_load);
             }
             Self::FilterAdd(filter) => {
-                encode_with_header_prefix!(FILTERADD_COMMAND, buffer, filter);
+                encode_with_header_prefix!(network, FILTERADD_COMMAND, buffer, filter);
             }
             Self::FilterClear => {
-                encode_with_header_prefix!(FILTERCLEAR_COMMAND, buffer);
+                encode_with_header_prefix!(network, FILTERCLEAR_COMMAND, buffer);
+            }
+            Self::MerkleBlock(merkle_block) => {
+                encode_with_header_prefix!(network, MERKLEBLOCK_COMMAND, buffer, merkle_block);
+            }
+            Self::Unknown { command, payload } => {
+                buffer.reserve(HEADER_LEN + payload.len());
+                let header = MessageHeader::new(network, *command, payload);
+                header.encode(buffer)?;
+                buffer.put_slice(payload);
             }
         }
 
         Ok(())
     }
 
+    /// Returns the command the message is sent with, e.g. [`PING_COMMAND`].
+    pub fn command(&self) -> [u8; 12] {
+        match self {
+            Self::Version(_) => VERSION_COMMAND,
+            Self::Verack => VERACK_COMMAND,
+            Self::Ping(_) => PING_COMMAND,
+            Self::Pong(_) => PONG_COMMAND,
+            Self::GetAddr => GETADDR_COMMAND,
+            Self::Addr(_) => ADDR_COMMAND,
+            Self::SendAddrV2 => SENDADDRV2_COMMAND,
+            Self::AddrV2(_) => ADDRV2_COMMAND,
+            Self::GetHeaders(_) => GETHEADERS_COMMAND,
+            Self::Headers(_) => HEADERS_COMMAND,
+            Self::GetBlocks(_) => GETBLOCKS_COMMAND,
+            Self::Block(_) | Self::LazyBlock(_) => BLOCK_COMMAND,
+            Self::GetData(_) => GETDATA_COMMAND,
+            Self::Inv(_) => INV_COMMAND,
+            Self::NotFound(_) => NOTFOUND_COMMAND,
+            Self::MemPool => MEMPOOL_COMMAND,
+            Self::Tx(_) => TX_COMMAND,
+            Self::Reject(_) => REJECT_COMMAND,
+            Self::FilterLoad(_) => FILTERLOAD_COMMAND,
+            Self::FilterAdd(_) => FILTERADD_COMMAND,
+            Self::FilterClear => FILTERCLEAR_COMMAND,
+            Self::MerkleBlock(_) => MERKLEBLOCK_COMMAND,
+            Self::Unknown { command, .. } => *command,
+        }
+    }
+
+    /// Returns the number of bytes [`Message::encode`] writes, including the header.
+    pub fn encoded_len(&self) -> usize {
+        let body_length = match self {
+            Self::Verack | Self::GetAddr | Self::SendAddrV2 | Self::MemPool | Self::FilterClear => {
+                0
+            }
+            Self::Version(version) => version.encoded_len(),
+            Self::Ping(nonce) | Self::Pong(nonce) => nonce.encoded_len(),
+            Self::Addr(addr) => addr.encoded_len(),
+            Self::AddrV2(addr) => addr.encoded_len(),
+            Self::GetHeaders(locator_hashes) | Self::GetBlocks(locator_hashes) => {
+                locator_hashes.encoded_len()
+            }
+            Self::Headers(headers) => headers.encoded_len(),
+            Self::Block(block) => block.encoded_len(),
+            Self::LazyBlock(block) => block.encoded_len(),
+            Self::GetData(inv) | Self::Inv(inv) | Self::NotFound(inv) => inv.encoded_len(),
+            Self::Tx(tx) => tx.encoded_len(),
+            Self::Reject(reject) => reject.encoded_len(),
+            Self::FilterLoad(filter_load) => filter_load.encoded_len(),
+            Self::FilterAdd(filter) => filter.encoded_len(),
+            Self::MerkleBlock(merkle_block) => merkle_block.encoded_len(),
+            Self::Unknown { payload, .. } => payload.len(),
+        };
+
+        HEADER_LEN + body_length
+    }
+
     /// Decodes the bytes into a message.
+    ///
+    /// Commands which aren't known are decoded into [`Message::Unknown`], with the remaining
+    /// bytes as the payload.
+    ///
+    /// Payload decoding failures are reported as a [`DecodeError`] wrapped in the returned
+    /// [`io::Error`], with the offset relative to the start of the payload.
     pub fn decode<B: Buf>(command: [u8; 12], bytes: &mut B) -> io::Result<Self> {
         let message = match command {
-            VERSION_COMMAND => Self::Version(Version::decode(bytes)?),
+            VERSION_COMMAND => Self::Version(Version::decode_detailed(bytes)?),
             VERACK_COMMAND => Self::Verack,
-            PING_COMMAND => Self::Ping(Nonce::decode(bytes)?),
-            PONG_COMMAND => Self::Pong(Nonce::decode(bytes)?),
+            PING_COMMAND => Self::Ping(Nonce::decode_detailed(bytes)?),
+            PONG_COMMAND => Self::Pong(Nonce::decode_detailed(bytes)?),
             GETADDR_COMMAND => Self::GetAddr,
-            ADDR_COMMAND => Self::Addr(Addr::decode(bytes)?),
-            GETHEADERS_COMMAND => Self::GetHeaders(LocatorHashes::decode(bytes)?),
-            HEADERS_COMMAND => Self::Headers(Headers::decode(bytes)?),
-            GETBLOCKS_COMMAND => Self::GetBlocks(LocatorHashes::decode(bytes)?),
-            BLOCK_COMMAND => Self::Block(Box::new(Block::decode(bytes)?)),
-            GETDATA_COMMAND => Self::GetData(Inv::decode(bytes)?),
-            INV_COMMAND => Self::Inv(Inv::decode(bytes)?),
-            NOTFOUND_COMMAND => Self::NotFound(Inv::decode(bytes)?),
+            ADDR_COMMAND => Self::Addr(Addr::decode_detailed(bytes)?),
+            SENDADDRV2_COMMAND => Self::SendAddrV2,
+            ADDRV2_COMMAND => Self::AddrV2(AddrV2::decode_detailed(bytes)?),
+            GETHEADERS_COMMAND => Self::GetHeaders(LocatorHashes::decode_detailed(bytes)?),
+            HEADERS_COMMAND => Self::Headers(Headers::decode_detailed(bytes)?),
+            GETBLOCKS_COMMAND => Self::GetBlocks(LocatorHashes::decode_detailed(bytes)?),
+            BLOCK_COMMAND => Self::Block(Box::new(Block::decode_detailed(bytes)?)),
+            GETDATA_COMMAND => Self::GetData(Inv::decode_detailed(bytes)?),
+            INV_COMMAND => Self::Inv(Inv::decode_detailed(bytes)?),
+            NOTFOUND_COMMAND => Self::NotFound(Inv::decode_detailed(bytes)?),
             MEMPOOL_COMMAND => Self::MemPool,
-            TX_COMMAND => Self::Tx(Tx::decode(bytes)?),
-            REJECT_COMMAND => Self::Reject(Reject::decode(bytes)?),
-            cmd => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    format!("Unknown command string: {:?}", cmd),
-                ))
+            TX_COMMAND => Self::Tx(Tx::decode_detailed(bytes)?),
+            REJECT_COMMAND => Self::Reject(Reject::decode_detailed(bytes)?),
+            FILTERLOAD_COMMAND => Self::FilterLoad(FilterLoad::decode_detailed(bytes)?),
+            FILTERADD_COMMAND => Self::FilterAdd(FilterAdd::decode_detailed(bytes)?),
+            FILTERCLEAR_COMMAND => Self::FilterClear,
+            MERKLEBLOCK_COMMAND => {
+                Self::MerkleBlock(Box::new(MerkleBlock::decode_detailed(bytes)?))
             }
+            command => Self::Unknown {
+                command,
+                payload: bytes.copy_to_bytes(bytes.remaining()),
+            },
         };
 
         Ok(message)
@@ -219,10 +352,13 @@ impl std::fmt::Display for Message {
             Message::Pong(nonce) => f.write_fmt(format_args!("Pong({:?})", nonce)),
             Message::GetAddr => f.write_str("GetAddr"),
             Message::Addr(_) => f.write_str("Addr"),
+            Message::SendAddrV2 => f.write_str("SendAddrV2"),
+            Message::AddrV2(_) => f.write_str("AddrV2"),
             Message::GetHeaders(_) => f.write_str("GetHeaders"),
             Message::Headers(_) => f.write_str("Headers"),
             Message::GetBlocks(_) => f.write_str("GetBlocks"),
             Message::Block(_) => f.write_str("Block"),
+            Message::LazyBlock(_) => f.write_str("LazyBlock"),
             Message::GetData(_) => f.write_str("GetData"),
             Message::Inv(_) => f.write_str("Inv"),
             Message::NotFound(_) => f.write_str("NotFound"),
@@ -232,11 +368,17 @@ impl std::fmt::Display for Message {
             Message::FilterLoad(_) => f.write_str("FilterLoad"),
             Message::FilterAdd(_) => f.write_str("FilterAdd"),
             Message::FilterClear => f.write_str("FilterClear"),
+            Message::MerkleBlock(_) => f.write_str("MerkleBlock"),
+            Message::Unknown { command, .. } => f.write_fmt(format_args!(
+                "Unknown({})",
+                String::from_utf8_lossy(command).trim_end_matches('\0')
+            )),
         }
     }
 }
 
-fn checksum(bytes: &[u8]) -> u32 {
+/// Computes the header checksum of a message body: the first 4 bytes of its double SHA-256 hash.
+pub fn checksum(bytes: &[u8]) -> u32 {
     let sha2 = Sha256::digest(bytes);
     let sha2d = Sha256::digest(&sha2);
 
@@ -245,3 +387,133 @@ fn checksum(bytes: &[u8]) -> u32 {
 
     u32::from_le_bytes(checksum)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        protocol::payload::{
+            addr::{NetAddr, NetworkAddrV2},
+            reject::CCode,
+            Hash, VarStr,
+        },
+        tools::fuzzing::default_fuzz_messages,
+        vectors::*,
+    };
+
+    fn test_messages() -> Vec<Message> {
+        let mut messages = default_fuzz_messages();
+        messages.extend([
+            Message::SendAddrV2,
+            Message::FilterClear,
+            Message::AddrV2(AddrV2::new(vec![
+                "127.0.0.1:8233"
+                    .parse::<std::net::SocketAddr>()
+                    .unwrap()
+                    .into(),
+                NetworkAddrV2::new(NetAddr::TorV3([7; 32]), 8233),
+            ])),
+            Message::Reject(Reject {
+                message: VarStr("tx".to_string()),
+                ccode: CCode::Invalid,
+                reason: VarStr("bad-txns".to_string()),
+                data: vec![1; 32],
+            }),
+            Message::FilterLoad(FilterLoad {
+                filter: vec![0xff; 300],
+                hash_fn_count: 11,
+                tweak: 0,
+                flags: 0,
+            }),
+        ]);
+
+        for block_bytes in [
+            &BLOCK_TESTNET_GENESIS_BYTES[..],
+            &BLOCK_TESTNET_0_207_500_BYTES[..],
+            &BLOCK_TESTNET_0_280_000_BYTES[..],
+            &BLOCK_TESTNET_0_584_000_BYTES[..],
+            &BLOCK_TESTNET_0_903_800_BYTES[..],
+            &BLOCK_TESTNET_1_028_500_BYTES[..],
+            &BLOCK_TESTNET_1_599_200_BYTES[..],
+        ] {
+            let block = Block::decode(&mut Cursor::new(block_bytes)).unwrap();
+            let txids: Vec<Hash> = block.txs.iter().map(Tx::txid).collect();
+
+            messages.push(Message::Headers(Headers::new(vec![block.header.clone()])));
+            messages.push(Message::Inv(Inv::new(
+                block.txs.iter().map(Tx::inv_hash).collect(),
+            )));
+            messages.push(Message::MerkleBlock(Box::new(MerkleBlock::new(
+                &block,
+                &txids[..1],
+            ))));
+            messages.extend(block.txs.iter().cloned().map(Message::Tx));
+            messages.push(Message::Block(Box::new(block)));
+        }
+
+        messages
+    }
+
+    #[test]
+    fn unknown_command_round_trip() {
+        let command = *b"feefilter\0\0\0";
+        let payload = 1000u64.to_le_bytes();
+
+        let mut bytes = BytesMut::new();
+        MessageHeader::new(Network::Testnet, command, &payload)
+            .encode(&mut bytes)
+            .unwrap();
+        bytes.put_slice(&payload);
+        let original = bytes.clone().freeze();
+
+        let header = MessageHeader::decode(&mut bytes).unwrap();
+        let message = Message::decode(header.command, &mut bytes).unwrap();
+        assert_eq!(
+            message,
+            Message::Unknown {
+                command,
+                payload: Bytes::copy_from_slice(&payload),
+            }
+        );
+        assert_eq!(message.to_string(), "Unknown(feefilter)");
+
+        let mut buffer = BytesMut::new();
+        message.encode(Network::Testnet, &mut buffer).unwrap();
+        assert_eq!(buffer.freeze(), original);
+    }
+
+    #[test]
+    fn encoded_len_matches_encoding() {
+        for message in test_messages() {
+            let mut buffer = BytesMut::new();
+            message.encode(Network::Testnet, &mut buffer).unwrap();
+            assert_eq!(buffer.len(), message.encoded_len(), "{}", message);
+
+            // The header is patched in after the payload, so check it describes the body.
+            let header = MessageHeader::decode(&mut &buffer[..]).unwrap();
+            let body = &buffer[HEADER_LEN..];
+            assert_eq!(header.body_length as usize, body.len(), "{}", message);
+            assert_eq!(header.checksum, checksum(body), "{}", message);
+        }
+    }
+
+    #[test]
+    fn encode_appends_to_the_buffer() {
+        let messages = test_messages();
+
+        let mut buffer = BytesMut::new();
+        for message in &messages {
+            message.encode(Network::Testnet, &mut buffer).unwrap();
+        }
+
+        // Each message is encoded as if the buffer had been empty.
+        for message in &messages {
+            let mut expected = BytesMut::new();
+            message.encode(Network::Testnet, &mut expected).unwrap();
+            assert_eq!(buffer.split_to(expected.len()), expected, "{}", message);
+        }
+        assert!(buffer.is_empty());
+    }
+}
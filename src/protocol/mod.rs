@@ -1,4 +1,8 @@
 //! An implementation of the Zcash network protocol types and messages.
 
+pub mod difficulty;
+pub mod dissector;
+pub mod equihash;
 pub mod message;
+pub mod network;
 pub mod payload;
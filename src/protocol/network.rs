@@ -0,0 +1,87 @@
+//! Network selection.
+
+use std::{fmt, str::FromStr};
+
+use crate::protocol::message::constants::{MAGIC_MAINNET, MAGIC_REGTEST, MAGIC_TESTNET};
+
+/// The network messages are exchanged on, identified by the magic bytes in the message header.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    /// The network the test suite's nodes are configured for.
+    #[default]
+    Testnet,
+    Regtest,
+    /// A network with custom magic bytes.
+    Custom([u8; 4]),
+}
+
+impl Network {
+    /// Returns the magic bytes identifying this network.
+    pub fn magic(&self) -> [u8; 4] {
+        match self {
+            Self::Mainnet => MAGIC_MAINNET,
+            Self::Testnet => MAGIC_TESTNET,
+            Self::Regtest => MAGIC_REGTEST,
+            Self::Custom(magic) => *magic,
+        }
+    }
+
+    /// Returns the network identified by the magic bytes, falling back to [`Network::Custom`].
+    pub fn from_magic(magic: [u8; 4]) -> Self {
+        match magic {
+            MAGIC_MAINNET => Self::Mainnet,
+            MAGIC_TESTNET => Self::Testnet,
+            MAGIC_REGTEST => Self::Regtest,
+            magic => Self::Custom(magic),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mainnet => f.write_str("mainnet"),
+            Self::Testnet => f.write_str("testnet"),
+            Self::Regtest => f.write_str("regtest"),
+            Self::Custom(magic) => f.write_str(&hex::encode(magic)),
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    /// Parses one of `mainnet`, `testnet` or `regtest`, or custom magic bytes as 8 hex digits.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "mainnet" => Ok(Self::Mainnet),
+            "testnet" => Ok(Self::Testnet),
+            "regtest" => Ok(Self::Regtest),
+            other => {
+                let mut magic = [0u8; 4];
+                hex::decode_to_slice(other, &mut magic)
+                    .map_err(|_| format!("unknown network {:?}", s))?;
+
+                Ok(Self::from_magic(magic))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn network_from_str() {
+        assert_eq!("Mainnet".parse(), Ok(Network::Mainnet));
+        assert_eq!("regtest".parse(), Ok(Network::Regtest));
+        assert_eq!("fa1af9bf".parse(), Ok(Network::Testnet));
+        assert_eq!(
+            "01020304".parse(),
+            Ok(Network::Custom([0x01, 0x02, 0x03, 0x04]))
+        );
+        assert!("signet".parse::<Network>().is_err());
+    }
+}
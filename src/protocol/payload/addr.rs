@@ -1,18 +1,32 @@
 //! Network address types.
+//!
+//! [`Addr`] only holds IP addresses, [`AddrV2`] also holds the overlay network addresses of
+//! [ZIP-155](https://zips.z.cash/zip-0155).
 
 use std::{
     convert::TryInto,
-    io,
-    net::{IpAddr::*, Ipv6Addr, SocketAddr},
+    fmt, io,
+    net::{IpAddr, IpAddr::*, Ipv4Addr, Ipv6Addr, SocketAddr},
 };
 
 use bytes::{Buf, BufMut};
+use sha3::{Digest, Sha3_256};
 use time::OffsetDateTime;
 
-use crate::protocol::payload::{codec::Codec, read_n_bytes, read_short_timestamp};
+use crate::protocol::payload::{
+    codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+    read_n_bytes, read_short_timestamp, Services, VarInt,
+};
+
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::hex_bytes;
+
+/// The maximum length of an address in an [`AddrV2`] message.
+pub const MAX_ADDRV2_ADDR_LEN: usize = 512;
 
 /// A list of network addresses, used for peering.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Addr {
     pub addrs: Vec<NetworkAddr>,
 }
@@ -39,19 +53,25 @@ impl Codec for Addr {
         self.addrs.encode(buffer)
     }
 
+    fn encoded_len(&self) -> usize {
+        self.addrs.encoded_len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        Ok(Self::new(Vec::decode(bytes)?))
+        Ok(Self::new(Vec::decode(bytes).in_field("addrs")?))
     }
 }
 
 /// A network address.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NetworkAddr {
     /// The last time this address was seen.
     /// Note: Present only when version is >= 31402
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339::option"))]
     pub last_seen: Option<OffsetDateTime>,
     /// The services supported by this address.
-    pub services: u64,
+    pub services: Services,
     /// The socket address.
     pub addr: SocketAddr,
 }
@@ -59,17 +79,17 @@ pub struct NetworkAddr {
 impl NetworkAddr {
     /// Creates a new NetworkAddr with the given socket address,
     /// `last_seen=OffsetDateTime::now_utc()`,
-    /// and `services=1` (only `NODE_NETWORK` is enabled).
+    /// and `services=NODE_NETWORK`.
     pub fn new(addr: SocketAddr) -> Self {
         Self {
             last_seen: Some(OffsetDateTime::now_utc()),
-            services: 1,
+            services: Services::NODE_NETWORK,
             addr,
         }
     }
 
     pub fn encode_without_timestamp<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
-        buffer.put_u64_le(self.services);
+        buffer.put_u64_le(self.services.bits());
 
         let (ip, port) = match self.addr {
             SocketAddr::V4(v4) => (v4.ip().to_ipv6_mapped(), v4.port()),
@@ -82,11 +102,18 @@ impl NetworkAddr {
         Ok(())
     }
 
+    /// Returns the number of bytes [`NetworkAddr::encode_without_timestamp`] writes.
+    pub fn encoded_len_without_timestamp(&self) -> usize {
+        8 + 16 + 2
+    }
+
     pub(super) fn decode_without_timestamp<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let services = u64::from_le_bytes(read_n_bytes(bytes)?);
+        let services = Services::from_bits_retain(u64::from_le_bytes(
+            read_n_bytes(bytes).in_field("services")?,
+        ));
 
         if bytes.remaining() < 16 {
-            return Err(io::ErrorKind::InvalidData.into());
+            return Err(DecodeError::truncated(16, bytes).in_field("addr").into());
         }
 
         let mut octets = [0u8; 16];
@@ -98,7 +125,7 @@ impl NetworkAddr {
             None => V6(v6_addr),
         };
 
-        let port = u16::from_be_bytes(read_n_bytes(bytes)?);
+        let port = u16::from_be_bytes(read_n_bytes(bytes).in_field("port")?);
 
         Ok(Self {
             last_seen: None,
@@ -123,8 +150,12 @@ impl Codec for NetworkAddr {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        4 + self.encoded_len_without_timestamp()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let timestamp = read_short_timestamp(bytes)?;
+        let timestamp = read_short_timestamp(bytes).in_field("last_seen")?;
         let without_timestamp = Self::decode_without_timestamp(bytes)?;
 
         Ok(Self {
@@ -133,3 +164,429 @@ impl Codec for NetworkAddr {
         })
     }
 }
+
+/// A list of network addresses of any network, used for peering once both peers sent
+/// [`SendAddrV2`](crate::protocol::message::Message::SendAddrV2).
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddrV2 {
+    pub addrs: Vec<NetworkAddrV2>,
+}
+
+impl AddrV2 {
+    /// Returns an `AddrV2` with no addresses.
+    pub fn empty() -> Self {
+        Self { addrs: Vec::new() }
+    }
+
+    /// Returns an `AddrV2` with the given addresses.
+    pub fn new(addrs: Vec<NetworkAddrV2>) -> Self {
+        Self { addrs }
+    }
+
+    /// Returns an iterator over the list of network addresses.
+    pub fn iter(&self) -> std::slice::Iter<'_, NetworkAddrV2> {
+        self.addrs.iter()
+    }
+}
+
+impl Codec for AddrV2 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.addrs.encode(buffer)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.addrs.encoded_len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        Ok(Self::new(Vec::decode(bytes).in_field("addrs")?))
+    }
+}
+
+/// A network address of an [`AddrV2`] message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NetworkAddrV2 {
+    /// The last time this address was seen.
+    #[cfg_attr(feature = "serde", serde(with = "time::serde::rfc3339"))]
+    pub last_seen: OffsetDateTime,
+    /// The services supported by this address.
+    pub services: Services,
+    /// The address.
+    pub addr: NetAddr,
+    /// The port, `0` for networks without ports.
+    pub port: u16,
+}
+
+impl NetworkAddrV2 {
+    /// Creates a new `NetworkAddrV2` with the given address and port,
+    /// `last_seen=OffsetDateTime::now_utc()`,
+    /// and `services=NODE_NETWORK`.
+    pub fn new(addr: NetAddr, port: u16) -> Self {
+        Self {
+            last_seen: OffsetDateTime::now_utc(),
+            services: Services::NODE_NETWORK,
+            addr,
+            port,
+        }
+    }
+
+    /// Returns the socket address for IPv4 and IPv6 addresses.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        self.addr.ip().map(|ip| SocketAddr::new(ip, self.port))
+    }
+}
+
+impl From<SocketAddr> for NetworkAddrV2 {
+    fn from(addr: SocketAddr) -> Self {
+        Self::new(addr.ip().into(), addr.port())
+    }
+}
+
+impl Codec for NetworkAddrV2 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        let timestamp: u32 = self.last_seen.unix_timestamp().try_into().unwrap();
+        buffer.put_u32_le(timestamp);
+        VarInt::new(self.services.bits() as usize).encode(buffer)?;
+
+        buffer.put_u8(self.addr.network_id());
+        let addr = self.addr.to_bytes();
+        VarInt::new(addr.len()).encode(buffer)?;
+        buffer.put_slice(&addr);
+
+        buffer.put_u16(self.port);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        let addr_len = self.addr.encoded_len();
+
+        4 + VarInt::new(self.services.bits() as usize).encoded_len()
+            + 1
+            + VarInt::new(addr_len).encoded_len()
+            + addr_len
+            + 2
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let last_seen = read_short_timestamp(bytes).in_field("last_seen")?;
+        let services = Services::from_bits_retain(read_compact_size(bytes).in_field("services")?);
+
+        let [network_id] = read_n_bytes(bytes).in_field("network_id")?;
+        let addr_len = *VarInt::decode(bytes).in_field("addr")?;
+        if addr_len > MAX_ADDRV2_ADDR_LEN {
+            let kind = DecodeErrorKind::LengthOverLimit {
+                length: addr_len,
+                limit: MAX_ADDRV2_ADDR_LEN,
+            };
+            return Err(DecodeError::new(kind, bytes).in_field("addr").into());
+        }
+
+        if bytes.remaining() < addr_len {
+            return Err(DecodeError::truncated(addr_len, bytes)
+                .in_field("addr")
+                .into());
+        }
+
+        let mut addr = vec![0u8; addr_len];
+        bytes.copy_to_slice(&mut addr);
+        let addr = NetAddr::from_bytes(network_id, addr).map_err(|reason| {
+            DecodeError::new(DecodeErrorKind::Invalid(reason), bytes).in_field("addr")
+        })?;
+
+        let port = u16::from_be_bytes(read_n_bytes(bytes).in_field("port")?);
+
+        Ok(Self {
+            last_seen,
+            services,
+            addr,
+            port,
+        })
+    }
+}
+
+/// An address on one of the networks of [ZIP-155](https://zips.z.cash/zip-0155).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NetAddr {
+    Ipv4(Ipv4Addr),
+    Ipv6(Ipv6Addr),
+    /// A Tor v2 onion service, deprecated in favour of [`NetAddr::TorV3`].
+    TorV2(#[cfg_attr(feature = "serde", serde(with = "hex_bytes"))] [u8; 10]),
+    /// The Ed25519 public key of a Tor v3 onion service.
+    TorV3(#[cfg_attr(feature = "serde", serde(with = "hex_bytes"))] [u8; 32]),
+    /// The SHA-256 hash of an I2P destination.
+    I2p(#[cfg_attr(feature = "serde", serde(with = "hex_bytes"))] [u8; 32]),
+    /// A CJDNS address, an IPv6 address in `fc00::/8`.
+    Cjdns(Ipv6Addr),
+    /// An address on a network this crate doesn't know, kept as is.
+    Unknown {
+        network_id: u8,
+        #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+        addr: Vec<u8>,
+    },
+}
+
+impl NetAddr {
+    /// Returns the network id the address is encoded with.
+    pub fn network_id(&self) -> u8 {
+        match self {
+            Self::Ipv4(_) => 1,
+            Self::Ipv6(_) => 2,
+            Self::TorV2(_) => 3,
+            Self::TorV3(_) => 4,
+            Self::I2p(_) => 5,
+            Self::Cjdns(_) => 6,
+            Self::Unknown { network_id, .. } => *network_id,
+        }
+    }
+
+    /// Returns the length of the encoded address, without the length prefix.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            Self::Ipv4(_) => 4,
+            Self::Ipv6(_) | Self::Cjdns(_) => 16,
+            Self::TorV2(addr) => addr.len(),
+            Self::TorV3(addr) | Self::I2p(addr) => addr.len(),
+            Self::Unknown { addr, .. } => addr.len(),
+        }
+    }
+
+    /// Returns the encoded address, without the length prefix.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Ipv4(ip) => ip.octets().to_vec(),
+            Self::Ipv6(ip) | Self::Cjdns(ip) => ip.octets().to_vec(),
+            Self::TorV2(addr) => addr.to_vec(),
+            Self::TorV3(addr) | Self::I2p(addr) => addr.to_vec(),
+            Self::Unknown { addr, .. } => addr.clone(),
+        }
+    }
+
+    /// Parses the encoded address for the network, failing if its length doesn't match the
+    /// network's address length. Unknown networks accept any length.
+    pub fn from_bytes(network_id: u8, addr: Vec<u8>) -> Result<Self, String> {
+        let wrong_length = |expected: usize| {
+            format!(
+                "network {} addresses are {} bytes, got {}",
+                network_id,
+                expected,
+                addr.len()
+            )
+        };
+
+        let addr = match network_id {
+            1 => Self::Ipv4(
+                <[u8; 4]>::try_from(&addr[..])
+                    .map_err(|_| wrong_length(4))?
+                    .into(),
+            ),
+            2 => Self::Ipv6(
+                <[u8; 16]>::try_from(&addr[..])
+                    .map_err(|_| wrong_length(16))?
+                    .into(),
+            ),
+            3 => Self::TorV2(addr[..].try_into().map_err(|_| wrong_length(10))?),
+            4 => Self::TorV3(addr[..].try_into().map_err(|_| wrong_length(32))?),
+            5 => Self::I2p(addr[..].try_into().map_err(|_| wrong_length(32))?),
+            6 => Self::Cjdns(
+                <[u8; 16]>::try_from(&addr[..])
+                    .map_err(|_| wrong_length(16))?
+                    .into(),
+            ),
+            _ => Self::Unknown { network_id, addr },
+        };
+
+        Ok(addr)
+    }
+
+    /// Returns the IP address of IPv4 and IPv6 addresses.
+    pub fn ip(&self) -> Option<IpAddr> {
+        match self {
+            Self::Ipv4(ip) => Some(V4(*ip)),
+            Self::Ipv6(ip) => Some(V6(*ip)),
+            _ => None,
+        }
+    }
+}
+
+impl From<IpAddr> for NetAddr {
+    fn from(ip: IpAddr) -> Self {
+        match ip {
+            V4(ip) => Self::Ipv4(ip),
+            V6(ip) => match ip.to_ipv4_mapped() {
+                Some(ip) => Self::Ipv4(ip),
+                None => Self::Ipv6(ip),
+            },
+        }
+    }
+}
+
+/// Formats IP and CJDNS addresses as usual, onion services and I2P destinations by their
+/// `.onion` and `.b32.i2p` host names.
+impl fmt::Display for NetAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ipv4(ip) => ip.fmt(f),
+            Self::Ipv6(ip) | Self::Cjdns(ip) => ip.fmt(f),
+            Self::TorV2(addr) => write!(f, "{}.onion", base32(addr)),
+            Self::TorV3(pubkey) => {
+                const VERSION: u8 = 3;
+                let checksum = Sha3_256::new()
+                    .chain_update(b".onion checksum")
+                    .chain_update(pubkey)
+                    .chain_update([VERSION])
+                    .finalize();
+
+                let addr = [&pubkey[..], &checksum[..2], &[VERSION]].concat();
+                write!(f, "{}.onion", base32(&addr))
+            }
+            Self::I2p(hash) => write!(f, "{}.b32.i2p", base32(hash)),
+            Self::Unknown { network_id, addr } => {
+                write!(f, "unknown({}):{}", network_id, hex::encode(addr))
+            }
+        }
+    }
+}
+
+/// Encodes the bytes with the lowercase RFC 4648 base32 alphabet, without padding.
+fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+    let mut encoded = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let (mut acc, mut acc_bits) = (0u16, 0);
+    for byte in bytes {
+        acc = (acc << 8) | u16::from(*byte);
+        acc_bits += 8;
+
+        while acc_bits >= 5 {
+            acc_bits -= 5;
+            encoded.push(ALPHABET[usize::from((acc >> acc_bits) & 0x1f)] as char);
+        }
+    }
+
+    if acc_bits > 0 {
+        encoded.push(ALPHABET[usize::from((acc << (5 - acc_bits)) & 0x1f)] as char);
+    }
+
+    encoded
+}
+
+/// Reads a compact size without the [`VarInt`] limit of the maximum message length.
+fn read_compact_size<B: Buf>(bytes: &mut B) -> io::Result<u64> {
+    let value = match u8::from_le_bytes(read_n_bytes(bytes)?) {
+        flag @ 0x00..=0xfc => flag.into(),
+        0xfd => u16::from_le_bytes(read_n_bytes(bytes)?).into(),
+        0xfe => u32::from_le_bytes(read_n_bytes(bytes)?).into(),
+        0xff => u64::from_le_bytes(read_n_bytes(bytes)?),
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn round_trip(addr: &NetworkAddrV2) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        addr.encode(&mut bytes).unwrap();
+        assert_eq!(
+            &NetworkAddrV2::decode(&mut Cursor::new(&bytes)).unwrap(),
+            addr
+        );
+
+        bytes
+    }
+
+    #[test]
+    fn addrv2_round_trip() {
+        let last_seen = OffsetDateTime::from_unix_timestamp(0x5f5e_1000).unwrap();
+        let addrs = [
+            NetAddr::Ipv4(Ipv4Addr::new(1, 2, 3, 4)),
+            NetAddr::Ipv6("2001:db8::1".parse().unwrap()),
+            NetAddr::TorV2([0xf1; 10]),
+            NetAddr::TorV3([0x79; 32]),
+            NetAddr::I2p([0xa2; 32]),
+            NetAddr::Cjdns("fc00::1".parse().unwrap()),
+            NetAddr::Unknown {
+                network_id: 0x42,
+                addr: vec![1, 2, 3],
+            },
+        ];
+
+        for addr in addrs {
+            let len = addr.to_bytes().len();
+            let addr = NetworkAddrV2 {
+                last_seen,
+                // Larger than a VarInt may be.
+                services: Services::from_bits_retain(1 << 40),
+                addr,
+                port: 8233,
+            };
+
+            // Timestamp, 9 byte services, network id, length, address and port.
+            assert_eq!(round_trip(&addr).len(), 4 + 9 + 1 + 1 + len + 2);
+        }
+
+        let addr: NetworkAddrV2 = SocketAddr::from(([127, 0, 0, 1], 8233)).into();
+        assert_eq!(addr.addr, NetAddr::Ipv4(Ipv4Addr::LOCALHOST));
+        assert_eq!(addr.socket_addr(), Some(([127, 0, 0, 1], 8233).into()));
+    }
+
+    #[test]
+    fn addrv2_rejects_bad_lengths() {
+        let addr = NetworkAddrV2 {
+            last_seen: OffsetDateTime::from_unix_timestamp(0).unwrap(),
+            services: Services::NODE_NETWORK,
+            addr: NetAddr::Unknown {
+                network_id: 1,
+                addr: vec![1, 2, 3],
+            },
+            port: 0,
+        };
+        let mut bytes = Vec::new();
+        addr.encode(&mut bytes).unwrap();
+        assert!(NetworkAddrV2::decode(&mut Cursor::new(&bytes)).is_err());
+
+        let addr = NetworkAddrV2 {
+            addr: NetAddr::Unknown {
+                network_id: 0x42,
+                addr: vec![0; MAX_ADDRV2_ADDR_LEN + 1],
+            },
+            ..addr
+        };
+        let mut bytes = Vec::new();
+        addr.encode(&mut bytes).unwrap();
+        assert!(NetworkAddrV2::decode(&mut Cursor::new(&bytes)).is_err());
+    }
+
+    #[test]
+    fn overlay_host_names() {
+        let tor = NetAddr::TorV3(
+            hex::decode("79bcc625184b05194975c28b66b66b0469f7f6556fb1ac3189a79b40dda32f1f")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(
+            tor.to_string(),
+            "pg6mmjiyjmcrsslvykfwnntlaru7p5svn6y2ymmju6nubxndf4pscryd.onion"
+        );
+
+        let i2p = NetAddr::I2p(
+            hex::decode("a2894dabaec08c0051a481a6dac88b64f98232ae42d4b6fd2fa81952dfe36a87")
+                .unwrap()
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(
+            i2p.to_string(),
+            "ukeu3k5oycgaauneqgtnvselmt4yemvoilkln7jpvamvfx7dnkdq.b32.i2p"
+        );
+    }
+}
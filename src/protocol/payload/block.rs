@@ -3,16 +3,31 @@
 use std::{convert::TryInto, io};
 
 use bytes::{Buf, BufMut};
-use sha2::Digest;
 
-use crate::protocol::payload::{
-    codec::Codec,
-    inv::{InvHash, ObjectKind},
-    read_n_bytes, Hash, ProtocolVersion, Tx, VarInt,
+use crate::protocol::{
+    difficulty::{CompactTarget, U256},
+    equihash,
+    network::Network,
+    payload::{
+        codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+        inv::{InvHash, ObjectKind},
+        merkle::hash_pair,
+        read_n_bytes, Hash, HashWriter, ProtocolVersion, Tx, VarInt,
+    },
 };
 
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::{hex_bytes, unix_timestamp};
+
+/// The BLAKE2b-256 personalization of the authorizing data commitment tree's nodes.
+const AUTH_DATA_HASH_PERSONALIZATION: &[u8; 16] = b"ZcashAuthDatHash";
+
+/// The length of the header fields preceding the nonce, which form the Equihash input.
+const EQUIHASH_INPUT_LEN: usize = 108;
+
 /// The locator hash object, used to communicate chain state.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct LocatorHashes {
     /// The protocol version.
     pub version: ProtocolVersion,
@@ -50,10 +65,16 @@ impl Codec for LocatorHashes {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        self.version.encoded_len()
+            + self.block_locator_hashes.encoded_len()
+            + self.hash_stop.encoded_len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let version = ProtocolVersion::decode(bytes)?;
-        let block_locator_hashes = Vec::decode(bytes)?;
-        let hash_stop = Hash::decode(bytes)?;
+        let version = ProtocolVersion::decode(bytes).in_field("version")?;
+        let block_locator_hashes = Vec::decode(bytes).in_field("block_locator_hashes")?;
+        let hash_stop = Hash::decode(bytes).in_field("hash_stop")?;
 
         Ok(Self {
             version,
@@ -65,6 +86,7 @@ impl Codec for LocatorHashes {
 
 /// A block, composed of its header and transactions.
 #[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     /// The block's header.
     pub header: Header,
@@ -165,6 +187,100 @@ impl Block {
     pub fn inv_hash(&self) -> InvHash {
         InvHash::new(ObjectKind::Block, self.double_sha256().unwrap())
     }
+
+    /// Computes the merkle root of the block's transaction ids.
+    ///
+    /// Levels with an odd number of nodes have their last node duplicated, as in Bitcoin.
+    pub fn compute_merkle_root(&self) -> Hash {
+        merkle_root(self.txs.iter().map(Tx::txid).collect()).0
+    }
+
+    /// Sets the header's merkle root to the one computed from the block's transactions, e.g.
+    /// after modifying them.
+    pub fn update_merkle_root(&mut self) {
+        self.header.merkle_root = self.compute_merkle_root();
+    }
+
+    /// Checks the header's merkle root against the one computed from the block's transactions.
+    ///
+    /// Trees with identical sibling nodes are rejected even if the root matches, as they allow
+    /// for duplicated transactions to go unnoticed (CVE-2012-2459).
+    pub fn verify_merkle_root(&self) -> io::Result<()> {
+        let (root, mutated) = merkle_root(self.txs.iter().map(Tx::txid).collect());
+
+        if root != self.header.merkle_root {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "computed merkle root doesn't match the header's merkle root",
+            ));
+        }
+
+        if mutated {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "merkle tree contains identical sibling hashes",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Computes the [ZIP-244](https://zips.z.cash/zip-0244#block-header-changes) authorizing data
+    /// commitment root of the block's transactions, committed to by NU5 block headers.
+    ///
+    /// Unlike the merkle root, the tree is padded to a power of two with zeroed leaves.
+    pub fn auth_data_root(&self) -> Hash {
+        let mut nodes: Vec<[u8; 32]> = self.txs.iter().map(|tx| tx.auth_digest().0).collect();
+
+        if nodes.is_empty() {
+            return Hash::zeroed();
+        }
+
+        nodes.resize(nodes.len().next_power_of_two(), [0; 32]);
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(2)
+                .map(|pair| {
+                    let hash = blake2b_simd::Params::new()
+                        .hash_length(32)
+                        .personal(AUTH_DATA_HASH_PERSONALIZATION)
+                        .to_state()
+                        .update(&pair[0])
+                        .update(&pair[1])
+                        .finalize();
+
+                    hash.as_bytes().try_into().unwrap()
+                })
+                .collect();
+        }
+
+        Hash::new(nodes[0])
+    }
+}
+
+/// Computes the Bitcoin-style merkle root of the leaves, along with whether any level contained
+/// identical sibling nodes.
+fn merkle_root(mut nodes: Vec<Hash>) -> (Hash, bool) {
+    if nodes.is_empty() {
+        return (Hash::zeroed(), false);
+    }
+
+    let mut mutated = false;
+    while nodes.len() > 1 {
+        // Identical siblings are only a mutation if they aren't the result of the duplication.
+        mutated |= nodes.chunks_exact(2).any(|pair| pair[0] == pair[1]);
+
+        if nodes.len() % 2 == 1 {
+            nodes.push(*nodes.last().unwrap());
+        }
+
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+
+    (nodes[0], mutated)
 }
 
 impl Codec for Block {
@@ -173,15 +289,20 @@ impl Codec for Block {
         self.txs.encode(buffer)
     }
 
+    fn encoded_len(&self) -> usize {
+        self.header.encoded_len_without_tx_count() + self.txs.encoded_len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let header = Header::decode_without_tx_count(bytes)?;
-        let txs = Vec::decode(bytes)?;
+        let header = Header::decode_without_tx_count(bytes).in_field("header")?;
+        let txs = Vec::decode(bytes).in_field("txs")?;
         Ok(Self { header, txs })
     }
 }
 
 /// A list of block headers.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Headers {
     pub headers: Vec<Header>,
 }
@@ -205,8 +326,12 @@ impl Codec for Headers {
         self.headers.encode(buffer)
     }
 
+    fn encoded_len(&self) -> usize {
+        self.headers.encoded_len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let headers = Vec::decode(bytes)?;
+        let headers = Vec::decode(bytes).in_field("headers")?;
         Ok(Self::new(headers))
     }
 }
@@ -214,6 +339,7 @@ impl Codec for Headers {
 /// A block header, see the [Zcash protocol
 /// spec](https://zips.z.cash/protocol/protocol.pdf#blockheader) for details.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Header {
     /// The block version number.
     pub version: ProtocolVersion,
@@ -224,16 +350,20 @@ pub struct Header {
     /// Field usage varies depending on version, see spec.
     pub light_client_root: Hash,
     /// The block timestamp.
+    #[cfg_attr(feature = "serde", serde(with = "unix_timestamp"))]
     pub timestamp: u32,
     /// An encoded version of the target threshold.
     pub bits: u32,
     /// The nonce used in the version messages, `Nonce(u64)`, is NOT the same as the nonce the
     /// block was generated with as it uses a `u32`.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
     pub nonce: [u8; 32],
-    /// The size of the Equihash solution in bytes (always `1344`).
+    /// The size of the Equihash solution in bytes (`1344` on mainnet and testnet, `36` on
+    /// regtest).
     pub solution_size: VarInt,
     /// The Equihash solution.
-    pub solution: [u8; 1344],
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub solution: Vec<u8>,
 }
 
 impl Codec for Header {
@@ -243,43 +373,92 @@ impl Codec for Header {
         VarInt(0).encode(buffer)
     }
 
+    fn encoded_len(&self) -> usize {
+        self.encoded_len_without_tx_count() + VarInt(0).encoded_len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self>
     where
         Self: Sized,
     {
-        let result = Self::decode_without_tx_count(bytes);
+        let header = Self::decode_without_tx_count(bytes)?;
 
         // tx_count must be zero
-        let tx_count = *VarInt::decode(bytes)?;
+        let tx_count = *VarInt::decode(bytes).in_field("tx_count")?;
         if tx_count != 0 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                format!("Message::Header.tx_count = {}, expected 0", tx_count),
-            ));
+            let kind = DecodeErrorKind::Invalid(format!("tx_count is {}, expected 0", tx_count));
+            return Err(DecodeError::new(kind, bytes).in_field("tx_count").into());
         }
 
-        result
+        Ok(header)
     }
 }
 
 impl Header {
     /// Calculates the double Sha256 hash for this header.
     pub fn double_sha256(&self) -> std::io::Result<Hash> {
-        let mut buffer = Vec::new();
+        let mut writer = HashWriter::new();
+        self.encode_without_tx_count(&mut writer)?;
 
-        self.encode_without_tx_count(&mut buffer)?;
+        Ok(writer.double_sha256())
+    }
 
-        let hash_bytes_1 = sha2::Sha256::digest(&buffer);
-        let hash_bytes_2 = sha2::Sha256::digest(&hash_bytes_1);
+    /// Verifies the header's Equihash solution with the given parameters.
+    pub fn verify_solution(&self, params: equihash::Params) -> Result<(), equihash::Error> {
+        let mut input = Vec::with_capacity(EQUIHASH_INPUT_LEN);
+        self.version.encode(&mut input).unwrap();
+        self.prev_block.encode(&mut input).unwrap();
+        self.merkle_root.encode(&mut input).unwrap();
+        self.light_client_root.encode(&mut input).unwrap();
+        input.put_u32_le(self.timestamp);
+        input.put_u32_le(self.bits);
 
-        let hash = Hash::new(hash_bytes_2.try_into().unwrap());
+        equihash::verify(params, &input, &self.nonce, &self.solution)
+    }
 
-        Ok(hash)
+    /// Returns the target encoded in `bits`.
+    pub fn compact_target(&self) -> CompactTarget {
+        CompactTarget(self.bits)
+    }
+
+    /// Returns the expected number of hashes needed to produce this header, see
+    /// [`CompactTarget::work`].
+    pub fn work(&self) -> U256 {
+        self.compact_target().work()
+    }
+
+    /// Returns `true` if the header's hash, read as a little-endian number, doesn't exceed the
+    /// target encoded in `bits`.
+    ///
+    /// Negative and overflowing targets are never met.
+    pub fn meets_target(&self) -> io::Result<bool> {
+        let target = match self.compact_target().target() {
+            Some(target) => target,
+            None => return Ok(false),
+        };
+        let hash = self.double_sha256()?;
+
+        Ok(U256::from_little_endian(&hash.0) <= target)
+    }
+
+    /// Verifies the header's proof of work: the Equihash solution for the network's parameters
+    /// and the hash against the `bits` target.
+    pub fn verify_pow(&self, network: Network) -> io::Result<()> {
+        self.verify_solution(equihash::Params::for_network(network))?;
+
+        if !self.meets_target()? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("header hash exceeds the target {:#010x}", self.bits),
+            ));
+        }
+
+        Ok(())
     }
 
     /// Encodes [Header] without the VarInt `tx_count=0`. This is useful for [Block] encoding which requires
     /// `tx_count=N`, as well as Hash calculation as it excludes `tx_count`.
-    fn encode_without_tx_count<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+    pub(super) fn encode_without_tx_count<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
         self.version.encode(buffer)?;
         self.prev_block.encode(buffer)?;
         self.merkle_root.encode(buffer)?;
@@ -295,23 +474,43 @@ impl Header {
         Ok(())
     }
 
+    /// Returns the number of bytes [`Header::encode_without_tx_count`] writes.
+    pub(super) fn encoded_len_without_tx_count(&self) -> usize {
+        self.version.encoded_len()
+            + self.prev_block.encoded_len()
+            + self.merkle_root.encoded_len()
+            + self.light_client_root.encoded_len()
+            + 4
+            + 4
+            + self.nonce.len()
+            + self.solution_size.encoded_len()
+            + self.solution.len()
+    }
+
     /// Decodes [Header] without consuming the VarInt `tx_count`. This is useful for [Block] decoding which
     /// requires the value to determine the number of transactions which follow in the body. [Header] on the
     /// otherhand requires that this value be 0. This gets asserted in Header::encode, making it unsuiteable
     /// for use by [Block].
-    fn decode_without_tx_count<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let version = ProtocolVersion::decode(bytes)?;
-        let prev_block = Hash::decode(bytes)?;
-        let merkle_root = Hash::decode(bytes)?;
-        let light_client_root = Hash::decode(bytes)?;
-
-        let timestamp = u32::from_le_bytes(read_n_bytes(bytes)?);
-
-        let bits = u32::from_le_bytes(read_n_bytes(bytes)?);
-        let nonce = read_n_bytes(bytes)?;
+    pub(super) fn decode_without_tx_count<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let version = ProtocolVersion::decode(bytes).in_field("version")?;
+        let prev_block = Hash::decode(bytes).in_field("prev_block")?;
+        let merkle_root = Hash::decode(bytes).in_field("merkle_root")?;
+        let light_client_root = Hash::decode(bytes).in_field("light_client_root")?;
+
+        let timestamp = u32::from_le_bytes(read_n_bytes(bytes).in_field("timestamp")?);
+
+        let bits = u32::from_le_bytes(read_n_bytes(bytes).in_field("bits")?);
+        let nonce = read_n_bytes(bytes).in_field("nonce")?;
+
+        let solution_size = VarInt::decode(bytes).in_field("solution_size")?;
+        if bytes.remaining() < solution_size.0 {
+            return Err(DecodeError::truncated(solution_size.0, bytes)
+                .in_field("solution")
+                .into());
+        }
 
-        let solution_size = VarInt::decode(bytes)?;
-        let solution = read_n_bytes(bytes)?;
+        let mut solution = vec![0u8; solution_size.0];
+        bytes.copy_to_slice(&mut solution);
 
         Ok(Self {
             version,
@@ -654,4 +853,110 @@ mod tests {
 
         assert_eq!(expected, hash);
     }
+
+    #[test]
+    fn testnet_blocks_verify_merkle_root() {
+        let vectors = [
+            &BLOCK_TESTNET_0_207_500_BYTES[..],
+            &BLOCK_TESTNET_0_280_000_BYTES[..],
+            &BLOCK_TESTNET_1_028_500_BYTES[..],
+            &BLOCK_TESTNET_1_599_200_BYTES[..],
+        ];
+        let blocks = vectors
+            .iter()
+            .map(|bytes| Block::decode(&mut Cursor::new(bytes)).unwrap())
+            .chain(Block::initial_testnet_blocks());
+
+        for block in blocks {
+            assert_eq!(block.compute_merkle_root(), block.header.merkle_root);
+            block.verify_merkle_root().unwrap();
+        }
+    }
+
+    #[test]
+    fn testnet_headers_verify_pow() {
+        let vectors = [
+            &BLOCK_TESTNET_0_207_500_BYTES[..],
+            &BLOCK_TESTNET_0_280_000_BYTES[..],
+            &BLOCK_TESTNET_0_584_000_BYTES[..],
+            &BLOCK_TESTNET_0_903_800_BYTES[..],
+            &BLOCK_TESTNET_1_028_500_BYTES[..],
+            &BLOCK_TESTNET_1_599_199_BYTES[..],
+            &BLOCK_TESTNET_1_599_200_BYTES[..],
+            &BLOCK_TESTNET_1_599_201_BYTES[..],
+        ];
+        let blocks = vectors
+            .iter()
+            .map(|bytes| Block::decode(&mut Cursor::new(bytes)).unwrap())
+            .chain(Block::initial_testnet_blocks());
+
+        for block in blocks {
+            block.header.verify_pow(Network::Testnet).unwrap();
+        }
+    }
+
+    #[test]
+    fn tampered_header_fails_pow() {
+        let mut header = Block::testnet_1().header;
+        header.nonce[0] ^= 1;
+        assert!(header.verify_solution(equihash::Params::MAINNET).is_err());
+
+        let mut header = Block::testnet_1().header;
+        header.solution[100] ^= 1;
+        assert!(header.verify_solution(equihash::Params::MAINNET).is_err());
+
+        // A valid solution whose hash misses a tighter target.
+        let mut header = Block::testnet_1().header;
+        header.bits = 0x0300_0001;
+        assert!(!header.meets_target().unwrap());
+        assert!(header.verify_pow(Network::Testnet).is_err());
+    }
+
+    #[test]
+    fn merkle_root_with_multiple_txs() {
+        // Assemble a block out of the coinbase transactions of the initial testnet blocks.
+        let blocks = Block::initial_testnet_blocks();
+        let mut block = blocks[0].clone();
+        block.txs = blocks.iter().map(|block| block.txs[0].clone()).collect();
+        assert!(block.verify_merkle_root().is_err());
+
+        block.update_merkle_root();
+        block.verify_merkle_root().unwrap();
+
+        // Duplicating the last transaction of an odd-sized level keeps the root (CVE-2012-2459).
+        block.txs.truncate(3);
+        block.update_merkle_root();
+        block.txs.push(block.txs[2].clone());
+        assert_eq!(block.compute_merkle_root(), block.header.merkle_root);
+        assert!(block.verify_merkle_root().is_err());
+    }
+
+    #[test]
+    fn auth_data_root() {
+        let block = Block::decode(&mut Cursor::new(&BLOCK_TESTNET_1_599_200_BYTES[..])).unwrap();
+        assert_eq!(block.auth_data_root(), block.txs[0].auth_digest());
+
+        // Pre-V5 transactions commit to `[0xff; 32]`, the tree is padded with zeroes.
+        let mut block = Block::testnet_1();
+        block.txs.push(Block::testnet_2().txs[0].clone());
+        block.txs.push(Block::testnet_3().txs[0].clone());
+
+        let node = |left: &[u8; 32], right: &[u8; 32]| -> [u8; 32] {
+            blake2b_simd::Params::new()
+                .hash_length(32)
+                .personal(AUTH_DATA_HASH_PERSONALIZATION)
+                .to_state()
+                .update(left)
+                .update(right)
+                .finalize()
+                .as_bytes()
+                .try_into()
+                .unwrap()
+        };
+        let expected = node(
+            &node(&[0xff; 32], &[0xff; 32]),
+            &node(&[0xff; 32], &[0; 32]),
+        );
+        assert_eq!(block.auth_data_root(), Hash::new(expected));
+    }
 }
@@ -1,6 +1,6 @@
 //! Traits for encoding and decoding network message types.
 
-use std::io;
+use std::{borrow::Cow, fmt, io};
 
 use bytes::{Buf, BufMut};
 
@@ -11,10 +11,28 @@ pub trait Codec {
     /// Encodes the payload into the supplied buffer.
     fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()>;
 
+    /// Returns the number of bytes [`Codec::encode`] writes, without encoding the payload.
+    fn encoded_len(&self) -> usize;
+
     /// Decodes the bytes and returns the payload.
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self>
     where
         Self: Sized;
+
+    /// Decodes the bytes like [`Codec::decode`], but returns a [`DecodeError`] whose path is
+    /// rooted at this type's name and whose offset is relative to the start of `bytes`.
+    fn decode_detailed<B: Buf>(bytes: &mut B) -> Result<Self, DecodeError>
+    where
+        Self: Sized,
+    {
+        let len = bytes.remaining();
+        Self::decode(bytes).map_err(|err| {
+            let mut err = DecodeError::from(err);
+            err.path
+                .push(PathSegment::Field(short_type_name::<Self>().into()));
+            err.with_input_len(len)
+        })
+    }
 }
 
 impl<T: Codec> Codec for Vec<T> {
@@ -27,11 +45,264 @@ impl<T: Codec> Codec for Vec<T> {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        VarInt(self.len()).encoded_len() + self.iter().map(T::encoded_len).sum::<usize>()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self>
     where
         Self: Sized,
     {
         let length = *VarInt::decode(bytes)?;
-        (0..length).map(|_| T::decode(bytes)).collect()
+        (0..length).map(|i| T::decode(bytes).at_index(i)).collect()
+    }
+}
+
+/// The reason decoding failed, see [`DecodeError`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The buffer ended before the `needed` bytes could be read.
+    Truncated { needed: usize },
+    /// A [`VarInt`] value exceeds the maximum message length.
+    BadVarInt { value: u64 },
+    /// An enum tag doesn't match any known variant of the `name`d type.
+    BadTag { name: &'static str, tag: u64 },
+    /// A length prefix exceeds the limit for its field.
+    LengthOverLimit { length: usize, limit: usize },
+    /// The data is invalid for any other reason.
+    Invalid(String),
+}
+
+impl fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated { needed } => write!(f, "buffer truncated, needed {} bytes", needed),
+            Self::BadVarInt { value } => write!(f, "VarInt value {} is out of range", value),
+            Self::BadTag { name, tag } => write!(f, "unknown {} tag {:#x}", name, tag),
+            Self::LengthOverLimit { length, limit } => {
+                write!(f, "length {} exceeds the limit of {}", length, limit)
+            }
+            Self::Invalid(reason) => f.write_str(reason),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathSegment {
+    Field(Cow<'static, str>),
+    Index(usize),
+}
+
+/// An error encountered while decoding a payload, along with where it happened.
+///
+/// The path names the fields leading to the failure, e.g. `Block.txs[3].V4.outputs_sapling[1]`,
+/// and is built up as the error propagates out of the nested decoders, see [`DecodeContext`].
+///
+/// Converts into an [`io::Error`] of kind [`io::ErrorKind::InvalidData`], from which it can be
+/// recovered again with [`DecodeError::from`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    kind: DecodeErrorKind,
+    // Innermost segment first, as the path is built while unwinding.
+    path: Vec<PathSegment>,
+    // The number of bytes left in the buffer when the error was detected.
+    remaining: Option<usize>,
+    offset: Option<usize>,
+}
+
+impl DecodeError {
+    /// Creates an error of the given kind, detected with `bytes` left to decode.
+    pub fn new<B: Buf>(kind: DecodeErrorKind, bytes: &B) -> Self {
+        Self {
+            kind,
+            path: Vec::new(),
+            remaining: Some(bytes.remaining()),
+            offset: None,
+        }
+    }
+
+    /// Creates a [`DecodeErrorKind::Truncated`] error, for when `needed` bytes exceed the ones
+    /// left in `bytes`.
+    pub fn truncated<B: Buf>(needed: usize, bytes: &B) -> Self {
+        Self::new(DecodeErrorKind::Truncated { needed }, bytes)
+    }
+
+    /// Returns the reason decoding failed.
+    pub fn kind(&self) -> &DecodeErrorKind {
+        &self.kind
+    }
+
+    /// Returns the path of the field which failed to decode, e.g. `Block.txs[3].V4`.
+    pub fn path(&self) -> String {
+        let mut path = String::new();
+        for segment in self.path.iter().rev() {
+            match segment {
+                PathSegment::Field(name) if path.is_empty() => path.push_str(name),
+                PathSegment::Field(name) => {
+                    path.push('.');
+                    path.push_str(name);
+                }
+                PathSegment::Index(i) => path.push_str(&format!("[{}]", i)),
+            }
+        }
+
+        path
+    }
+
+    /// Returns the byte offset at which the error was detected, relative to the start of the
+    /// outermost [`Codec::decode_detailed`] call.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Prepends a field name to the error's path.
+    pub fn in_field(mut self, name: &'static str) -> Self {
+        self.path.push(PathSegment::Field(name.into()));
+        self
+    }
+
+    /// Prepends a collection index to the error's path.
+    pub fn at_index(mut self, index: usize) -> Self {
+        self.path.push(PathSegment::Index(index));
+        self
+    }
+
+    /// Sets the offset from the length of the buffer that decoding started with.
+    pub fn with_input_len(mut self, len: usize) -> Self {
+        if let Some(remaining) = self.remaining {
+            self.offset = Some(len.saturating_sub(remaining));
+        }
+        self
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path();
+        if !path.is_empty() {
+            write!(f, "{}: ", path)?;
+        }
+
+        write!(f, "{}", self.kind)?;
+
+        if let Some(offset) = self.offset {
+            write!(f, " at byte offset {}", offset)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for io::Error {
+    fn from(err: DecodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl From<io::Error> for DecodeError {
+    /// Unwraps a [`DecodeError`] carried by the [`io::Error`], other errors are converted into
+    /// a [`DecodeErrorKind::Invalid`] without an offset.
+    fn from(err: io::Error) -> Self {
+        let kind = err.kind();
+        let reason = match err.into_inner() {
+            Some(inner) => match inner.downcast::<DecodeError>() {
+                Ok(err) => return *err,
+                Err(inner) => inner.to_string(),
+            },
+            None => io::Error::from(kind).to_string(),
+        };
+
+        Self {
+            kind: DecodeErrorKind::Invalid(reason),
+            path: Vec::new(),
+            remaining: None,
+            offset: None,
+        }
+    }
+}
+
+/// Returns the name of `T` with the module paths stripped, e.g. `Vec<Hash>`.
+fn short_type_name<T>() -> String {
+    let name = std::any::type_name::<T>();
+    let mut short = String::with_capacity(name.len());
+    let mut segment_start = 0;
+
+    for (i, c) in name.char_indices() {
+        match c {
+            ':' => segment_start = i + 1,
+            '<' | '>' | ',' | ' ' | '[' | ']' | ';' | '(' | ')' | '&' => {
+                short.push_str(&name[segment_start..i]);
+                short.push(c);
+                segment_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    short.push_str(&name[segment_start..]);
+
+    short
+}
+
+/// Adds path context to the errors of nested decoders, see [`DecodeError`].
+pub trait DecodeContext<T> {
+    /// Prepends a field name to the error's path.
+    fn in_field(self, name: &'static str) -> io::Result<T>;
+
+    /// Prepends a collection index to the error's path.
+    fn at_index(self, index: usize) -> io::Result<T>;
+}
+
+impl<T> DecodeContext<T> for io::Result<T> {
+    fn in_field(self, name: &'static str) -> io::Result<T> {
+        self.map_err(|err| DecodeError::from(err).in_field(name).into())
+    }
+
+    fn at_index(self, index: usize) -> io::Result<T> {
+        self.map_err(|err| DecodeError::from(err).at_index(index).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::protocol::payload::{block::Block, Hash};
+
+    #[test]
+    fn decode_error_round_trips_through_io_error() {
+        let bytes = Cursor::new([0u8; 4]);
+        let err = DecodeError::truncated(32, &bytes).in_field("hash");
+
+        let io_err: io::Error = err.clone().into();
+        assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(DecodeError::from(io_err), err);
+    }
+
+    #[test]
+    fn vec_errors_carry_the_index() {
+        let mut bytes = Vec::new();
+        vec![Hash::zeroed(); 2].encode(&mut bytes).unwrap();
+        bytes.truncate(bytes.len() - 1);
+
+        let err = Vec::<Hash>::decode_detailed(&mut Cursor::new(&bytes)).unwrap_err();
+        assert_eq!(err.path(), "Vec<Hash>[1]");
+        assert_eq!(err.kind(), &DecodeErrorKind::Truncated { needed: 32 });
+        assert_eq!(err.offset(), Some(33));
+    }
+
+    #[test]
+    fn truncated_block_reports_path_and_offset() {
+        let mut bytes = Vec::new();
+        Block::testnet_1().encode(&mut bytes).unwrap();
+        let len = bytes.len();
+        bytes.truncate(len - 1);
+
+        let err = Block::decode_detailed(&mut Cursor::new(&bytes)).unwrap_err();
+        assert_eq!(err.path(), "Block.txs[0].V1.lock_time");
+        assert_eq!(err.kind(), &DecodeErrorKind::Truncated { needed: 4 });
+        assert_eq!(err.offset(), Some(len - 4));
     }
 }
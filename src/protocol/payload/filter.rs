@@ -1,22 +1,47 @@
 //! Bloom filtering types, see [BIP 37](https://github.com/bitcoin/bips/blob/master/bip-0037.mediawiki).
 
-use std::io::{self, Cursor, ErrorKind, Read};
+use std::io;
 
 use bytes::{Buf, BufMut};
 
-use crate::protocol::payload::{codec::Codec, read_n_bytes};
+use crate::protocol::payload::{
+    codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+    read_n_bytes,
+    script::{Instruction, Instructions, ScriptKind},
+    Tx, VarInt,
+};
+
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::hex_bytes;
+
+/// The maximum size of a filter in bytes.
+pub const MAX_FILTER_BYTES: usize = 36_000;
+/// The maximum number of hash functions a filter may use.
+pub const MAX_HASH_FN_COUNT: u32 = 50;
+/// The maximum length of a [`FilterAdd`] data element.
+pub const MAX_FILTER_ADD_BYTES: usize = 520;
+
+// Multiplier applied to the hash function index when deriving each function's seed.
+const HASH_FN_SEED_MULTIPLIER: u32 = 0xfba4_c795;
+
+// Only the two lowest bits of `FilterLoad::flags` select the update mode.
+const UPDATE_MASK: u8 = 0b11;
 
 /// A modification to an existing filter.
 #[derive(Debug, PartialEq, Eq, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FilterAdd {
     /// The data element to add to the current filter.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
     pub data: Vec<u8>,
 }
 
 /// A new filter on the connection.
 #[derive(Debug, PartialEq, Eq, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FilterLoad {
     /// The filter itself.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
     pub filter: Vec<u8>,
     /// The number of hash functions to use in this filter.
     pub hash_fn_count: u32,
@@ -28,34 +53,46 @@ pub struct FilterLoad {
 
 impl Codec for FilterAdd {
     fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        VarInt::new(self.data.len()).encode(buffer)?;
         buffer.put_slice(&self.data);
 
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        VarInt::new(self.data.len()).encoded_len() + self.data.len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self>
     where
         Self: Sized,
     {
-        let mut data = Vec::new();
-        bytes.reader().read_to_end(&mut data)?;
+        let data_len = *VarInt::decode(bytes).in_field("data")?;
+
+        if data_len > MAX_FILTER_ADD_BYTES {
+            let kind = DecodeErrorKind::LengthOverLimit {
+                length: data_len,
+                limit: MAX_FILTER_ADD_BYTES,
+            };
+            return Err(DecodeError::new(kind, bytes).in_field("data").into());
+        }
 
-        if data.len() > 520 {
-            return Err(io::Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Maximum FilterAdd data length is 520, but got {}",
-                    data.len()
-                ),
-            ));
+        if bytes.remaining() < data_len {
+            return Err(DecodeError::truncated(data_len, bytes)
+                .in_field("data")
+                .into());
         }
 
+        let mut data = vec![0; data_len];
+        bytes.copy_to_slice(&mut data);
+
         Ok(Self { data })
     }
 }
 
 impl Codec for FilterLoad {
     fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        VarInt::new(self.filter.len()).encode(buffer)?;
         buffer.put_slice(&self.filter);
         buffer.put_u32_le(self.hash_fn_count);
         buffer.put_u32_le(self.tweak);
@@ -64,46 +101,36 @@ impl Codec for FilterLoad {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        VarInt::new(self.filter.len()).encoded_len() + self.filter.len() + 4 + 4 + 1
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self>
     where
         Self: Sized,
     {
-        // Have to read to end in order to get size of filter.
-        // (we only know the final 9 bytes are reserved for the other fields)
-        let mut buffer = Vec::new();
-        let bytes_read = bytes.reader().read_to_end(&mut buffer)?;
-
-        const NON_FILTER_BYTES: usize = 4 + 4 + 1;
-        if bytes_read < NON_FILTER_BYTES {
-            return Err(io::Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Minimum FilterLoad bytes required is {} but only got {}",
-                    NON_FILTER_BYTES, bytes_read
-                ),
-            ));
-        }
-        let filter_bytes = bytes_read - NON_FILTER_BYTES;
-        // maximum filter size is 36k bytes
-        const MAX_FILTER_BYTES: usize = 36_000;
+        let filter_bytes = *VarInt::decode(bytes).in_field("filter")?;
+
         if filter_bytes > MAX_FILTER_BYTES {
-            return Err(io::Error::new(
-                ErrorKind::InvalidData,
-                format!(
-                    "Maximum filter bytes is {} but got {}",
-                    MAX_FILTER_BYTES, filter_bytes
-                ),
-            ));
+            let kind = DecodeErrorKind::LengthOverLimit {
+                length: filter_bytes,
+                limit: MAX_FILTER_BYTES,
+            };
+            return Err(DecodeError::new(kind, bytes).in_field("filter").into());
         }
 
-        let mut cursor = Cursor::new(&buffer[..]);
+        if bytes.remaining() < filter_bytes {
+            return Err(DecodeError::truncated(filter_bytes, bytes)
+                .in_field("filter")
+                .into());
+        }
 
         let mut filter = vec![0; filter_bytes];
-        cursor.read_exact(&mut filter)?;
+        bytes.copy_to_slice(&mut filter);
 
-        let hash_fn_count = u32::from_le_bytes(read_n_bytes(&mut cursor)?);
-        let tweak = u32::from_le_bytes(read_n_bytes(&mut cursor)?);
-        let flags = u8::from_le_bytes(read_n_bytes(&mut cursor)?);
+        let hash_fn_count = u32::from_le_bytes(read_n_bytes(bytes).in_field("hash_fn_count")?);
+        let tweak = u32::from_le_bytes(read_n_bytes(bytes).in_field("tweak")?);
+        let flags = u8::from_le_bytes(read_n_bytes(bytes).in_field("flags")?);
 
         Ok(Self {
             filter,
@@ -114,10 +141,396 @@ impl Codec for FilterLoad {
     }
 }
 
+/// Controls how a [`BloomFilter`] is updated when a transaction output matches it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum UpdateMode {
+    /// The filter is never updated.
+    None,
+    /// The outpoint of every matching output is added to the filter.
+    All,
+    /// The outpoint is only added if the matching output is pay-to-pubkey or bare multisig.
+    P2PubKeyOnly,
+}
+
+impl UpdateMode {
+    /// Returns the update mode encoded in the `flags` field of a [`FilterLoad`].
+    pub fn from_flags(flags: u8) -> Self {
+        match flags & UPDATE_MASK {
+            1 => Self::All,
+            2 => Self::P2PubKeyOnly,
+            _ => Self::None,
+        }
+    }
+
+    /// Returns the `flags` value encoding this update mode.
+    pub fn flags(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::All => 1,
+            Self::P2PubKeyOnly => 2,
+        }
+    }
+}
+
+/// A bloom filter as described by BIP 37.
+///
+/// This can be used to mirror the filter a node has loaded for a connection and check which
+/// objects should be relayed over it.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BloomFilter {
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    data: Vec<u8>,
+    hash_fn_count: u32,
+    tweak: u32,
+    flags: u8,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for `elements` entries at the given false positive rate.
+    ///
+    /// The size and number of hash functions are capped at [`MAX_FILTER_BYTES`] and
+    /// [`MAX_HASH_FN_COUNT`] respectively, following the reference implementation.
+    pub fn new(elements: usize, fp_rate: f64, tweak: u32, mode: UpdateMode) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let elements = elements.max(1) as f64;
+
+        let filter_bits = (-1.0 / (ln2 * ln2) * elements * fp_rate.ln()) as usize;
+        let data = vec![0; filter_bits.min(MAX_FILTER_BYTES * 8) / 8];
+
+        let hash_fn_count = ((data.len() * 8) as f64 / elements * ln2) as u32;
+
+        Self {
+            data,
+            hash_fn_count: hash_fn_count.min(MAX_HASH_FN_COUNT),
+            tweak,
+            flags: mode.flags(),
+        }
+    }
+
+    /// Returns the raw filter bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the update mode selected by this filter's flags.
+    pub fn update_mode(&self) -> UpdateMode {
+        UpdateMode::from_flags(self.flags)
+    }
+
+    /// Returns `true` if the filter respects the BIP 37 size and hash function limits.
+    pub fn is_within_size_constraints(&self) -> bool {
+        self.data.len() <= MAX_FILTER_BYTES && self.hash_fn_count <= MAX_HASH_FN_COUNT
+    }
+
+    /// Inserts the data element into the filter.
+    pub fn insert(&mut self, element: &[u8]) {
+        if self.data.is_empty() {
+            return;
+        }
+
+        for n in 0..self.hash_fn_count {
+            let index = self.bit_index(n, element);
+            self.data[index >> 3] |= 1 << (index & 7);
+        }
+    }
+
+    /// Returns `true` if the data element may be present in the filter.
+    pub fn contains(&self, element: &[u8]) -> bool {
+        if self.data.is_empty() {
+            return false;
+        }
+
+        (0..self.hash_fn_count).all(|n| {
+            let index = self.bit_index(n, element);
+            self.data[index >> 3] & (1 << (index & 7)) != 0
+        })
+    }
+
+    /// Returns `true` if the transaction matches the filter, updating the filter with matched
+    /// outpoints according to its [`UpdateMode`].
+    ///
+    /// A transaction matches if its txid, a data push in one of its output scripts, one of the
+    /// outpoints it spends or a data push in one of its input scripts is in the filter.
+    pub fn matches_tx(&mut self, tx: &Tx) -> io::Result<bool> {
+        let tx_hash = tx.txid();
+
+        let mut tx_hash_bytes = Vec::with_capacity(32);
+        tx_hash.encode(&mut tx_hash_bytes)?;
+        let mut is_match = self.contains(&tx_hash_bytes);
+
+        for (index, tx_out) in tx.tx_out().iter().enumerate() {
+            let script = tx_out.pk_script();
+
+            if pushed_data(script).any(|data| !data.is_empty() && self.contains(data)) {
+                is_match = true;
+
+                let update = match self.update_mode() {
+                    UpdateMode::None => false,
+                    UpdateMode::All => true,
+                    UpdateMode::P2PubKeyOnly => matches!(
+                        ScriptKind::classify(script),
+                        ScriptKind::PayToPubKey(_) | ScriptKind::Multisig { .. }
+                    ),
+                };
+
+                if update {
+                    self.insert(&outpoint(&tx_hash_bytes, index as u32));
+                }
+            }
+        }
+
+        if is_match {
+            return Ok(true);
+        }
+
+        for tx_in in tx.tx_in() {
+            let mut prev_out_hash = Vec::with_capacity(32);
+            tx_in.prev_out_hash().encode(&mut prev_out_hash)?;
+
+            if self.contains(&outpoint(&prev_out_hash, tx_in.prev_out_index()))
+                || pushed_data(tx_in.script()).any(|data| !data.is_empty() && self.contains(data))
+            {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn bit_index(&self, n: u32, element: &[u8]) -> usize {
+        let seed = n
+            .wrapping_mul(HASH_FN_SEED_MULTIPLIER)
+            .wrapping_add(self.tweak);
+        murmur3(seed, element) as usize % (self.data.len() * 8)
+    }
+}
+
+impl From<&FilterLoad> for BloomFilter {
+    fn from(filter_load: &FilterLoad) -> Self {
+        Self {
+            data: filter_load.filter.clone(),
+            hash_fn_count: filter_load.hash_fn_count,
+            tweak: filter_load.tweak,
+            flags: filter_load.flags,
+        }
+    }
+}
+
+impl From<BloomFilter> for FilterLoad {
+    fn from(filter: BloomFilter) -> Self {
+        Self {
+            filter: filter.data,
+            hash_fn_count: filter.hash_fn_count,
+            tweak: filter.tweak,
+            flags: filter.flags,
+        }
+    }
+}
+
+/// Serializes an outpoint (transaction hash and output index) as it is inserted into a filter.
+fn outpoint(tx_hash: &[u8], index: u32) -> Vec<u8> {
+    let mut outpoint = Vec::with_capacity(tx_hash.len() + 4);
+    outpoint.put_slice(tx_hash);
+    outpoint.put_u32_le(index);
+
+    outpoint
+}
+
+/// Returns an iterator over the data pushed by the script, stopping at the first malformed push.
+fn pushed_data(script: &[u8]) -> impl Iterator<Item = &[u8]> {
+    Instructions::new(script)
+        .map_while(Result::ok)
+        .filter_map(|instruction| match instruction {
+            Instruction::Push(data) => Some(data),
+            Instruction::Op(_) => None,
+        })
+}
+
+/// The 32-bit x86 variant of MurmurHash3, as used by BIP 37.
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h1 = seed;
+    let mut blocks = data.chunks_exact(4);
+
+    for block in &mut blocks {
+        let mut k1 = u32::from_le_bytes([block[0], block[1], block[2], block[3]]);
+        k1 = k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+
+        h1 ^= k1;
+        h1 = h1.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let mut k1 = 0u32;
+        for (i, byte) in tail.iter().enumerate() {
+            k1 ^= (*byte as u32) << (8 * i);
+        }
+
+        h1 ^= k1.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    // Finalization mix, forces all bits of the hash block to avalanche.
+    h1 ^= data.len() as u32;
+    h1 ^= h1 >> 16;
+    h1 = h1.wrapping_mul(0x85eb_ca6b);
+    h1 ^= h1 >> 13;
+    h1 = h1.wrapping_mul(0xc2b2_ae35);
+    h1 ^= h1 >> 16;
+
+    h1
+}
+
 #[cfg(test)]
 mod tests {
+    use std::io::Cursor;
+
+    use hex::FromHex;
+
     use super::*;
 
+    #[test]
+    fn murmur3_vectors() {
+        // Vectors taken from the Bitcoin Core test suite.
+        let cases: [(u32, u32, &str); 8] = [
+            (0x0000_0000, 0x0000_0000, ""),
+            (0x6a39_6f08, 0xfba4_c795, ""),
+            (0x81f1_6f39, 0xffff_ffff, ""),
+            (0x514e_28b7, 0x0000_0000, "00"),
+            (0xea3f_0b17, 0xfba4_c795, "00"),
+            (0xfd6c_f10d, 0x0000_0000, "ff"),
+            (0x16c6_b7ab, 0x0000_0000, "0011"),
+            (0xb447_1bf8, 0x0000_0000, "00112233"),
+        ];
+
+        for (expected, seed, data) in cases {
+            let data = Vec::<u8>::from_hex(data).unwrap();
+            assert_eq!(murmur3(seed, &data), expected);
+        }
+    }
+
+    #[test]
+    fn bloom_filter_insert_contains() {
+        let mut filter = BloomFilter::new(3, 0.01, 0, UpdateMode::All);
+
+        let element = Vec::<u8>::from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap();
+        filter.insert(&element);
+        assert!(filter.contains(&element));
+
+        let other = Vec::<u8>::from_hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8").unwrap();
+        assert!(!filter.contains(&other));
+
+        for element in [
+            "b5a2c786d9ef4658287ced5914b37a1b4aa32eee",
+            "b9300670b4c5366e95b2699e8b18bc75e5f729c5",
+        ] {
+            filter.insert(&Vec::<u8>::from_hex(element).unwrap());
+        }
+
+        let mut buffer = Vec::new();
+        FilterLoad::from(filter).encode(&mut buffer).unwrap();
+        assert_eq!(
+            buffer,
+            Vec::<u8>::from_hex("03614e9b050000000000000001").unwrap()
+        );
+    }
+
+    #[test]
+    fn bloom_filter_with_tweak() {
+        let mut filter = BloomFilter::new(3, 0.01, 2_147_483_649, UpdateMode::All);
+
+        for element in [
+            "99108ad8ed9bb6274d3980bab5a85c048f0950c8",
+            "b5a2c786d9ef4658287ced5914b37a1b4aa32eee",
+            "b9300670b4c5366e95b2699e8b18bc75e5f729c5",
+        ] {
+            filter.insert(&Vec::<u8>::from_hex(element).unwrap());
+        }
+
+        let mut buffer = Vec::new();
+        FilterLoad::from(filter).encode(&mut buffer).unwrap();
+        assert_eq!(
+            buffer,
+            Vec::<u8>::from_hex("03ce4299050000000100008001").unwrap()
+        );
+    }
+
+    #[test]
+    fn filter_messages_decode() {
+        use bytes::BytesMut;
+
+        use crate::protocol::{
+            message::{Message, MessageHeader},
+            network::Network,
+        };
+
+        let filter_load = BloomFilter::new(10, 0.001, 7, UpdateMode::P2PubKeyOnly);
+        let messages = [
+            Message::FilterLoad(filter_load.into()),
+            Message::FilterAdd(FilterAdd {
+                data: vec![0xab; 20],
+            }),
+            Message::FilterClear,
+        ];
+
+        for message in messages {
+            let mut buffer = BytesMut::new();
+            message.encode(Network::Testnet, &mut buffer).unwrap();
+
+            let header = MessageHeader::decode(&mut buffer).unwrap();
+            let decoded = Message::decode(header.command, &mut buffer).unwrap();
+            assert_eq!(decoded, message);
+        }
+    }
+
+    #[test]
+    fn reserved_flags_never_update() {
+        use crate::protocol::payload::{
+            script::Script,
+            tx::{TxBuilder, TxOut},
+        };
+
+        let pubkey = [0x02; 33];
+        let tx = TxBuilder::new(4)
+            .with_output(TxOut::new(1_000, Script::p2pk(&pubkey).into()))
+            .build()
+            .unwrap();
+        let mut txid = Vec::new();
+        tx.txid().encode(&mut txid).unwrap();
+
+        for (flags, mode, updated) in [
+            (2, UpdateMode::P2PubKeyOnly, true),
+            (3, UpdateMode::None, false),
+        ] {
+            let mut filter_load = FilterLoad::from(BloomFilter::new(10, 0.001, 0, mode));
+            filter_load.flags = flags;
+            let mut filter = BloomFilter::from(&filter_load);
+            assert_eq!(filter.update_mode(), mode);
+
+            filter.insert(&pubkey);
+            assert!(filter.matches_tx(&tx).unwrap());
+            assert_eq!(filter.contains(&outpoint(&txid, 0)), updated);
+        }
+    }
+
+    #[test]
+    fn pushed_data_skips_opcodes() {
+        // OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+        let mut script = vec![0x76, 0xa9, 0x14];
+        script.extend_from_slice(&[0xab; 20]);
+        script.extend_from_slice(&[0x88, 0xac]);
+
+        let pushes: Vec<&[u8]> = pushed_data(&script).collect();
+        assert_eq!(pushes, vec![&[0xab; 20][..]]);
+
+        // A truncated push ends the iteration.
+        assert_eq!(pushed_data(&[0x4c, 0x05, 0x01]).count(), 0);
+    }
+
     #[test]
     #[ignore]
     fn filter_load_roundtrip() {
@@ -4,10 +4,14 @@ use std::io;
 
 use bytes::{Buf, BufMut};
 
-use crate::protocol::payload::{codec::Codec, read_n_bytes, Hash};
+use crate::protocol::payload::{
+    codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+    read_n_bytes, Hash,
+};
 
 /// An inventory vector.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Inv {
     pub inventory: Vec<InvHash>,
 }
@@ -29,26 +33,57 @@ impl Codec for Inv {
         self.inventory.encode(buffer)
     }
 
+    fn encoded_len(&self) -> usize {
+        self.inventory.encoded_len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         Ok(Self {
-            inventory: Vec::decode(bytes)?,
+            inventory: Vec::decode(bytes).in_field("inventory")?,
         })
     }
 }
 
 /// An inventory hash.
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InvHash {
     /// The object type linked to this inventory.
     kind: ObjectKind,
     /// The hash of the object.
     hash: Hash,
+    /// The transaction's authorizing data commitment, only present for [`ObjectKind::WTx`].
+    auth_digest: Option<Hash>,
 }
 
 impl InvHash {
-    /// Returns a new `InvHash` instance.
+    /// Returns a new `InvHash` instance, see [`InvHash::new_wtx`] for [`ObjectKind::WTx`].
     pub fn new(kind: ObjectKind, hash: Hash) -> Self {
-        Self { kind, hash }
+        Self {
+            kind,
+            hash,
+            auth_digest: None,
+        }
+    }
+
+    /// Returns a new [`ObjectKind::WTx`] `InvHash` from the transaction's id and authorizing data
+    /// commitment, which together form its wtxid ([ZIP-239](https://zips.z.cash/zip-0239)).
+    pub fn new_wtx(txid: Hash, auth_digest: Hash) -> Self {
+        Self {
+            kind: ObjectKind::WTx,
+            hash: txid,
+            auth_digest: Some(auth_digest),
+        }
+    }
+
+    /// Returns the type of the object.
+    pub fn kind(&self) -> ObjectKind {
+        self.kind
+    }
+
+    /// Returns the hash of the object, the txid for [`ObjectKind::WTx`].
+    pub fn hash(&self) -> Hash {
+        self.hash
     }
 }
 
@@ -57,19 +92,40 @@ impl Codec for InvHash {
         self.kind.encode(buffer)?;
         self.hash.encode(buffer)?;
 
+        if let Some(auth_digest) = &self.auth_digest {
+            auth_digest.encode(buffer)?;
+        }
+
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        self.kind.encoded_len()
+            + self.hash.encoded_len()
+            + self.auth_digest.as_ref().map_or(0, Hash::encoded_len)
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let kind = ObjectKind::decode(bytes)?;
-        let hash = Hash::decode(bytes)?;
+        let kind = ObjectKind::decode(bytes).in_field("kind")?;
+        let hash = Hash::decode(bytes).in_field("hash")?;
+
+        let auth_digest = if kind == ObjectKind::WTx {
+            Some(Hash::decode(bytes).in_field("auth_digest")?)
+        } else {
+            None
+        };
 
-        Ok(Self { kind, hash })
+        Ok(Self {
+            kind,
+            hash,
+            auth_digest,
+        })
     }
 }
 
 /// The inventory object kind.
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ObjectKind {
     /// Any data of this kind may be ignored.
     Error,
@@ -79,6 +135,8 @@ pub enum ObjectKind {
     Block,
     /// The hash is that of a block header.
     FilteredBlock,
+    /// The hash is that of a V5 transaction's id, followed by its authorizing data commitment.
+    WTx,
 }
 
 impl Codec for ObjectKind {
@@ -88,6 +146,7 @@ impl Codec for ObjectKind {
             Self::Tx => 1,
             Self::Block => 2,
             Self::FilteredBlock => 3,
+            Self::WTx => 5,
         };
 
         buffer.put_u32_le(value);
@@ -95,6 +154,10 @@ impl Codec for ObjectKind {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        4
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         let value = u32::from_le_bytes(read_n_bytes(bytes)?);
 
@@ -103,11 +166,13 @@ impl Codec for ObjectKind {
             1 => Self::Tx,
             2 => Self::Block,
             3 => Self::FilteredBlock,
-            _ => {
-                return Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "ObjectKind is not known",
-                ))
+            5 => Self::WTx,
+            tag => {
+                let kind = DecodeErrorKind::BadTag {
+                    name: "ObjectKind",
+                    tag: tag.into(),
+                };
+                return Err(DecodeError::new(kind, bytes).into());
             }
         };
 
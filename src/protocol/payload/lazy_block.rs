@@ -0,0 +1,451 @@
+//! A block which is decoded on demand, see [`LazyBlock`].
+
+use std::io;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::protocol::payload::{
+    block::{Block, Header},
+    codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+    inv::{InvHash, ObjectKind},
+    read_n_bytes, Hash, HashWriter, Tx, VarInt,
+};
+
+// The encoded lengths of the fixed size transaction components.
+const JOIN_SPLIT_LEN_WITHOUT_PROOF: usize = 8 + 8 + 32 + 64 + 64 + 32 + 32 + 64 + 1202;
+const BCTV14_PROOF_LEN: usize = 296;
+const GROTH16_PROOF_LEN: usize = 192;
+const JOIN_SPLIT_PUB_KEY_AND_SIG_LEN: usize = 32 + 64;
+const SPEND_DESCRIPTION_V4_LEN: usize = 32 + 32 + 32 + 32 + 192 + 64;
+const OUTPUT_DESCRIPTION_V4_LEN: usize = 32 + 32 + 32 + 580 + 80 + 192;
+const SPEND_DESCRIPTION_V5_LEN: usize = 32 + 32 + 32;
+const OUTPUT_DESCRIPTION_V5_LEN: usize = 32 + 32 + 32 + 580 + 80;
+const ACTION_DESCRIPTION_LEN: usize = 32 + 32 + 32 + 32 + 32 + 580 + 80;
+
+/// A block which keeps its encoding and only decodes the header up front.
+///
+/// The transactions are walked on demand with [`LazyBlock::txs`], as [`TxView`]s borrowing the
+/// block's bytes, so receiving a block costs little more than the frame it arrived in. Use
+/// [`LazyBlock::to_block`] for a fully decoded [`Block`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LazyBlock {
+    bytes: Bytes,
+    header: Header,
+    tx_count: usize,
+    // The offset of the first transaction, after the header and the transaction count.
+    txs_offset: usize,
+}
+
+impl LazyBlock {
+    /// Creates a `LazyBlock` from an encoded block, decoding only its header and transaction
+    /// count. The transactions are only checked once walked.
+    pub fn from_bytes(bytes: Bytes) -> io::Result<Self> {
+        let mut cursor = &bytes[..];
+        let header = Header::decode_without_tx_count(&mut cursor).in_field("header")?;
+        let tx_count = *VarInt::decode(&mut cursor).in_field("txs")?;
+        let txs_offset = bytes.len() - cursor.len();
+
+        Ok(Self {
+            bytes,
+            header,
+            tx_count,
+            txs_offset,
+        })
+    }
+
+    /// Returns the block's encoding.
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    /// Returns the block's header.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the number of transactions the block claims to have.
+    pub fn tx_count(&self) -> usize {
+        self.tx_count
+    }
+
+    /// Returns an iterator walking the block's transactions.
+    ///
+    /// Each transaction's extent is found without decoding it, the iterator yields an error and
+    /// ends on the first one which is malformed.
+    pub fn txs(&self) -> TxViews<'_> {
+        TxViews {
+            bytes: &self.bytes[self.txs_offset..],
+            offset: self.txs_offset,
+            index: 0,
+            count: self.tx_count,
+        }
+    }
+
+    /// Decodes the whole block.
+    pub fn to_block(&self) -> io::Result<Block> {
+        Block::decode(&mut &self.bytes[..])
+    }
+
+    /// Calculates the double Sha256 hash for this block.
+    pub fn double_sha256(&self) -> io::Result<Hash> {
+        self.header.double_sha256()
+    }
+
+    /// Convenience function which creates the [`InvHash`] for this block.
+    pub fn inv_hash(&self) -> InvHash {
+        InvHash::new(ObjectKind::Block, self.double_sha256().unwrap())
+    }
+}
+
+impl From<&Block> for LazyBlock {
+    fn from(block: &Block) -> Self {
+        let mut bytes = BytesMut::with_capacity(block.encoded_len());
+        block.encode(&mut bytes).unwrap();
+
+        Self::from_bytes(bytes.freeze()).unwrap()
+    }
+}
+
+impl Codec for LazyBlock {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_slice(&self.bytes);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Takes all the remaining bytes as the block, which is only a copy if `bytes` isn't already
+    /// backed by [`Bytes`].
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        Self::from_bytes(bytes.copy_to_bytes(bytes.remaining()))
+    }
+}
+
+/// An iterator over the transactions of a [`LazyBlock`], see [`LazyBlock::txs`].
+pub struct TxViews<'a> {
+    bytes: &'a [u8],
+    // The offset of `bytes` within the block, for error reporting.
+    offset: usize,
+    index: usize,
+    count: usize,
+}
+
+impl<'a> Iterator for TxViews<'a> {
+    type Item = io::Result<TxView<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let mut cursor = self.bytes;
+        match skip_tx(&mut cursor) {
+            Ok(()) => {
+                let (tx, rest) = self.bytes.split_at(self.bytes.len() - cursor.len());
+                self.bytes = rest;
+                self.offset += tx.len();
+                self.index += 1;
+
+                Some(Ok(TxView { bytes: tx }))
+            }
+            Err(err) => {
+                let err = DecodeError::from(err)
+                    .at_index(self.index)
+                    .in_field("txs")
+                    .with_input_len(self.offset + self.bytes.len());
+                // There's no telling where the next transaction starts.
+                self.index = self.count;
+
+                Some(Err(err.into()))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.count - self.index))
+    }
+}
+
+/// A transaction borrowed from a [`LazyBlock`], decoded on demand.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TxView<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> TxView<'a> {
+    /// Returns the transaction's encoding.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the transaction version, without the overwintered flag.
+    pub fn version(&self) -> u32 {
+        u32::from_le_bytes(self.bytes[..4].try_into().unwrap()) & !(1 << 31)
+    }
+
+    /// Calculates the double Sha256 hash for this transaction, without decoding it.
+    ///
+    /// This is only the transaction id for V1-V4 transactions, see [`TxView::txid`].
+    pub fn double_sha256(&self) -> Hash {
+        let mut writer = HashWriter::new();
+        writer.put_slice(self.bytes);
+
+        writer.double_sha256()
+    }
+
+    /// Returns the transaction id, which requires decoding V5 transactions, see [`Tx::txid`].
+    pub fn txid(&self) -> io::Result<Hash> {
+        match self.version() {
+            5 => Ok(self.decode()?.txid()),
+            _ => Ok(self.double_sha256()),
+        }
+    }
+
+    /// Decodes the transaction.
+    pub fn decode(&self) -> io::Result<Tx> {
+        Tx::decode(&mut &self.bytes[..])
+    }
+}
+
+/// Advances past the transaction at the start of `bytes`, walking its structure without
+/// decoding it. Mirrors [`Tx::decode`], including its errors.
+fn skip_tx<B: Buf>(bytes: &mut B) -> io::Result<()> {
+    const LOW_31_BITS: u32 = !(1 << 31);
+    let header = u32::from_le_bytes(read_n_bytes(bytes).in_field("header")?);
+
+    match (header & LOW_31_BITS, header >> 31 != 0) {
+        (1, false) => skip_tx_v1(bytes).in_field("V1"),
+        (2, false) => skip_tx_v2_v3(bytes, false).in_field("V2"),
+        (3, true) => skip_tx_v2_v3(bytes, true).in_field("V3"),
+        (4, true) => skip_tx_v4(bytes).in_field("V4"),
+        (5, true) => skip_tx_v5(bytes).in_field("V5"),
+        _ => {
+            let kind = DecodeErrorKind::BadTag {
+                name: "transaction version",
+                tag: header.into(),
+            };
+            Err(DecodeError::new(kind, bytes).in_field("header").into())
+        }
+    }
+}
+
+fn skip_tx_v1<B: Buf>(bytes: &mut B) -> io::Result<()> {
+    skip_transparent(bytes)?;
+    skip(bytes, 4).in_field("lock_time")
+}
+
+fn skip_tx_v2_v3<B: Buf>(bytes: &mut B, overwintered: bool) -> io::Result<()> {
+    if overwintered {
+        skip(bytes, 4).in_field("group_id")?;
+    }
+    skip_transparent(bytes)?;
+    skip(bytes, 4).in_field("lock_time")?;
+    if overwintered {
+        skip(bytes, 4).in_field("expiry_height")?;
+    }
+
+    skip_join_split(bytes, BCTV14_PROOF_LEN)
+}
+
+fn skip_tx_v4<B: Buf>(bytes: &mut B) -> io::Result<()> {
+    skip(bytes, 4).in_field("group_id")?;
+    skip_transparent(bytes)?;
+    skip(bytes, 4).in_field("lock_time")?;
+    skip(bytes, 4).in_field("expiry_height")?;
+    skip(bytes, 8).in_field("value_balance_sapling")?;
+
+    let spends = skip_list(bytes, SPEND_DESCRIPTION_V4_LEN).in_field("spends_sapling")?;
+    let outputs = skip_list(bytes, OUTPUT_DESCRIPTION_V4_LEN).in_field("outputs_sapling")?;
+    skip_join_split(bytes, GROTH16_PROOF_LEN)?;
+
+    if spends + outputs > 0 {
+        skip(bytes, 64).in_field("binding_sig_sapling")?;
+    }
+
+    Ok(())
+}
+
+fn skip_tx_v5<B: Buf>(bytes: &mut B) -> io::Result<()> {
+    skip(bytes, 4).in_field("group_id")?;
+    skip(bytes, 4).in_field("consensus_branch")?;
+    skip(bytes, 4).in_field("lock_time")?;
+    skip(bytes, 4).in_field("expiry_height")?;
+    skip_transparent(bytes)?;
+
+    let spends = skip_list(bytes, SPEND_DESCRIPTION_V5_LEN).in_field("spends_sapling")?;
+    let outputs = skip_list(bytes, OUTPUT_DESCRIPTION_V5_LEN).in_field("outputs_sapling")?;
+    if spends + outputs > 0 {
+        skip(bytes, 8).in_field("value_balance_sapling")?;
+    }
+    if spends > 0 {
+        skip(bytes, 32).in_field("anchor_sapling")?;
+    }
+    skip(bytes, spends * 192).in_field("spend_proofs_sapling")?;
+    skip(bytes, spends * 64).in_field("spend_auth_sigs_sapling")?;
+    skip(bytes, outputs * 192).in_field("output_proofs_sapling")?;
+    if spends + outputs > 0 {
+        skip(bytes, 64).in_field("binding_sig_sapling")?;
+    }
+
+    let actions = skip_list(bytes, ACTION_DESCRIPTION_LEN).in_field("actions_orchard")?;
+    if actions > 0 {
+        skip(bytes, 1).in_field("flags_orchard")?;
+        skip(bytes, 8).in_field("value_balance_orchard")?;
+        skip(bytes, 32).in_field("anchor_orchard")?;
+        let proofs_len = *VarInt::decode(bytes).in_field("proofs_orchard")?;
+        skip(bytes, proofs_len).in_field("proofs_orchard")?;
+        skip(bytes, actions * 64).in_field("auth_sigs_orchard")?;
+        skip(bytes, 64).in_field("binding_sig_orchard")?;
+    }
+
+    Ok(())
+}
+
+/// Skips the transparent inputs and outputs.
+fn skip_transparent<B: Buf>(bytes: &mut B) -> io::Result<()> {
+    let tx_in = *VarInt::decode(bytes).in_field("tx_in")?;
+    for i in 0..tx_in {
+        skip_tx_in(bytes).at_index(i).in_field("tx_in")?;
+    }
+
+    let tx_out = *VarInt::decode(bytes).in_field("tx_out")?;
+    for i in 0..tx_out {
+        skip_tx_out(bytes).at_index(i).in_field("tx_out")?;
+    }
+
+    Ok(())
+}
+
+fn skip_tx_in<B: Buf>(bytes: &mut B) -> io::Result<()> {
+    skip(bytes, 32).in_field("prev_out_hash")?;
+    skip(bytes, 4).in_field("prev_out_index")?;
+    let script_len = *VarInt::decode(bytes).in_field("script_len")?;
+    skip(bytes, script_len).in_field("script")?;
+    skip(bytes, 4).in_field("sequence")
+}
+
+fn skip_tx_out<B: Buf>(bytes: &mut B) -> io::Result<()> {
+    skip(bytes, 8).in_field("value")?;
+    let pk_script_len = *VarInt::decode(bytes).in_field("pk_script_len")?;
+    skip(bytes, pk_script_len).in_field("pk_script")
+}
+
+/// Skips the JoinSplit descriptions, along with the public key and signature which follow them
+/// when there are any.
+fn skip_join_split<B: Buf>(bytes: &mut B, proof_len: usize) -> io::Result<()> {
+    let count =
+        skip_list(bytes, JOIN_SPLIT_LEN_WITHOUT_PROOF + proof_len).in_field("join_split")?;
+    if count > 0 {
+        skip(bytes, JOIN_SPLIT_PUB_KEY_AND_SIG_LEN).in_field("join_split_pub_key")?;
+    }
+
+    Ok(())
+}
+
+/// Skips a list of fixed length elements, returning their number.
+fn skip_list<B: Buf>(bytes: &mut B, element_len: usize) -> io::Result<usize> {
+    let count = *VarInt::decode(bytes)?;
+    skip(bytes, count.saturating_mul(element_len))?;
+
+    Ok(count)
+}
+
+fn skip<B: Buf>(bytes: &mut B, n: usize) -> io::Result<()> {
+    if bytes.remaining() < n {
+        return Err(DecodeError::truncated(n, bytes).into());
+    }
+    bytes.advance(n);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{protocol::payload::tx::TxBuilder, vectors::*};
+
+    fn vector_blocks() -> Vec<Bytes> {
+        [
+            &BLOCK_TESTNET_GENESIS_BYTES[..],
+            &BLOCK_TESTNET_0_207_500_BYTES[..],
+            &BLOCK_TESTNET_0_280_000_BYTES[..],
+            &BLOCK_TESTNET_0_584_000_BYTES[..],
+            &BLOCK_TESTNET_0_903_800_BYTES[..],
+            &BLOCK_TESTNET_1_028_500_BYTES[..],
+            &BLOCK_TESTNET_1_599_200_BYTES[..],
+        ]
+        .into_iter()
+        .map(Bytes::copy_from_slice)
+        .collect()
+    }
+
+    #[test]
+    fn txs_match_the_decoded_block() {
+        for bytes in vector_blocks() {
+            let lazy = LazyBlock::from_bytes(bytes.clone()).unwrap();
+            let block = Block::decode(&mut &bytes[..]).unwrap();
+
+            assert_eq!(lazy.header(), &block.header);
+            assert_eq!(lazy.tx_count(), block.txs.len());
+            assert_eq!(lazy.to_block().unwrap(), block);
+            assert_eq!(LazyBlock::from(&block), lazy);
+
+            let views: Vec<_> = lazy.txs().collect::<io::Result<_>>().unwrap();
+            assert_eq!(views.len(), block.txs.len());
+            for (view, tx) in views.iter().zip(&block.txs) {
+                assert_eq!(view.as_bytes().len(), tx.encoded_len());
+                assert_eq!(view.version(), tx.version());
+                assert_eq!(view.txid().unwrap(), tx.txid());
+                assert_eq!(&view.decode().unwrap(), tx);
+            }
+        }
+    }
+
+    #[test]
+    fn shielded_txs_are_walked() {
+        let mut block = Block::testnet_genesis();
+        block.txs.extend([
+            TxBuilder::new(2).with_join_splits(2).build().unwrap(),
+            TxBuilder::new(3).with_join_splits(1).build().unwrap(),
+            TxBuilder::new(4)
+                .with_join_splits(1)
+                .with_sapling_spends(2)
+                .with_sapling_outputs(1)
+                .build()
+                .unwrap(),
+            TxBuilder::new(5)
+                .with_sapling_spends(1)
+                .with_sapling_outputs(2)
+                .with_orchard_actions(3)
+                .build()
+                .unwrap(),
+        ]);
+        let lazy = LazyBlock::from(&block);
+
+        let views: Vec<_> = lazy.txs().collect::<io::Result<_>>().unwrap();
+        for (view, tx) in views.iter().zip(&block.txs) {
+            assert_eq!(view.as_bytes().len(), tx.encoded_len());
+            assert_eq!(view.version(), tx.version());
+            assert_eq!(view.txid().unwrap(), tx.txid());
+        }
+    }
+
+    #[test]
+    fn truncated_tx_ends_the_walk() {
+        let bytes = vector_blocks().pop().unwrap();
+        let lazy = LazyBlock::from_bytes(bytes.slice(..bytes.len() - 1)).unwrap();
+
+        let mut txs = lazy.txs();
+        let last = lazy.tx_count() - 1;
+        for _ in 0..last {
+            txs.next().unwrap().unwrap();
+        }
+
+        let err = DecodeError::from(txs.next().unwrap().unwrap_err());
+        assert!(err.path().starts_with(&format!("txs[{}]", last)), "{}", err);
+        assert!(txs.next().is_none());
+    }
+}
@@ -0,0 +1,428 @@
+//! Merkle block types, see [BIP 37](https://github.com/bitcoin/bips/blob/master/bip-0037.mediawiki#partial-merkle-branch-format).
+
+use std::io::{self, Error, ErrorKind};
+
+use bytes::{Buf, BufMut};
+use sha2::{Digest, Sha256};
+
+use crate::protocol::payload::{
+    block::{Block, Header},
+    codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+    read_n_bytes, Hash, Tx, VarInt,
+};
+
+/// A filtered block, containing the block header and the partial merkle tree of the matched
+/// transactions.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MerkleBlock {
+    /// The header of the filtered block.
+    pub header: Header,
+    /// The partial merkle tree committing to the matched transactions.
+    pub tree: PartialMerkleTree,
+}
+
+impl MerkleBlock {
+    /// Builds the `MerkleBlock` for the block, matching the transactions with the supplied hashes.
+    pub fn new(block: &Block, matched_txids: &[Hash]) -> Self {
+        Self {
+            header: block.header.clone(),
+            tree: PartialMerkleTree::from_block(block, matched_txids),
+        }
+    }
+
+    /// Checks the partial merkle tree folds back to the header's merkle root and returns the
+    /// matched transaction hashes.
+    pub fn verify(&self) -> io::Result<Vec<Hash>> {
+        let (root, matches) = self.tree.extract_matches()?;
+
+        if root != self.header.merkle_root {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "partial merkle tree root doesn't match the header's merkle root",
+            ));
+        }
+
+        Ok(matches.into_iter().map(|(txid, _)| txid).collect())
+    }
+}
+
+impl Codec for MerkleBlock {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.header.encode_without_tx_count(buffer)?;
+        self.tree.encode(buffer)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.header.encoded_len_without_tx_count() + self.tree.encoded_len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let header = Header::decode_without_tx_count(bytes).in_field("header")?;
+        let tree = PartialMerkleTree::decode(bytes).in_field("tree")?;
+
+        Ok(Self { header, tree })
+    }
+}
+
+/// A partial merkle tree, proving the inclusion of a subset of a block's transactions.
+#[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PartialMerkleTree {
+    /// The number of transactions in the block.
+    pub tx_count: u32,
+    /// The hashes used to rebuild the tree, in depth-first order.
+    pub hashes: Vec<Hash>,
+    /// The flag bits describing the tree traversal, in depth-first order.
+    pub flags: Vec<bool>,
+}
+
+impl PartialMerkleTree {
+    /// Builds the tree for the block, matching the transactions with the supplied hashes.
+    pub fn from_block(block: &Block, matched_txids: &[Hash]) -> Self {
+        let txids: Vec<Hash> = block.txs.iter().map(Tx::txid).collect();
+        let matches: Vec<bool> = txids
+            .iter()
+            .map(|txid| matched_txids.contains(txid))
+            .collect();
+
+        Self::new(&txids, &matches)
+    }
+
+    /// Builds the tree from the list of transaction hashes and whether each of them matched.
+    ///
+    /// # Panics
+    ///
+    /// If `txids` and `matches` differ in length.
+    pub fn new(txids: &[Hash], matches: &[bool]) -> Self {
+        assert_eq!(txids.len(), matches.len());
+
+        let mut tree = Self {
+            tx_count: txids.len() as u32,
+            hashes: Vec::new(),
+            flags: Vec::new(),
+        };
+
+        if txids.is_empty() {
+            return tree;
+        }
+
+        let mut height = 0;
+        while tree.width(height) > 1 {
+            height += 1;
+        }
+
+        tree.build(height, 0, txids, matches);
+
+        tree
+    }
+
+    /// Rebuilds the merkle root from the tree and returns it along with the matched transaction
+    /// hashes and their index in the block.
+    pub fn extract_matches(&self) -> io::Result<(Hash, Vec<(Hash, u32)>)> {
+        let invalid = |reason: &str| Error::new(ErrorKind::InvalidData, reason.to_string());
+
+        if self.tx_count == 0 {
+            return Err(invalid("partial merkle tree has no transactions"));
+        }
+
+        if self.hashes.len() > self.tx_count as usize {
+            return Err(invalid(
+                "partial merkle tree has more hashes than transactions",
+            ));
+        }
+
+        if self.flags.len() < self.hashes.len() {
+            return Err(invalid("partial merkle tree has fewer flags than hashes"));
+        }
+
+        let mut height = 0;
+        while self.width(height) > 1 {
+            height += 1;
+        }
+
+        let mut traversal = Traversal::default();
+        let root = self.extract(height, 0, &mut traversal)?;
+
+        // All flag bits (up to byte padding) and hashes must be consumed.
+        if traversal.flags_used.div_ceil(8) != self.flags.len().div_ceil(8) {
+            return Err(invalid("partial merkle tree has unused flag bits"));
+        }
+
+        if traversal.hashes_used != self.hashes.len() {
+            return Err(invalid("partial merkle tree has unused hashes"));
+        }
+
+        Ok((root, traversal.matches))
+    }
+
+    /// Returns the number of nodes at the given height.
+    fn width(&self, height: u32) -> u32 {
+        ((self.tx_count as u64 + (1 << height) - 1) >> height) as u32
+    }
+
+    /// Computes the hash of the node at the given height and position.
+    fn node_hash(&self, height: u32, pos: u32, txids: &[Hash]) -> Hash {
+        if height == 0 {
+            return txids[pos as usize];
+        }
+
+        let left = self.node_hash(height - 1, pos * 2, txids);
+        let right = if pos * 2 + 1 < self.width(height - 1) {
+            self.node_hash(height - 1, pos * 2 + 1, txids)
+        } else {
+            left
+        };
+
+        hash_pair(&left, &right)
+    }
+
+    fn build(&mut self, height: u32, pos: u32, txids: &[Hash], matches: &[bool]) {
+        // Whether this node is the parent of at least one matched transaction.
+        let start = (pos << height) as usize;
+        let end = (((pos + 1) << height) as usize).min(txids.len());
+        let is_parent_of_match = matches[start..end].iter().any(|is_match| *is_match);
+
+        self.flags.push(is_parent_of_match);
+
+        if height == 0 || !is_parent_of_match {
+            self.hashes.push(self.node_hash(height, pos, txids));
+        } else {
+            self.build(height - 1, pos * 2, txids, matches);
+            if pos * 2 + 1 < self.width(height - 1) {
+                self.build(height - 1, pos * 2 + 1, txids, matches);
+            }
+        }
+    }
+
+    fn extract(&self, height: u32, pos: u32, traversal: &mut Traversal) -> io::Result<Hash> {
+        let overflow = || Error::new(ErrorKind::InvalidData, "partial merkle tree overflowed");
+
+        let is_parent_of_match = *self.flags.get(traversal.flags_used).ok_or_else(overflow)?;
+        traversal.flags_used += 1;
+
+        if height == 0 || !is_parent_of_match {
+            let hash = *self
+                .hashes
+                .get(traversal.hashes_used)
+                .ok_or_else(overflow)?;
+            traversal.hashes_used += 1;
+
+            if height == 0 && is_parent_of_match {
+                traversal.matches.push((hash, pos));
+            }
+
+            return Ok(hash);
+        }
+
+        let left = self.extract(height - 1, pos * 2, traversal)?;
+        let right = if pos * 2 + 1 < self.width(height - 1) {
+            let right = self.extract(height - 1, pos * 2 + 1, traversal)?;
+
+            // Identical siblings allow for the CVE-2012-2459 mutation and are rejected.
+            if right == left {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "partial merkle tree contains identical sibling hashes",
+                ));
+            }
+
+            right
+        } else {
+            left
+        };
+
+        Ok(hash_pair(&left, &right))
+    }
+}
+
+impl Codec for PartialMerkleTree {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_u32_le(self.tx_count);
+        self.hashes.encode(buffer)?;
+
+        // Flags are packed into bytes, least significant bit first.
+        let mut flag_bytes = vec![0u8; self.flags.len().div_ceil(8)];
+        for (i, flag) in self.flags.iter().enumerate() {
+            flag_bytes[i / 8] |= (*flag as u8) << (i % 8);
+        }
+
+        VarInt::new(flag_bytes.len()).encode(buffer)?;
+        buffer.put_slice(&flag_bytes);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        let flag_bytes_len = self.flags.len().div_ceil(8);
+
+        4 + self.hashes.encoded_len() + VarInt::new(flag_bytes_len).encoded_len() + flag_bytes_len
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let tx_count = u32::from_le_bytes(read_n_bytes(bytes).in_field("tx_count")?);
+
+        // A tree never holds more hashes than transactions, nor more flags than nodes.
+        let hashes_len = *VarInt::decode(bytes).in_field("hashes")?;
+        check_len("hashes", hashes_len, tx_count as usize, bytes)?;
+        let hashes = (0..hashes_len)
+            .map(|i| Hash::decode(bytes).at_index(i))
+            .collect::<io::Result<_>>()
+            .in_field("hashes")?;
+
+        let flag_bytes_len = *VarInt::decode(bytes).in_field("flags")?;
+        let max_flag_bytes = (2 * tx_count as usize + 1).div_ceil(8);
+        check_len("flags", flag_bytes_len, max_flag_bytes, bytes)?;
+        if bytes.remaining() < flag_bytes_len {
+            return Err(DecodeError::truncated(flag_bytes_len, bytes)
+                .in_field("flags")
+                .into());
+        }
+
+        let mut flag_bytes = vec![0u8; flag_bytes_len];
+        bytes.copy_to_slice(&mut flag_bytes);
+
+        let flags = (0..flag_bytes_len * 8)
+            .map(|i| flag_bytes[i / 8] & (1 << (i % 8)) != 0)
+            .collect();
+
+        Ok(Self {
+            tx_count,
+            hashes,
+            flags,
+        })
+    }
+}
+
+/// Rejects a length prefix of the `field` over the `limit` the tree allows.
+fn check_len<B: Buf>(
+    field: &'static str,
+    length: usize,
+    limit: usize,
+    bytes: &B,
+) -> io::Result<()> {
+    if length > limit {
+        let kind = DecodeErrorKind::LengthOverLimit { length, limit };
+        return Err(DecodeError::new(kind, bytes).in_field(field).into());
+    }
+
+    Ok(())
+}
+
+/// Tracks the progress of a partial merkle tree traversal.
+#[derive(Default)]
+struct Traversal {
+    flags_used: usize,
+    hashes_used: usize,
+    matches: Vec<(Hash, u32)>,
+}
+
+/// Computes the parent node hash of two merkle tree nodes.
+pub(super) fn hash_pair(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(left.0);
+    hasher.update(right.0);
+
+    Hash::new(Sha256::digest(hasher.finalize()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn txids(n: u8) -> Vec<Hash> {
+        (0..n).map(|i| Hash::new([i; 32])).collect()
+    }
+
+    #[test]
+    fn partial_merkle_tree_round_trip() {
+        let txids = txids(7);
+        let matches = [false, true, false, false, true, true, false];
+        let tree = PartialMerkleTree::new(&txids, &matches);
+
+        let mut buffer = Vec::new();
+        tree.encode(&mut buffer).unwrap();
+        let decoded = PartialMerkleTree::decode(&mut Cursor::new(&buffer)).unwrap();
+
+        // Decoding pads the flags up to a multiple of 8 bits.
+        assert_eq!(decoded.tx_count, tree.tx_count);
+        assert_eq!(decoded.hashes, tree.hashes);
+        assert_eq!(decoded.flags[..tree.flags.len()], tree.flags);
+
+        let (_, matched) = decoded.extract_matches().unwrap();
+        assert_eq!(matched, vec![(txids[1], 1), (txids[4], 4), (txids[5], 5)]);
+    }
+
+    #[test]
+    fn partial_merkle_tree_root_is_independent_of_matches() {
+        let txids = txids(11);
+        let all = PartialMerkleTree::new(&txids, &[true; 11]);
+        let none = PartialMerkleTree::new(&txids, &[false; 11]);
+
+        assert_eq!(none.hashes.len(), 1);
+        assert_eq!(
+            all.extract_matches().unwrap().0,
+            none.extract_matches().unwrap().0
+        );
+    }
+
+    #[test]
+    fn partial_merkle_tree_rejects_unused_hashes() {
+        let mut tree = PartialMerkleTree::new(&txids(4), &[true, false, false, false]);
+        tree.hashes.push(Hash::zeroed());
+
+        assert!(tree.extract_matches().is_err());
+    }
+
+    #[test]
+    fn partial_merkle_tree_rejects_oversized_vectors() {
+        // More hashes than transactions.
+        let mut tree = PartialMerkleTree::new(&txids(2), &[true, true]);
+        tree.hashes.extend(txids(1));
+        let mut buffer = Vec::new();
+        tree.encode(&mut buffer).unwrap();
+        assert!(PartialMerkleTree::decode(&mut Cursor::new(&buffer)).is_err());
+
+        // More flag bytes than a two transaction tree has nodes, declared but never sent.
+        let mut buffer = vec![2, 0, 0, 0, 0];
+        VarInt::new(1000).encode(&mut buffer).unwrap();
+        let err = PartialMerkleTree::decode(&mut Cursor::new(&buffer)).unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit of 1"));
+    }
+
+    #[test]
+    fn merkle_block_verifies_against_header() {
+        for block in Block::initial_testnet_blocks() {
+            let txid = block.txs[0].double_sha256().unwrap();
+            let merkle_block = MerkleBlock::new(&block, &[txid]);
+
+            assert_eq!(merkle_block.verify().unwrap(), vec![txid]);
+        }
+    }
+
+    #[test]
+    fn merkle_block_with_multiple_txs() {
+        // Assemble a block out of the coinbase transactions of the initial testnet blocks.
+        let blocks = Block::initial_testnet_blocks();
+        let mut block = blocks[0].clone();
+        block.txs = blocks.iter().map(|block| block.txs[0].clone()).collect();
+
+        block.update_merkle_root();
+
+        let txids: Vec<Hash> = block.txs.iter().map(Tx::txid).collect();
+        let (root, _) = PartialMerkleTree::new(&txids, &[false; 11])
+            .extract_matches()
+            .unwrap();
+        assert_eq!(root, block.header.merkle_root);
+
+        let matched = [txids[3], txids[10]];
+        let merkle_block = MerkleBlock::new(&block, &matched);
+        assert_eq!(merkle_block.verify().unwrap(), matched);
+
+        let mut tampered = merkle_block;
+        tampered.header.merkle_root = Hash::zeroed();
+        assert!(tampered.verify().is_err());
+    }
+}
@@ -2,39 +2,63 @@
 
 use std::io;
 
-use bytes::{Buf, BufMut};
+use bytes::{buf::UninitSlice, Buf, BufMut};
 use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
 use time::OffsetDateTime;
 
 pub mod addr;
-pub use addr::Addr;
+pub use addr::{Addr, AddrV2};
 
 pub mod block;
 
 pub mod inv;
 pub use inv::Inv;
 
+pub mod lazy_block;
+pub use lazy_block::LazyBlock;
+
+pub mod merkle;
+pub use merkle::MerkleBlock;
+
+pub mod script;
+pub use script::Script;
+
+pub mod services;
+pub use services::Services;
+
 pub mod tx;
 pub use tx::Tx;
 
 pub mod version;
-pub use version::Version;
+pub use version::{Version, VersionBuilder};
 
 pub mod reject;
 pub use reject::Reject;
 
-use self::codec::Codec;
+use self::codec::{Codec, DecodeError, DecodeErrorKind};
 use crate::protocol::message::constants::{MAX_MESSAGE_LEN, PROTOCOL_VERSION};
 
 pub mod codec;
 
+#[cfg(feature = "serde")]
+pub(crate) mod serialization;
+
 pub mod filter;
 pub use filter::{FilterAdd, FilterLoad};
 
 /// A `u64`-backed nonce.
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Nonce(u64);
 
+impl Nonce {
+    /// Creates a `Nonce` with a fixed value, instead of the random one given by `default`.
+    pub fn new(nonce: u64) -> Self {
+        Self(nonce)
+    }
+}
+
 impl Default for Nonce {
     fn default() -> Self {
         Self(thread_rng().gen())
@@ -48,11 +72,12 @@ impl Codec for Nonce {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        8
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        if bytes.remaining() < 8 {
-            return Err(io::ErrorKind::InvalidData.into());
-        }
-        let nonce = bytes.get_u64_le();
+        let nonce = u64::from_le_bytes(read_n_bytes(bytes)?);
 
         Ok(Self(nonce))
     }
@@ -60,6 +85,7 @@ impl Codec for Nonce {
 
 /// Specifies the protocol version.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ProtocolVersion(pub u32);
 
 impl ProtocolVersion {
@@ -76,6 +102,10 @@ impl Codec for ProtocolVersion {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        4
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         let version = u32::from_le_bytes(read_n_bytes(bytes)?);
 
@@ -85,6 +115,7 @@ impl Codec for ProtocolVersion {
 
 /// A variable length integer.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VarInt(usize);
 
 impl VarInt {
@@ -126,6 +157,15 @@ impl Codec for VarInt {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        match self.0 {
+            0x0000_0000..=0x0000_00fc => 1,
+            0x0000_00fd..=0x0000_ffff => 3,
+            0x0001_0000..=0xffff_ffff => 5,
+            _ => 9,
+        }
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         let flag = u8::from_le_bytes(read_n_bytes(bytes)?);
 
@@ -137,13 +177,7 @@ impl Codec for VarInt {
         };
 
         if len > MAX_MESSAGE_LEN as u64 {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "VarInt length of {} exceeds max message length of {}",
-                    len, MAX_MESSAGE_LEN
-                ),
-            ));
+            return Err(DecodeError::new(DecodeErrorKind::BadVarInt { value: len }, bytes).into());
         }
 
         Ok(VarInt(len as usize))
@@ -152,6 +186,7 @@ impl Codec for VarInt {
 
 /// A variable length string.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct VarStr(pub String);
 
 impl VarStr {
@@ -162,34 +197,28 @@ impl VarStr {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        VarInt(self.0.len()).encoded_len() + self.0.len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         let str_len = VarInt::decode(bytes)?;
 
-        if *str_len > MAX_MESSAGE_LEN {
-            return Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!(
-                    "VarStr length of {} exceeds max message length of {}",
-                    *str_len, MAX_MESSAGE_LEN
-                ),
-            ));
-        }
-
         if bytes.remaining() < str_len.0 {
-            return Err(io::ErrorKind::InvalidData.into());
+            return Err(DecodeError::truncated(str_len.0, bytes).into());
         }
 
         let mut buffer = vec![0u8; str_len.0];
         bytes.copy_to_slice(&mut buffer);
 
         Ok(VarStr(String::from_utf8(buffer).map_err(|err| {
-            std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string())
+            DecodeError::new(DecodeErrorKind::Invalid(err.to_string()), bytes)
         })?))
     }
 }
 
 /// A general purpose hash of length `32`.
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub struct Hash([u8; 32]);
 
 impl Hash {
@@ -211,9 +240,13 @@ impl Codec for Hash {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        self.0.len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         if bytes.remaining() < 32 {
-            return Err(io::ErrorKind::InvalidData.into());
+            return Err(DecodeError::truncated(32, bytes).into());
         }
 
         let mut hash = Hash([0u8; 32]);
@@ -223,10 +256,83 @@ impl Codec for Hash {
     }
 }
 
+/// A [`BufMut`] which feeds everything written to it into a SHA-256 hasher, so values can be
+/// hashed straight from their [`Codec::encode`] without an intermediate buffer.
+pub struct HashWriter {
+    hasher: Sha256,
+    // Small writes are collected here, to avoid updating the hasher a few bytes at a time.
+    buffer: [u8; 64],
+    len: usize,
+}
+
+impl HashWriter {
+    /// Creates a `HashWriter` which hasn't hashed anything yet.
+    pub fn new() -> Self {
+        Self {
+            hasher: Sha256::new(),
+            buffer: [0; 64],
+            len: 0,
+        }
+    }
+
+    /// Returns the double SHA-256 hash of everything written so far.
+    pub fn double_sha256(mut self) -> Hash {
+        self.flush();
+        let sha2 = self.hasher.finalize();
+
+        Hash(Sha256::digest(sha2).into())
+    }
+
+    fn flush(&mut self) {
+        self.hasher.update(&self.buffer[..self.len]);
+        self.len = 0;
+    }
+}
+
+impl Default for HashWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// SAFETY: `chunk_mut` hands out the unused part of the initialized `buffer`, so advancing over
+// any part of it never exposes uninitialized memory.
+unsafe impl BufMut for HashWriter {
+    fn remaining_mut(&self) -> usize {
+        usize::MAX
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(
+            self.len + cnt <= self.buffer.len(),
+            "advanced past the chunk"
+        );
+        self.len += cnt;
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        if self.len == self.buffer.len() {
+            self.flush();
+        }
+
+        UninitSlice::new(&mut self.buffer[self.len..])
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        if self.len + src.len() <= self.buffer.len() {
+            self.buffer[self.len..self.len + src.len()].copy_from_slice(src);
+            self.len += src.len();
+        } else {
+            self.flush();
+            self.hasher.update(src);
+        }
+    }
+}
+
 /// Reads `n` bytes from the bytes.
 pub fn read_n_bytes<const N: usize, B: Buf>(bytes: &mut B) -> io::Result<[u8; N]> {
     if bytes.remaining() < N {
-        return Err(io::ErrorKind::InvalidData.into());
+        return Err(DecodeError::truncated(N, bytes).into());
     }
 
     let mut buffer = [0u8; N];
@@ -238,13 +344,19 @@ pub fn read_n_bytes<const N: usize, B: Buf>(bytes: &mut B) -> io::Result<[u8; N]
 /// Reads a timestamp encoded as 8 bytes.
 pub fn read_timestamp<B: Buf>(bytes: &mut B) -> io::Result<OffsetDateTime> {
     let timestamp_i64 = i64::from_le_bytes(read_n_bytes(bytes)?);
-    OffsetDateTime::from_unix_timestamp(timestamp_i64)
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bad UTC timestamp"))
+    OffsetDateTime::from_unix_timestamp(timestamp_i64).map_err(|_| bad_timestamp(bytes).into())
 }
 
 /// Reads a timestamp encoded as 4 bytes.
 pub fn read_short_timestamp<B: Buf>(bytes: &mut B) -> io::Result<OffsetDateTime> {
     let timestamp_u32 = u32::from_le_bytes(read_n_bytes(bytes)?);
     OffsetDateTime::from_unix_timestamp(timestamp_u32.into())
-        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Bad UTC timestamp"))
+        .map_err(|_| bad_timestamp(bytes).into())
+}
+
+fn bad_timestamp<B: Buf>(bytes: &B) -> DecodeError {
+    DecodeError::new(
+        DecodeErrorKind::Invalid("Bad UTC timestamp".to_string()),
+        bytes,
+    )
 }
@@ -4,10 +4,17 @@ use std::io::{self, Read};
 
 use bytes::{Buf, BufMut};
 
-use crate::protocol::payload::{codec::Codec, VarStr};
+use crate::protocol::payload::{
+    codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+    VarStr,
+};
+
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::hex_bytes;
 
 /// A reject message payload.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Reject {
     /// The type of message rejected.
     pub message: VarStr,
@@ -21,6 +28,7 @@ pub struct Reject {
     /// so the field is 32 bytes.
     ///
     /// We support any length data to fully adhere to the spec.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
     pub data: Vec<u8>,
 }
 
@@ -34,10 +42,17 @@ impl Codec for Reject {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        self.message.encoded_len()
+            + self.ccode.encoded_len()
+            + self.reason.encoded_len()
+            + self.data.len()
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let message = VarStr::decode(bytes)?;
-        let ccode = CCode::decode(bytes)?;
-        let reason = VarStr::decode(bytes)?;
+        let message = VarStr::decode(bytes).in_field("message")?;
+        let ccode = CCode::decode(bytes).in_field("ccode")?;
+        let reason = VarStr::decode(bytes).in_field("reason")?;
 
         // Current usage of the data field is `Option<[u8; 32]>`,
         // but the spec allows for any length [u8], so we support that case.
@@ -65,6 +80,7 @@ const OTHER_CODE: u8 = 0x50;
 
 /// The code specifying the reject reason.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CCode {
     Malformed,
     Invalid,
@@ -96,9 +112,13 @@ impl Codec for CCode {
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        1
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
         if bytes.remaining() == 0 {
-            return Err(io::ErrorKind::InvalidData.into());
+            return Err(DecodeError::truncated(1, bytes).into());
         }
 
         match bytes.get_u8() {
@@ -111,10 +131,13 @@ impl Codec for CCode {
             INSUFFICIENT_FEE_CODE => Ok(Self::InsufficientFee),
             CHECKPOINT_CODE => Ok(Self::Checkpoint),
             OTHER_CODE => Ok(Self::Other),
-            b => Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("Invalid CCode {:#x}", b),
-            )),
+            b => {
+                let kind = DecodeErrorKind::BadTag {
+                    name: "CCode",
+                    tag: b.into(),
+                };
+                Err(DecodeError::new(kind, bytes).into())
+            }
         }
     }
 }
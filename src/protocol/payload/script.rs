@@ -0,0 +1,460 @@
+//! Transparent scripts, see the [Bitcoin wiki](https://en.bitcoin.it/wiki/Script).
+
+use std::fmt;
+
+use bytes::BufMut;
+
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::hex_bytes;
+
+/// Script opcodes.
+pub mod opcodes {
+    pub const OP_0: u8 = 0x00;
+    pub const OP_PUSHDATA1: u8 = 0x4c;
+    pub const OP_PUSHDATA2: u8 = 0x4d;
+    pub const OP_PUSHDATA4: u8 = 0x4e;
+    pub const OP_1NEGATE: u8 = 0x4f;
+    pub const OP_1: u8 = 0x51;
+    pub const OP_16: u8 = 0x60;
+    pub const OP_RETURN: u8 = 0x6a;
+    pub const OP_DUP: u8 = 0x76;
+    pub const OP_EQUAL: u8 = 0x87;
+    pub const OP_EQUALVERIFY: u8 = 0x88;
+    pub const OP_HASH160: u8 = 0xa9;
+    pub const OP_CHECKSIG: u8 = 0xac;
+    pub const OP_CHECKMULTISIG: u8 = 0xae;
+}
+
+use opcodes::*;
+
+/// A transparent script.
+///
+/// The raw bytes are kept as is, so malformed scripts can be built and inspected, see
+/// [`Script::push_raw`] and [`Script::instructions`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Script(#[cfg_attr(feature = "serde", serde(with = "hex_bytes"))] pub Vec<u8>);
+
+impl Script {
+    /// Returns an empty script.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns `OP_DUP OP_HASH160 <pubkey_hash> OP_EQUALVERIFY OP_CHECKSIG`.
+    pub fn p2pkh(pubkey_hash: [u8; 20]) -> Self {
+        Self::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_data(&pubkey_hash)
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+    }
+
+    /// Returns `OP_HASH160 <script_hash> OP_EQUAL`.
+    pub fn p2sh(script_hash: [u8; 20]) -> Self {
+        Self::new()
+            .push_opcode(OP_HASH160)
+            .push_data(&script_hash)
+            .push_opcode(OP_EQUAL)
+    }
+
+    /// Returns `<pubkey> OP_CHECKSIG`.
+    pub fn p2pk(pubkey: &[u8]) -> Self {
+        Self::new().push_data(pubkey).push_opcode(OP_CHECKSIG)
+    }
+
+    /// Returns `OP_RETURN <data>`.
+    pub fn null_data(data: &[u8]) -> Self {
+        Self::new().push_opcode(OP_RETURN).push_data(data)
+    }
+
+    /// Returns `<required> <pubkey>... <keys.len()> OP_CHECKMULTISIG`.
+    ///
+    /// Counts above 16 aren't small integers, which makes the script non-standard.
+    pub fn multisig(required: usize, pubkeys: &[&[u8]]) -> Self {
+        let mut script = Self::new().push_int(required as i64);
+        for pubkey in pubkeys {
+            script = script.push_data(pubkey);
+        }
+
+        script
+            .push_int(pubkeys.len() as i64)
+            .push_opcode(OP_CHECKMULTISIG)
+    }
+
+    /// Appends the opcode.
+    pub fn push_opcode(mut self, opcode: u8) -> Self {
+        self.0.push(opcode);
+        self
+    }
+
+    /// Appends the data with the shortest push opcode for its length.
+    pub fn push_data(mut self, data: &[u8]) -> Self {
+        match data.len() {
+            len @ 0..=0x4b => self.0.push(len as u8),
+            len @ 0x4c..=0xff => {
+                self.0.push(OP_PUSHDATA1);
+                self.0.push(len as u8);
+            }
+            len @ 0x100..=0xffff => {
+                self.0.push(OP_PUSHDATA2);
+                self.0.put_u16_le(len as u16);
+            }
+            len => {
+                self.0.push(OP_PUSHDATA4);
+                self.0.put_u32_le(len as u32);
+            }
+        }
+        self.0.extend_from_slice(data);
+        self
+    }
+
+    /// Appends the integer, as a small integer opcode if possible and otherwise as pushed
+    /// little-endian sign-magnitude bytes.
+    pub fn push_int(self, value: i64) -> Self {
+        match value {
+            0 => self.push_opcode(OP_0),
+            -1 => self.push_opcode(OP_1NEGATE),
+            1..=16 => self.push_opcode(OP_1 + value as u8 - 1),
+            _ => {
+                let mut bytes = Vec::new();
+                let mut magnitude = value.unsigned_abs();
+                while magnitude > 0 {
+                    bytes.push(magnitude as u8);
+                    magnitude >>= 8;
+                }
+
+                // The top bit is the sign, add a byte if the magnitude already uses it.
+                let sign = if value < 0 { 0x80 } else { 0 };
+                if bytes.last().unwrap() & 0x80 != 0 {
+                    bytes.push(sign);
+                } else {
+                    *bytes.last_mut().unwrap() |= sign;
+                }
+
+                self.push_data(&bytes)
+            }
+        }
+    }
+
+    /// Appends raw bytes, e.g. to build truncated pushes.
+    pub fn push_raw(mut self, bytes: &[u8]) -> Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    /// Returns an iterator over the script's instructions.
+    pub fn instructions(&self) -> Instructions<'_> {
+        Instructions::new(&self.0)
+    }
+
+    /// Classifies the script by its standard template.
+    pub fn kind(&self) -> ScriptKind {
+        ScriptKind::classify(&self.0)
+    }
+}
+
+impl From<Vec<u8>> for Script {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Script> for Vec<u8> {
+    fn from(script: Script) -> Self {
+        script.0
+    }
+}
+
+impl AsRef<[u8]> for Script {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// A parsed script instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction<'a> {
+    /// Data pushed by `OP_0`, a direct push or one of the `OP_PUSHDATA` opcodes.
+    Push(&'a [u8]),
+    /// Any other opcode, including the small integers.
+    Op(u8),
+}
+
+/// A push whose length exceeds the rest of the script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncatedPush {
+    /// The offset of the push opcode.
+    pub offset: usize,
+}
+
+impl fmt::Display for TruncatedPush {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "truncated push at script offset {}", self.offset)
+    }
+}
+
+impl std::error::Error for TruncatedPush {}
+
+/// An iterator over a script's instructions, ending after the first [`TruncatedPush`].
+#[derive(Debug, Clone)]
+pub struct Instructions<'a> {
+    script: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Instructions<'a> {
+    /// Creates an iterator over the instructions of the raw script.
+    pub fn new(script: &'a [u8]) -> Self {
+        Self { script, offset: 0 }
+    }
+
+    fn read_len(&mut self, width: usize) -> Option<usize> {
+        let bytes = self.script.get(self.offset..self.offset + width)?;
+        self.offset += width;
+
+        let mut len = [0u8; 8];
+        len[..width].copy_from_slice(bytes);
+        Some(u64::from_le_bytes(len) as usize)
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, TruncatedPush>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        let opcode = *self.script.get(start)?;
+        self.offset += 1;
+
+        let len = match opcode {
+            0x00..=0x4b => Some(opcode as usize),
+            OP_PUSHDATA1 => self.read_len(1),
+            OP_PUSHDATA2 => self.read_len(2),
+            OP_PUSHDATA4 => self.read_len(4),
+            _ => return Some(Ok(Instruction::Op(opcode))),
+        };
+
+        match len.and_then(|len| self.script.get(self.offset..self.offset.checked_add(len)?)) {
+            Some(data) => {
+                self.offset += data.len();
+                Some(Ok(Instruction::Push(data)))
+            }
+            None => {
+                self.offset = self.script.len();
+                Some(Err(TruncatedPush { offset: start }))
+            }
+        }
+    }
+}
+
+/// The standard template a script matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptKind {
+    /// `OP_DUP OP_HASH160 <pubkey_hash> OP_EQUALVERIFY OP_CHECKSIG`.
+    PayToPubKeyHash([u8; 20]),
+    /// `OP_HASH160 <script_hash> OP_EQUAL`.
+    PayToScriptHash([u8; 20]),
+    /// `<pubkey> OP_CHECKSIG`.
+    PayToPubKey(Vec<u8>),
+    /// `<required> <pubkey>... <n> OP_CHECKMULTISIG`, with `1 <= required <= n <= 16`.
+    Multisig {
+        required: usize,
+        pubkeys: Vec<Vec<u8>>,
+    },
+    /// `OP_RETURN` followed only by push opcodes, up to and including `OP_16` like `zcashd`'s
+    /// `IsPushOnly`, with the data they push. `OP_1NEGATE` pushes `0x81`, `OP_1` to `OP_16` their
+    /// value and the reserved `0x50` nothing.
+    NullData(Vec<Vec<u8>>),
+    /// Any other script, including malformed ones.
+    NonStandard,
+}
+
+impl ScriptKind {
+    /// Classifies the raw script by its standard template.
+    pub fn classify(script: &[u8]) -> Self {
+        let instructions: Vec<Instruction> = match Instructions::new(script).collect() {
+            Ok(instructions) => instructions,
+            Err(_) => return Self::NonStandard,
+        };
+
+        use Instruction::*;
+        match instructions.as_slice() {
+            [Op(OP_DUP), Op(OP_HASH160), Push(hash), Op(OP_EQUALVERIFY), Op(OP_CHECKSIG)]
+                if hash.len() == 20 =>
+            {
+                Self::PayToPubKeyHash((*hash).try_into().unwrap())
+            }
+            [Op(OP_HASH160), Push(hash), Op(OP_EQUAL)] if hash.len() == 20 => {
+                Self::PayToScriptHash((*hash).try_into().unwrap())
+            }
+            [Push(pubkey), Op(OP_CHECKSIG)] if is_pubkey(pubkey) => {
+                Self::PayToPubKey(pubkey.to_vec())
+            }
+            [Op(OP_RETURN), rest @ ..]
+                if rest
+                    .iter()
+                    .all(|i| matches!(i, Push(_)) || matches!(i, Op(op) if *op <= OP_16)) =>
+            {
+                Self::NullData(
+                    rest.iter()
+                        .filter_map(|i| match i {
+                            Push(data) => Some(data.to_vec()),
+                            Op(OP_1NEGATE) => Some(vec![0x81]),
+                            Op(op) => small_int(*op).map(|n| vec![n as u8]),
+                        })
+                        .collect(),
+                )
+            }
+            [Op(m), pubkeys @ .., Op(n), Op(OP_CHECKMULTISIG)] => {
+                let (Some(required), Some(n)) = (small_int(*m), small_int(*n)) else {
+                    return Self::NonStandard;
+                };
+                let pubkeys: Vec<Vec<u8>> = pubkeys
+                    .iter()
+                    .map_while(|i| match i {
+                        Push(pubkey) if is_pubkey(pubkey) => Some(pubkey.to_vec()),
+                        _ => None,
+                    })
+                    .collect();
+
+                if required >= 1 && required <= n && n == pubkeys.len() {
+                    Self::Multisig { required, pubkeys }
+                } else {
+                    Self::NonStandard
+                }
+            }
+            _ => Self::NonStandard,
+        }
+    }
+}
+
+/// Returns the value of `OP_1` to `OP_16`.
+fn small_int(opcode: u8) -> Option<usize> {
+    (OP_1..=OP_16)
+        .contains(&opcode)
+        .then(|| (opcode - OP_1 + 1) as usize)
+}
+
+/// Returns `true` if the length matches a compressed or uncompressed public key.
+fn is_pubkey(data: &[u8]) -> bool {
+    data.len() == 33 || data.len() == 65
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_round_trip() {
+        let key = [2u8; 33];
+        let long_key = [4u8; 65];
+
+        assert_eq!(
+            Script::p2pkh([1; 20]).kind(),
+            ScriptKind::PayToPubKeyHash([1; 20])
+        );
+        assert_eq!(
+            Script::p2sh([3; 20]).kind(),
+            ScriptKind::PayToScriptHash([3; 20])
+        );
+        assert_eq!(
+            Script::p2pk(&key).kind(),
+            ScriptKind::PayToPubKey(key.to_vec())
+        );
+        assert_eq!(
+            Script::null_data(b"ziggurat").kind(),
+            ScriptKind::NullData(vec![b"ziggurat".to_vec()])
+        );
+        assert_eq!(
+            Script::multisig(1, &[&key, &long_key]).kind(),
+            ScriptKind::Multisig {
+                required: 1,
+                pubkeys: vec![key.to_vec(), long_key.to_vec()]
+            }
+        );
+
+        // The well known P2PKH layout.
+        let mut expected = vec![0x76, 0xa9, 0x14];
+        expected.extend_from_slice(&[1; 20]);
+        expected.extend_from_slice(&[0x88, 0xac]);
+        assert_eq!(Script::p2pkh([1; 20]).0, expected);
+    }
+
+    #[test]
+    fn non_standard_scripts() {
+        let key = [2u8; 33];
+
+        // More signatures required than keys.
+        assert_eq!(Script::multisig(2, &[&key]).kind(), ScriptKind::NonStandard);
+        // A count above 16 isn't a small integer.
+        assert_eq!(
+            Script::multisig(17, &[&key[..]; 17]).kind(),
+            ScriptKind::NonStandard
+        );
+        // A hash of the wrong length.
+        assert_eq!(
+            Script::new()
+                .push_opcode(OP_HASH160)
+                .push_data(&[0; 19])
+                .push_opcode(OP_EQUAL)
+                .kind(),
+            ScriptKind::NonStandard
+        );
+        // A truncated push.
+        let truncated = Script::p2pkh([1; 20]).push_raw(&[OP_PUSHDATA1, 5, 0]);
+        assert_eq!(truncated.kind(), ScriptKind::NonStandard);
+    }
+
+    #[test]
+    fn null_data_is_push_only() {
+        // zcashd relays small integers after `OP_RETURN` too.
+        let script = Script::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(OP_1NEGATE)
+            .push_data(&[0xab])
+            .push_opcode(OP_1)
+            .push_opcode(OP_16)
+            .push_opcode(0x50);
+        assert_eq!(
+            script.kind(),
+            ScriptKind::NullData(vec![vec![0x81], vec![0xab], vec![1], vec![16]])
+        );
+
+        // Opcodes above `OP_16` aren't pushes.
+        let script = Script::new().push_opcode(OP_RETURN).push_opcode(OP_16 + 1);
+        assert_eq!(script.kind(), ScriptKind::NonStandard);
+    }
+
+    #[test]
+    fn instructions() {
+        let script = Script::new()
+            .push_int(0)
+            .push_int(16)
+            .push_int(-1)
+            .push_int(1000)
+            .push_data(&[7; 300])
+            .push_raw(&[OP_PUSHDATA2, 0xff, 0xff]);
+
+        let instructions: Vec<_> = script.instructions().collect();
+        assert_eq!(
+            instructions,
+            vec![
+                Ok(Instruction::Push(&[])),
+                Ok(Instruction::Op(OP_16)),
+                Ok(Instruction::Op(OP_1NEGATE)),
+                Ok(Instruction::Push(&[0xe8, 0x03])),
+                Ok(Instruction::Push(&[7; 300])),
+                Err(TruncatedPush { offset: 309 }),
+            ]
+        );
+
+        assert_eq!(
+            Script::new().push_int(-128).0,
+            vec![2, 0x80, 0x80],
+            "the sign needs its own byte"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Helpers for the optional `serde` support of messages and payloads.
+//!
+//! Hashes are written as reversed hex, the way block explorers and RPC output show them, other
+//! byte fields as plain hex and timestamps as RFC 3339.
+
+use std::fmt;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::protocol::payload::{
+    block::Block,
+    tx::{TxIn, TxOut},
+    Hash, LazyBlock,
+};
+
+impl Serialize for Hash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut bytes = self.0;
+        bytes.reverse();
+        serializer.serialize_str(&hex::encode(bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for Hash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut bytes: [u8; 32] = hex_bytes::deserialize(deserializer)?;
+        bytes.reverse();
+        Ok(Self(bytes))
+    }
+}
+
+/// Lazy blocks are (de)serialized like the [`Block`] they decode into.
+impl Serialize for LazyBlock {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::Error;
+
+        let block = self.to_block().map_err(S::Error::custom)?;
+        block.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for LazyBlock {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Block::deserialize(deserializer).map(|block| LazyBlock::from(&block))
+    }
+}
+
+/// The serialized fields of a [`TxIn`], the script length is recomputed from the script rather
+/// than trusted.
+#[derive(Deserialize)]
+pub(crate) struct TxInFields {
+    prev_out_hash: Hash,
+    prev_out_index: u32,
+    #[serde(with = "hex_bytes")]
+    script: Vec<u8>,
+    sequence: u32,
+}
+
+impl From<TxInFields> for TxIn {
+    fn from(fields: TxInFields) -> Self {
+        TxIn::new(
+            fields.prev_out_hash,
+            fields.prev_out_index,
+            fields.script,
+            fields.sequence,
+        )
+    }
+}
+
+/// The serialized fields of a [`TxOut`], the script length is recomputed from the script rather
+/// than trusted.
+#[derive(Deserialize)]
+pub(crate) struct TxOutFields {
+    value: i64,
+    #[serde(with = "hex_bytes")]
+    pk_script: Vec<u8>,
+}
+
+impl From<TxOutFields> for TxOut {
+    fn from(fields: TxOutFields) -> Self {
+        TxOut::new(fields.value, fields.pk_script)
+    }
+}
+
+/// Wraps bytes so they can be (de)serialized as hex inside options and sequences.
+struct Hex<T>(T);
+
+impl<T: AsRef<[u8]>> Serialize for Hex<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(&self.0))
+    }
+}
+
+impl<'de, T: TryFrom<Vec<u8>>> Deserialize<'de> for Hex<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        let bytes = hex::decode(string).map_err(de::Error::custom)?;
+        let len = bytes.len();
+
+        T::try_from(bytes)
+            .map(Hex)
+            .map_err(|_| de::Error::invalid_length(len, &"the field's number of bytes"))
+    }
+}
+
+/// (De)serializes byte arrays and vectors as hex strings.
+pub(crate) mod hex_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: AsRef<[u8]>>(
+        bytes: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Hex(bytes).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        Hex::deserialize(deserializer).map(|hex| hex.0)
+    }
+
+    /// (De)serializes optional bytes as a hex string or `null`.
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer, T: AsRef<[u8]>>(
+            bytes: &Option<T>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            bytes.as_ref().map(Hex).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+            deserializer: D,
+        ) -> Result<Option<T>, D::Error> {
+            Option::<Hex<T>>::deserialize(deserializer).map(|hex| hex.map(|hex| hex.0))
+        }
+    }
+
+    /// (De)serializes a sequence of byte arrays as hex strings.
+    pub mod seq {
+        use super::*;
+
+        pub fn serialize<S: Serializer, T: AsRef<[u8]>>(
+            items: &[T],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(items.iter().map(Hex))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+            deserializer: D,
+        ) -> Result<Vec<T>, D::Error> {
+            let items = Vec::<Hex<T>>::deserialize(deserializer)?;
+            Ok(items.into_iter().map(|hex| hex.0).collect())
+        }
+    }
+
+    /// (De)serializes an optional sequence of byte arrays as hex strings or `null`.
+    pub mod option_seq {
+        use super::*;
+
+        pub fn serialize<S: Serializer, T: AsRef<[u8]>>(
+            items: &Option<Vec<T>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            items
+                .as_ref()
+                .map(|items| items.iter().map(Hex).collect::<Vec<_>>())
+                .serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<Vec<u8>>>(
+            deserializer: D,
+        ) -> Result<Option<Vec<T>>, D::Error> {
+            let items = Option::<Vec<Hex<T>>>::deserialize(deserializer)?;
+            Ok(items.map(|items| items.into_iter().map(|hex| hex.0).collect()))
+        }
+    }
+}
+
+/// (De)serializes integer Unix timestamps as RFC 3339. Values which aren't a representable date
+/// are kept as integers, so deliberately invalid timestamps survive a round trip.
+pub(crate) mod unix_timestamp {
+    use super::*;
+
+    pub fn serialize<S: Serializer, T: Copy + Into<i64>>(
+        timestamp: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let timestamp = (*timestamp).into();
+        match OffsetDateTime::from_unix_timestamp(timestamp)
+            .ok()
+            .and_then(|date| date.format(&Rfc3339).ok())
+        {
+            Some(date) => serializer.serialize_str(&date),
+            None => serializer.serialize_i64(timestamp),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: TryFrom<i64>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        let timestamp = deserializer.deserialize_any(TimestampVisitor)?;
+
+        T::try_from(timestamp).map_err(|_| {
+            de::Error::invalid_value(de::Unexpected::Signed(timestamp), &"a timestamp in range")
+        })
+    }
+
+    struct TimestampVisitor;
+
+    impl<'de> de::Visitor<'de> for TimestampVisitor {
+        type Value = i64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an RFC 3339 date or seconds since the Unix epoch")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<i64, E> {
+            OffsetDateTime::parse(value, &Rfc3339)
+                .map(|date| date.unix_timestamp())
+                .map_err(E::custom)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<i64, E> {
+            Ok(value)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<i64, E> {
+            i64::try_from(value)
+                .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        protocol::{
+            message::Message,
+            payload::{codec::Codec, Version, VersionBuilder},
+        },
+        vectors::*,
+    };
+
+    #[test]
+    fn hash_is_reversed_hex() {
+        let mut bytes = [0; 32];
+        bytes[0] = 0xab;
+        let hash = Hash::new(bytes);
+
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}ab\"", "0".repeat(62)));
+        assert_eq!(serde_json::from_str::<Hash>(&json).unwrap(), hash);
+    }
+
+    #[test]
+    fn blocks_round_trip() {
+        for block_bytes in [
+            &BLOCK_TESTNET_0_207_500_BYTES[..],
+            &BLOCK_TESTNET_1_028_500_BYTES[..],
+            &BLOCK_TESTNET_1_599_200_BYTES[..],
+        ] {
+            let block = Block::decode(&mut Cursor::new(block_bytes)).unwrap();
+            let lazy_block = Message::LazyBlock(LazyBlock::from(&block));
+            let message = Message::Block(Box::new(block));
+
+            let json = serde_json::to_string(&message).unwrap();
+            assert_eq!(serde_json::from_str::<Message>(&json).unwrap(), message);
+
+            let lazy_json = serde_json::to_string(&lazy_block).unwrap();
+            assert_eq!(lazy_json.replacen("LazyBlock", "Block", 1), json);
+            assert_eq!(
+                serde_json::from_str::<Message>(&lazy_json).unwrap(),
+                lazy_block
+            );
+        }
+    }
+
+    #[test]
+    fn script_lengths_are_recomputed() {
+        let tx_in = TxIn::new(Hash::new([1; 32]), 2, vec![0x51, 0x52], u32::MAX);
+        let tx_out = TxOut::new(1_000, vec![0x53]);
+
+        let mut in_json = serde_json::to_value(&tx_in).unwrap();
+        let mut out_json = serde_json::to_value(&tx_out).unwrap();
+        assert!(in_json.get("script_len").is_none());
+        assert!(out_json.get("pk_script_len").is_none());
+
+        // Stale lengths, e.g. from an edited dump, are ignored.
+        in_json["script_len"] = 99.into();
+        out_json["pk_script_len"] = 99.into();
+        let tx_in_de: TxIn = serde_json::from_value(in_json).unwrap();
+        let tx_out_de: TxOut = serde_json::from_value(out_json).unwrap();
+
+        let mut bytes = Vec::new();
+        tx_in_de.encode(&mut bytes).unwrap();
+        assert_eq!(TxIn::decode(&mut Cursor::new(&bytes[..])).unwrap(), tx_in);
+
+        let mut bytes = Vec::new();
+        tx_out_de.encode(&mut bytes).unwrap();
+        assert_eq!(TxOut::decode(&mut Cursor::new(&bytes[..])).unwrap(), tx_out);
+    }
+
+    #[test]
+    fn version_timestamps() {
+        let addr = "127.0.0.1:8233".parse().unwrap();
+        let version = VersionBuilder::new(addr, addr).unix_timestamp(0).build();
+        let json = serde_json::to_value(&version).unwrap();
+        assert_eq!(json["timestamp"], "1970-01-01T00:00:00Z");
+        assert_eq!(serde_json::from_value::<Version>(json).unwrap(), version);
+
+        // Out of range values are kept as integers.
+        let version = VersionBuilder::new(addr, addr)
+            .unix_timestamp(i64::MAX)
+            .build();
+        let json = serde_json::to_value(&version).unwrap();
+        assert_eq!(json["timestamp"], i64::MAX);
+        assert_eq!(serde_json::from_value::<Version>(json).unwrap(), version);
+    }
+}
@@ -0,0 +1,61 @@
+//! Service flags advertised in [`Version`](super::Version) messages and network addresses.
+
+use std::fmt;
+
+bitflags::bitflags! {
+    /// The services a node supports.
+    ///
+    /// Unknown bits are kept as is, so they survive decoding and re-encoding.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    #[cfg_attr(feature = "serde", serde(transparent))]
+    pub struct Services: u64 {
+        /// The node serves the full block chain.
+        const NODE_NETWORK = 1 << 0;
+        /// The node can answer `getutxo` requests, inherited from Bitcoin and unused by Zcash.
+        const NODE_GETUTXO = 1 << 1;
+        /// The node supports bloom filtered connections, see
+        /// [`FilterLoad`](super::FilterLoad).
+        const NODE_BLOOM = 1 << 2;
+        /// The node serves the last 288 blocks only, see
+        /// [BIP-159](https://github.com/bitcoin/bips/blob/master/bip-0159.mediawiki).
+        const NODE_NETWORK_LIMITED = 1 << 10;
+
+        // Keep unknown bits.
+        const _ = !0;
+    }
+}
+
+impl Default for Services {
+    /// Only [`Services::NODE_NETWORK`].
+    fn default() -> Self {
+        Self::NODE_NETWORK
+    }
+}
+
+/// Formats the known flags by name and the unknown bits in hex, e.g.
+/// `NODE_NETWORK | NODE_BLOOM | 0x1000`.
+impl fmt::Display for Services {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("0x0");
+        }
+
+        bitflags::parser::to_writer(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_bits_are_kept() {
+        let services = Services::from_bits_retain(0x1005);
+
+        assert_eq!(services.bits(), 0x1005);
+        assert!(services.contains(Services::NODE_NETWORK | Services::NODE_BLOOM));
+        assert_eq!(services.to_string(), "NODE_NETWORK | NODE_BLOOM | 0x1000");
+        assert_eq!(Services::empty().to_string(), "0x0");
+    }
+}
@@ -0,0 +1,553 @@
+//! A builder for transactions, optionally breaking specific consensus or policy rules.
+
+use std::io;
+
+use crate::protocol::payload::tx::{
+    invalid_input, ActionDescription, JoinSplit, OutputDescriptionV4, OutputDescriptionV5,
+    SpendDescriptionV4, SpendDescriptionV5, Tx, TxIn, TxOut, TxV1, TxV2, TxV3, TxV4, TxV5, Zkproof,
+    NU5_BRANCH_ID, NU5_VERSION_GROUP_ID, OVERWINTER_VERSION_GROUP_ID, SAPLING_BRANCH_ID,
+    SAPLING_VERSION_GROUP_ID,
+};
+
+/// The number of blocks after the configured height at which transactions expire by default,
+/// matching `zcashd`.
+pub const DEFAULT_EXPIRY_DELTA: u32 = 40;
+
+/// The maximum expiry height, larger values are invalid (ZIP-203).
+pub const MAX_EXPIRY_HEIGHT: u32 = 499_999_999;
+
+/// The maximum size of a script in bytes.
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+
+/// A rule a [`TxBuilder`] can deliberately break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// The first transparent output's value is negative, an output is added if there are none.
+    NegativeValue,
+    /// The first transparent output's script exceeds [`MAX_SCRIPT_SIZE`], an output is added if
+    /// there are none.
+    OversizedScript,
+    /// The consensus branch id belongs to a different network upgrade, V5 only.
+    WrongBranchId,
+    /// Every nullifier repeats the first one, needs at least two nullifiers.
+    DuplicateNullifiers,
+}
+
+/// A builder for [`Tx`] of any version, filled with placeholder shielded components.
+///
+/// The version group id and consensus branch id (V5) are set for the version, and the
+/// shielded components are structurally valid but carry zeroed commitments, proofs and
+/// signatures. Each placeholder nullifier is unique.
+#[derive(Debug, Clone)]
+pub struct TxBuilder {
+    version: u32,
+    consensus_branch: u32,
+    tx_in: Vec<TxIn>,
+    tx_out: Vec<TxOut>,
+    lock_time: u32,
+    expiry_height: u32,
+    join_splits: usize,
+    sapling_spends: usize,
+    sapling_outputs: usize,
+    orchard_actions: usize,
+    violations: Vec<Violation>,
+}
+
+impl TxBuilder {
+    /// Creates a builder for a transaction of the given version, without expiry.
+    pub fn new(version: u32) -> Self {
+        Self {
+            version,
+            consensus_branch: NU5_BRANCH_ID,
+            tx_in: Vec::new(),
+            tx_out: Vec::new(),
+            lock_time: 0,
+            expiry_height: 0,
+            join_splits: 0,
+            sapling_spends: 0,
+            sapling_outputs: 0,
+            orchard_actions: 0,
+            violations: Vec::new(),
+        }
+    }
+
+    /// Sets the consensus branch id, only encoded in V5 transactions.
+    pub fn with_consensus_branch(mut self, consensus_branch: u32) -> Self {
+        self.consensus_branch = consensus_branch;
+        self
+    }
+
+    /// Adds a transparent input.
+    pub fn with_input(mut self, tx_in: TxIn) -> Self {
+        self.tx_in.push(tx_in);
+        self
+    }
+
+    /// Adds a transparent output.
+    pub fn with_output(mut self, tx_out: TxOut) -> Self {
+        self.tx_out.push(tx_out);
+        self
+    }
+
+    /// Sets the lock time.
+    pub fn with_lock_time(mut self, lock_time: u32) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Sets the expiry height to [`DEFAULT_EXPIRY_DELTA`] blocks after `height`, capped at
+    /// [`MAX_EXPIRY_HEIGHT`].
+    pub fn with_height(mut self, height: u32) -> Self {
+        self.expiry_height = height
+            .saturating_add(DEFAULT_EXPIRY_DELTA)
+            .min(MAX_EXPIRY_HEIGHT);
+        self
+    }
+
+    /// Sets the expiry height, `0` disables expiry.
+    pub fn with_expiry_height(mut self, expiry_height: u32) -> Self {
+        self.expiry_height = expiry_height;
+        self
+    }
+
+    /// Adds placeholder JoinSplit descriptions, V2-V4 only.
+    pub fn with_join_splits(mut self, count: usize) -> Self {
+        self.join_splits = count;
+        self
+    }
+
+    /// Adds placeholder Sapling spend descriptions, V4 and V5 only.
+    pub fn with_sapling_spends(mut self, count: usize) -> Self {
+        self.sapling_spends = count;
+        self
+    }
+
+    /// Adds placeholder Sapling output descriptions, V4 and V5 only.
+    pub fn with_sapling_outputs(mut self, count: usize) -> Self {
+        self.sapling_outputs = count;
+        self
+    }
+
+    /// Adds placeholder Orchard action descriptions, V5 only.
+    pub fn with_orchard_actions(mut self, count: usize) -> Self {
+        self.orchard_actions = count;
+        self
+    }
+
+    /// Breaks the rule when building, see [`Violation`].
+    pub fn with_violation(mut self, violation: Violation) -> Self {
+        self.violations.push(violation);
+        self
+    }
+
+    /// Builds the transaction, failing if it can't hold the requested components or violations.
+    pub fn build(&self) -> io::Result<Tx> {
+        let supports_join_splits = (2..=4).contains(&self.version);
+        let supports_sapling = self.version >= 4;
+        if (self.join_splits > 0 && !supports_join_splits)
+            || (self.sapling_spends + self.sapling_outputs > 0 && !supports_sapling)
+            || (self.orchard_actions > 0 && self.version != 5)
+        {
+            return Err(invalid_input(format!(
+                "V{} transactions can't hold the requested shielded components",
+                self.version
+            )));
+        }
+
+        let mut tx = match self.version {
+            1 => Tx::V1(TxV1 {
+                tx_in: self.tx_in.clone(),
+                tx_out: self.tx_out.clone(),
+                lock_time: self.lock_time,
+            }),
+            2 => Tx::V2(TxV2 {
+                tx_in: self.tx_in.clone(),
+                tx_out: self.tx_out.clone(),
+                lock_time: self.lock_time,
+                join_split: self.join_split(|| Zkproof::BCTV14([0; 296])),
+                join_split_pub_key: self.join_split_key(),
+                join_split_sig: self.join_split_sig(),
+            }),
+            3 => Tx::V3(TxV3 {
+                group_id: OVERWINTER_VERSION_GROUP_ID,
+                tx_in: self.tx_in.clone(),
+                tx_out: self.tx_out.clone(),
+                lock_time: self.lock_time,
+                expiry_height: self.expiry_height,
+                join_split: self.join_split(|| Zkproof::BCTV14([0; 296])),
+                join_split_pub_key: self.join_split_key(),
+                join_split_sig: self.join_split_sig(),
+            }),
+            4 => Tx::V4(self.build_v4()),
+            5 => Tx::V5(Box::new(self.build_v5())),
+            version => {
+                return Err(invalid_input(format!(
+                    "unsupported transaction version {}",
+                    version
+                )))
+            }
+        };
+
+        for violation in &self.violations {
+            self.apply(&mut tx, *violation)?;
+        }
+
+        Ok(tx)
+    }
+
+    fn build_v4(&self) -> TxV4 {
+        let has_sapling = self.sapling_spends + self.sapling_outputs > 0;
+
+        TxV4 {
+            group_id: SAPLING_VERSION_GROUP_ID,
+            tx_in: self.tx_in.clone(),
+            tx_out: self.tx_out.clone(),
+            lock_time: self.lock_time,
+            expiry_height: self.expiry_height,
+            value_balance_sapling: 0,
+            spends_sapling: (0..self.sapling_spends)
+                .map(|i| SpendDescriptionV4 {
+                    cv: [0; 32],
+                    anchor: [0; 32],
+                    nullifier: placeholder_nullifier(Pool::Sapling, i),
+                    rk: [0; 32],
+                    zkproof: [0; 192],
+                    spend_auth_sig: [0; 64],
+                })
+                .collect(),
+            outputs_sapling: (0..self.sapling_outputs)
+                .map(|_| OutputDescriptionV4 {
+                    cv: [0; 32],
+                    cmu: [0; 32],
+                    ephemeral_key: [0; 32],
+                    enc_ciphertext: [0; 580],
+                    out_ciphertext: [0; 80],
+                    zkproof: [0; 192],
+                })
+                .collect(),
+            join_split: self.join_split(|| Zkproof::Groth16([0; 192])),
+            join_split_pub_key: self.join_split_key(),
+            join_split_sig: self.join_split_sig(),
+            binding_sig_sapling: has_sapling.then_some([0; 64]),
+        }
+    }
+
+    fn build_v5(&self) -> TxV5 {
+        let has_sapling = self.sapling_spends + self.sapling_outputs > 0;
+        let has_orchard = self.orchard_actions > 0;
+
+        TxV5 {
+            group_id: NU5_VERSION_GROUP_ID,
+            consensus_branch: self.consensus_branch,
+            lock_time: self.lock_time,
+            expiry_height: self.expiry_height,
+            tx_in: self.tx_in.clone(),
+            tx_out: self.tx_out.clone(),
+            spends_sapling: (0..self.sapling_spends)
+                .map(|i| SpendDescriptionV5 {
+                    cv: [0; 32],
+                    nullifier: placeholder_nullifier(Pool::Sapling, i),
+                    rk: [0; 32],
+                })
+                .collect(),
+            outputs_sapling: (0..self.sapling_outputs)
+                .map(|_| OutputDescriptionV5 {
+                    cv: [0; 32],
+                    cmu: [0; 32],
+                    ephemeral_key: [0; 32],
+                    enc_ciphertext: [0; 580],
+                    out_ciphertext: [0; 80],
+                })
+                .collect(),
+            value_balance_sapling: has_sapling.then_some(0),
+            anchor_sapling: (self.sapling_spends > 0).then_some([0; 32]),
+            spend_proofs_sapling: vec![[0; 192]; self.sapling_spends],
+            spend_auth_sigs_sapling: vec![[0; 64]; self.sapling_spends],
+            output_proofs_sapling: vec![[0; 192]; self.sapling_outputs],
+            binding_sig_sapling: has_sapling.then_some([0; 64]),
+            actions_orchard: (0..self.orchard_actions)
+                .map(|i| ActionDescription {
+                    cv: [0; 32],
+                    nullifier: placeholder_nullifier(Pool::Orchard, i),
+                    rk: [0; 32],
+                    cmx: [0; 32],
+                    ephemeral_key: [0; 32],
+                    enc_ciphertext: [0; 580],
+                    out_ciphertext: [0; 80],
+                })
+                .collect(),
+            // Spends and outputs enabled.
+            flags_orchard: has_orchard.then_some(0b11),
+            value_balance_orchard: has_orchard.then_some(0),
+            anchor_orchard: has_orchard.then_some([0; 32]),
+            // The size of a Halo 2 proof for the actions.
+            proofs_orchard: has_orchard.then(|| vec![0; 2720 + 2272 * self.orchard_actions]),
+            auth_sigs_orchard: has_orchard.then(|| vec![[0; 64]; self.orchard_actions]),
+            binding_sig_orchard: has_orchard.then_some([0; 64]),
+        }
+    }
+
+    fn join_split(&self, zkproof: impl Fn() -> Zkproof) -> Vec<JoinSplit> {
+        (0..self.join_splits)
+            .map(|i| {
+                let mut nullifiers = [0; 64];
+                nullifiers[..32].copy_from_slice(&placeholder_nullifier(Pool::Sprout, 2 * i));
+                nullifiers[32..].copy_from_slice(&placeholder_nullifier(Pool::Sprout, 2 * i + 1));
+
+                JoinSplit {
+                    pub_old: 0,
+                    pub_new: 0,
+                    anchor: [0; 32],
+                    nullifiers,
+                    commitments: [0; 64],
+                    ephemeral_key: [0; 32],
+                    random_seed: [0; 32],
+                    vmacs: [0; 64],
+                    zkproof: zkproof(),
+                    enc_ciphertexts: [0; 1202],
+                }
+            })
+            .collect()
+    }
+
+    fn join_split_key(&self) -> Option<[u8; 32]> {
+        (self.join_splits > 0).then_some([0; 32])
+    }
+
+    fn join_split_sig(&self) -> Option<[u8; 64]> {
+        (self.join_splits > 0).then_some([0; 64])
+    }
+
+    fn apply(&self, tx: &mut Tx, violation: Violation) -> io::Result<()> {
+        match violation {
+            Violation::NegativeValue => {
+                let tx_out = first_output(tx);
+                *tx_out = TxOut::new(-1, tx_out.pk_script().to_vec());
+            }
+            Violation::OversizedScript => {
+                let tx_out = first_output(tx);
+                *tx_out = TxOut::new(tx_out.value(), vec![0; MAX_SCRIPT_SIZE + 1]);
+            }
+            Violation::WrongBranchId => match tx {
+                Tx::V5(tx) => {
+                    tx.consensus_branch = if self.consensus_branch == SAPLING_BRANCH_ID {
+                        NU5_BRANCH_ID
+                    } else {
+                        SAPLING_BRANCH_ID
+                    };
+                }
+                _ => {
+                    return Err(invalid_input(
+                        "only V5 transactions encode a consensus branch id",
+                    ))
+                }
+            },
+            Violation::DuplicateNullifiers => {
+                let mut nullifiers = nullifiers_mut(tx);
+                match nullifiers.split_first_mut() {
+                    Some((first, rest)) if !rest.is_empty() => {
+                        for nullifier in rest {
+                            nullifier.copy_from_slice(first);
+                        }
+                    }
+                    _ => return Err(invalid_input("fewer than two nullifiers to duplicate")),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The shielded pools, placeholder nullifiers are unique across them.
+#[derive(Clone, Copy)]
+enum Pool {
+    Sprout = 1,
+    Sapling,
+    Orchard,
+}
+
+fn placeholder_nullifier(pool: Pool, index: usize) -> [u8; 32] {
+    let mut nullifier = [0; 32];
+    nullifier[0] = pool as u8;
+    nullifier[1..9].copy_from_slice(&(index as u64).to_le_bytes());
+
+    nullifier
+}
+
+fn first_output(tx: &mut Tx) -> &mut TxOut {
+    let tx_out = match tx {
+        Tx::V1(tx) => &mut tx.tx_out,
+        Tx::V2(tx) => &mut tx.tx_out,
+        Tx::V3(tx) => &mut tx.tx_out,
+        Tx::V4(tx) => &mut tx.tx_out,
+        Tx::V5(tx) => &mut tx.tx_out,
+    };
+
+    if tx_out.is_empty() {
+        tx_out.push(TxOut::new(0, Vec::new()));
+    }
+
+    &mut tx_out[0]
+}
+
+fn nullifiers_mut(tx: &mut Tx) -> Vec<&mut [u8]> {
+    fn join_split(join_split: &mut [JoinSplit]) -> impl Iterator<Item = &mut [u8]> {
+        join_split
+            .iter_mut()
+            .flat_map(|description| description.nullifiers.chunks_exact_mut(32))
+    }
+
+    match tx {
+        Tx::V1(_) => Vec::new(),
+        Tx::V2(tx) => join_split(&mut tx.join_split).collect(),
+        Tx::V3(tx) => join_split(&mut tx.join_split).collect(),
+        Tx::V4(tx) => join_split(&mut tx.join_split)
+            .chain(
+                tx.spends_sapling
+                    .iter_mut()
+                    .map(|spend| &mut spend.nullifier[..]),
+            )
+            .collect(),
+        Tx::V5(tx) => {
+            let TxV5 {
+                spends_sapling,
+                actions_orchard,
+                ..
+            } = tx.as_mut();
+
+            spends_sapling
+                .iter_mut()
+                .map(|spend| &mut spend.nullifier[..])
+                .chain(
+                    actions_orchard
+                        .iter_mut()
+                        .map(|action| &mut action.nullifier[..]),
+                )
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::protocol::payload::{codec::Codec, Hash};
+
+    fn round_trip(tx: &Tx) -> Tx {
+        let mut bytes = Vec::new();
+        tx.encode(&mut bytes).unwrap();
+        Tx::decode(&mut Cursor::new(&bytes)).unwrap()
+    }
+
+    #[test]
+    fn builds_every_version() {
+        let builders = [
+            TxBuilder::new(1),
+            TxBuilder::new(2).with_join_splits(1),
+            TxBuilder::new(3).with_join_splits(2),
+            TxBuilder::new(4)
+                .with_join_splits(1)
+                .with_sapling_spends(2)
+                .with_sapling_outputs(1),
+            TxBuilder::new(5)
+                .with_sapling_spends(1)
+                .with_sapling_outputs(2)
+                .with_orchard_actions(2),
+        ];
+
+        for (version, builder) in (1..).zip(builders) {
+            let tx = builder
+                .with_input(TxIn::new(Hash::new([1; 32]), 0, vec![0x51], u32::MAX))
+                .with_output(TxOut::new(1_000, vec![0x51]))
+                .with_height(1_000)
+                .build()
+                .unwrap();
+
+            assert_eq!(tx.version(), version);
+            assert_eq!(round_trip(&tx), tx);
+            if version >= 3 {
+                assert_eq!(tx.expiry_height(), Some(1_000 + DEFAULT_EXPIRY_DELTA));
+            }
+        }
+
+        let Tx::V5(tx) = TxBuilder::new(5).build().unwrap() else {
+            panic!("expected a V5 transaction");
+        };
+        assert_eq!(tx.group_id, NU5_VERSION_GROUP_ID);
+        assert_eq!(tx.consensus_branch, NU5_BRANCH_ID);
+
+        for height in [MAX_EXPIRY_HEIGHT - 1, u32::MAX] {
+            let tx = TxBuilder::new(4).with_height(height).build().unwrap();
+            assert_eq!(tx.expiry_height(), Some(MAX_EXPIRY_HEIGHT));
+        }
+    }
+
+    #[test]
+    fn violations() {
+        let tx = TxBuilder::new(4)
+            .with_violation(Violation::NegativeValue)
+            .with_violation(Violation::OversizedScript)
+            .build()
+            .unwrap();
+        assert_eq!(tx.tx_out()[0].value(), -1);
+        assert_eq!(tx.tx_out()[0].pk_script().len(), MAX_SCRIPT_SIZE + 1);
+        assert_eq!(round_trip(&tx), tx);
+
+        let Tx::V5(tx) = TxBuilder::new(5)
+            .with_violation(Violation::WrongBranchId)
+            .build()
+            .unwrap()
+        else {
+            panic!("expected a V5 transaction");
+        };
+        assert_ne!(tx.consensus_branch, NU5_BRANCH_ID);
+
+        let Tx::V5(tx) = TxBuilder::new(5)
+            .with_sapling_spends(1)
+            .with_orchard_actions(1)
+            .with_violation(Violation::DuplicateNullifiers)
+            .build()
+            .unwrap()
+        else {
+            panic!("expected a V5 transaction");
+        };
+        assert_eq!(
+            tx.spends_sapling[0].nullifier,
+            tx.actions_orchard[0].nullifier
+        );
+
+        // A single JoinSplit carries two nullifiers.
+        let Tx::V2(tx) = TxBuilder::new(2)
+            .with_join_splits(1)
+            .with_violation(Violation::DuplicateNullifiers)
+            .build()
+            .unwrap()
+        else {
+            panic!("expected a V2 transaction");
+        };
+        assert_eq!(
+            tx.join_split[0].nullifiers[..32],
+            tx.join_split[0].nullifiers[32..]
+        );
+    }
+
+    #[test]
+    fn unsupported_components_fail() {
+        assert!(TxBuilder::new(1).with_join_splits(1).build().is_err());
+        assert!(TxBuilder::new(3).with_sapling_spends(1).build().is_err());
+        assert!(TxBuilder::new(4).with_orchard_actions(1).build().is_err());
+        assert!(TxBuilder::new(6).build().is_err());
+        assert!(TxBuilder::new(4)
+            .with_violation(Violation::WrongBranchId)
+            .build()
+            .is_err());
+        assert!(TxBuilder::new(5)
+            .with_sapling_spends(1)
+            .with_violation(Violation::DuplicateNullifiers)
+            .build()
+            .is_err());
+    }
+}
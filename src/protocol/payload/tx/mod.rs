@@ -0,0 +1,1882 @@
+//! Transaction-related types.
+
+use std::io;
+
+use bytes::{Buf, BufMut};
+
+use crate::protocol::payload::{
+    codec::{Codec, DecodeContext, DecodeError, DecodeErrorKind},
+    inv::{InvHash, ObjectKind},
+    read_n_bytes, Hash, HashWriter, VarInt,
+};
+
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::hex_bytes;
+
+pub mod builder;
+pub use builder::{TxBuilder, Violation};
+pub mod sighash;
+pub use sighash::{SigHashType, SignableInput};
+
+mod zip243;
+mod zip244;
+
+/// The version group id of V3 transactions.
+pub const OVERWINTER_VERSION_GROUP_ID: u32 = 0x03c4_8270;
+/// The version group id of V4 transactions.
+pub const SAPLING_VERSION_GROUP_ID: u32 = 0x892f_2085;
+/// The version group id of V5 transactions.
+pub const NU5_VERSION_GROUP_ID: u32 = 0x26a7_270a;
+
+/// The consensus branch id of the Sapling network upgrade.
+pub const SAPLING_BRANCH_ID: u32 = 0x76b8_09bb;
+/// The consensus branch id of the NU5 network upgrade, the first to allow V5 transactions.
+pub const NU5_BRANCH_ID: u32 = 0xc2d6_d0b4;
+
+/// A Zcash transaction ([spec](https://zips.z.cash/protocol/canopy.pdf#txnencodingandconsensus)).
+///
+/// Supports V1-V5.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tx {
+    V1(TxV1),
+    V2(TxV2),
+    V3(TxV3),
+    V4(TxV4),
+    V5(Box<TxV5>),
+}
+
+impl Tx {
+    /// Calculates the double Sha256 hash for this transaction.
+    ///
+    /// This is only the transaction id for V1-V4 transactions, see [`Tx::txid`].
+    pub fn double_sha256(&self) -> io::Result<Hash> {
+        let mut writer = HashWriter::new();
+        self.encode(&mut writer)?;
+
+        Ok(writer.double_sha256())
+    }
+
+    /// Returns the transaction id: the double Sha256 hash for V1-V4 transactions and the
+    /// [ZIP-244](https://zips.z.cash/zip-0244) digest for V5 transactions.
+    ///
+    /// Fields the encoding requires but which were left out are hashed as zeros, so transactions
+    /// which fail to encode still have an id.
+    pub fn txid(&self) -> Hash {
+        match self {
+            Tx::V5(tx) => tx.txid(),
+            _ => {
+                let mut writer = HashWriter::new();
+                self.encode_with(&mut writer, MissingFields::ZeroFill)
+                    .expect("zero-filled transactions always encode");
+
+                writer.double_sha256()
+            }
+        }
+    }
+
+    /// Returns the [ZIP-244](https://zips.z.cash/zip-0244) authorizing data commitment.
+    ///
+    /// V1-V4 transactions don't commit to their authorizing data, their digest is `[0xff; 32]`.
+    pub fn auth_digest(&self) -> Hash {
+        match self {
+            Tx::V5(tx) => tx.auth_digest(),
+            _ => Hash::new([0xff; 32]),
+        }
+    }
+
+    /// Convenience function which creates the [`InvHash`] for this `Tx`.
+    ///
+    /// V5 transactions are announced by wtxid with [`ObjectKind::WTx`], earlier versions by txid
+    /// with [`ObjectKind::Tx`].
+    pub fn inv_hash(&self) -> InvHash {
+        match self {
+            Tx::V5(tx) => InvHash::new_wtx(tx.txid(), tx.auth_digest()),
+            _ => InvHash::new(ObjectKind::Tx, self.txid()),
+        }
+    }
+
+    /// Returns the transaction version, without the overwintered flag.
+    pub fn version(&self) -> u32 {
+        match self {
+            Tx::V1(_) => 1,
+            Tx::V2(_) => 2,
+            Tx::V3(_) => 3,
+            Tx::V4(_) => 4,
+            Tx::V5(_) => 5,
+        }
+    }
+
+    /// Returns the lock time of this transaction.
+    pub fn lock_time(&self) -> u32 {
+        match self {
+            Tx::V1(tx) => tx.lock_time,
+            Tx::V2(tx) => tx.lock_time,
+            Tx::V3(tx) => tx.lock_time,
+            Tx::V4(tx) => tx.lock_time,
+            Tx::V5(tx) => tx.lock_time,
+        }
+    }
+
+    /// Returns the expiry height of this transaction, `None` for V1 and V2 transactions.
+    pub fn expiry_height(&self) -> Option<u32> {
+        match self {
+            Tx::V1(_) | Tx::V2(_) => None,
+            Tx::V3(tx) => Some(tx.expiry_height),
+            Tx::V4(tx) => Some(tx.expiry_height),
+            Tx::V5(tx) => Some(tx.expiry_height),
+        }
+    }
+
+    /// Returns `true` if this is a coinbase transaction, whose single input spends the null
+    /// outpoint.
+    pub fn is_coinbase(&self) -> bool {
+        is_coinbase(self.tx_in())
+    }
+
+    /// Returns the transparent inputs of this transaction.
+    pub fn tx_in(&self) -> &[TxIn] {
+        match self {
+            Tx::V1(tx) => &tx.tx_in,
+            Tx::V2(tx) => &tx.tx_in,
+            Tx::V3(tx) => &tx.tx_in,
+            Tx::V4(tx) => &tx.tx_in,
+            Tx::V5(tx) => &tx.tx_in,
+        }
+    }
+
+    /// Returns the transparent outputs of this transaction.
+    pub fn tx_out(&self) -> &[TxOut] {
+        match self {
+            Tx::V1(tx) => &tx.tx_out,
+            Tx::V2(tx) => &tx.tx_out,
+            Tx::V3(tx) => &tx.tx_out,
+            Tx::V4(tx) => &tx.tx_out,
+            Tx::V5(tx) => &tx.tx_out,
+        }
+    }
+}
+
+fn is_coinbase(tx_in: &[TxIn]) -> bool {
+    matches!(tx_in, [tx_in] if tx_in.prev_out_hash == Hash::zeroed() && tx_in.prev_out_index == u32::MAX)
+}
+
+/// How the encoding treats the fields it requires, which may have been left out as the fields
+/// are public.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MissingFields {
+    /// Fails the encoding with [`io::ErrorKind::InvalidInput`].
+    Reject,
+    /// Writes zeros in their place, e.g. to hash the transaction.
+    ZeroFill,
+}
+
+impl MissingFields {
+    fn field<T: Default>(self, field: Option<T>, name: &str) -> io::Result<T> {
+        self.or(field, T::default, name)
+    }
+
+    // Arrays longer than 32 bytes don't implement `Default`.
+    fn bytes<const N: usize>(self, field: Option<[u8; N]>, name: &str) -> io::Result<[u8; N]> {
+        self.or(field, || [0; N], name)
+    }
+
+    fn or<T>(self, field: Option<T>, zero: impl FnOnce() -> T, name: &str) -> io::Result<T> {
+        match (field, self) {
+            (Some(field), _) => Ok(field),
+            (None, Self::ZeroFill) => Ok(zero()),
+            (None, Self::Reject) => Err(invalid_input(format!("missing {}", name))),
+        }
+    }
+}
+
+pub(crate) fn invalid_input<E: Into<Box<dyn std::error::Error + Send + Sync>>>(
+    error: E,
+) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, error)
+}
+
+impl Tx {
+    fn encode_with<B: BufMut>(&self, buffer: &mut B, missing: MissingFields) -> io::Result<()> {
+        match self {
+            Tx::V1(tx) => {
+                // The overwintered flag is NOT set.
+                buffer.put_u32_le(1u32);
+                tx.encode(buffer)?;
+            }
+            Tx::V2(tx) => {
+                // The overwintered flag is NOT set.
+                buffer.put_u32_le(2u32);
+                tx.encode_with(buffer, missing)?;
+            }
+            Tx::V3(tx) => {
+                // The overwintered flag IS set.
+                buffer.put_u32_le(3u32 | 1 << 31);
+                tx.encode_with(buffer, missing)?;
+            }
+            Tx::V4(tx) => {
+                // The overwintered flag IS set.
+                buffer.put_u32_le(4u32 | 1 << 31);
+                tx.encode_with(buffer, missing)?;
+            }
+            Tx::V5(tx) => {
+                // The overwintered flag IS set.
+                buffer.put_u32_le(5u32 | 1 << 31);
+                tx.encode_with(buffer, missing)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Codec for Tx {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.encode_with(buffer, MissingFields::Reject)
+    }
+
+    /// Fields the encoding requires but which were left out are counted as zero-filled, the way
+    /// [`Tx::txid`] hashes them.
+    fn encoded_len(&self) -> usize {
+        // The version header.
+        4 + match self {
+            Tx::V1(tx) => tx.encoded_len(),
+            Tx::V2(tx) => tx.encoded_len(),
+            Tx::V3(tx) => tx.encoded_len(),
+            Tx::V4(tx) => tx.encoded_len(),
+            Tx::V5(tx) => tx.encoded_len(),
+        }
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        const LOW_31_BITS: u32 = !(1 << 31);
+        let header = u32::from_le_bytes(read_n_bytes(bytes).in_field("header")?);
+
+        // Extract transaction version and check if overwinter flag is set.
+        let (version, overwinter) = (header & LOW_31_BITS, header >> 31 != 0);
+
+        let tx = match (version, overwinter) {
+            (1, false) => Self::V1(TxV1::decode(bytes).in_field("V1")?),
+            (2, false) => Self::V2(TxV2::decode(bytes).in_field("V2")?),
+            (3, true) => Self::V3(TxV3::decode(bytes).in_field("V3")?),
+            (4, true) => Self::V4(TxV4::decode(bytes).in_field("V4")?),
+            (5, true) => Self::V5(Box::new(TxV5::decode(bytes).in_field("V5")?)),
+            // Unknown version and overwinter flag combination.
+            _ => {
+                let kind = DecodeErrorKind::BadTag {
+                    name: "transaction version",
+                    tag: header.into(),
+                };
+                return Err(DecodeError::new(kind, bytes).in_field("header").into());
+            }
+        };
+
+        Ok(tx)
+    }
+}
+
+/// A V1 transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxV1 {
+    /// The transparent inputs.
+    pub tx_in: Vec<TxIn>,
+    /// The transparent outputs.
+    pub tx_out: Vec<TxOut>,
+    /// The block height or timestamp before which the transaction can't be mined.
+    pub lock_time: u32,
+}
+
+impl Codec for TxV1 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.tx_in.encode(buffer)?;
+        self.tx_out.encode(buffer)?;
+
+        buffer.put_u32_le(self.lock_time);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.tx_in.encoded_len() + self.tx_out.encoded_len() + 4
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let tx_in = Vec::<TxIn>::decode(bytes).in_field("tx_in")?;
+        let tx_out = Vec::<TxOut>::decode(bytes).in_field("tx_out")?;
+
+        let lock_time = u32::from_le_bytes(read_n_bytes(bytes).in_field("lock_time")?);
+
+        Ok(Self {
+            tx_in,
+            tx_out,
+            lock_time,
+        })
+    }
+}
+
+/// A V2 transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxV2 {
+    /// The transparent inputs.
+    pub tx_in: Vec<TxIn>,
+    /// The transparent outputs.
+    pub tx_out: Vec<TxOut>,
+    /// The block height or timestamp before which the transaction can't be mined.
+    pub lock_time: u32,
+    /// The BCTV14 JoinSplit descriptions.
+    pub join_split: Vec<JoinSplit>,
+    /// The key the JoinSplit signature verifies with, present if and only if there are
+    /// JoinSplit descriptions.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub join_split_pub_key: Option<[u8; 32]>,
+    /// The Ed25519 signature over the transaction, present if and only if there are JoinSplit
+    /// descriptions.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub join_split_sig: Option<[u8; 64]>,
+}
+
+impl TxV2 {
+    fn encode_with<B: BufMut>(&self, buffer: &mut B, missing: MissingFields) -> io::Result<()> {
+        self.tx_in.encode(buffer)?;
+        self.tx_out.encode(buffer)?;
+
+        buffer.put_u32_le(self.lock_time);
+
+        VarInt(self.join_split.len()).encode(buffer)?;
+        for description in &self.join_split {
+            // Encode join split description.
+            description.encode(buffer)?;
+        }
+
+        if !self.join_split.is_empty() {
+            buffer.put_slice(&missing.bytes(self.join_split_pub_key, "join_split_pub_key")?);
+            buffer.put_slice(&missing.bytes(self.join_split_sig, "join_split_sig")?);
+        }
+
+        Ok(())
+    }
+}
+
+impl Codec for TxV2 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.encode_with(buffer, MissingFields::Reject)
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.tx_in.encoded_len()
+            + self.tx_out.encoded_len()
+            + 4
+            + join_split_encoded_len(&self.join_split)
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let tx_in = Vec::<TxIn>::decode(bytes).in_field("tx_in")?;
+        let tx_out = Vec::<TxOut>::decode(bytes).in_field("tx_out")?;
+        let lock_time = u32::from_le_bytes(read_n_bytes(bytes).in_field("lock_time")?);
+
+        let join_split_count = *VarInt::decode(bytes).in_field("join_split")?;
+        let mut join_split = Vec::new();
+
+        for i in 0..join_split_count {
+            let description = JoinSplit::decode_bctv14(bytes)
+                .at_index(i)
+                .in_field("join_split")?;
+            join_split.push(description);
+        }
+
+        let (join_split_pub_key, join_split_sig) = if join_split_count > 0 {
+            let pub_key = read_n_bytes(bytes).in_field("join_split_pub_key")?;
+            let sig = read_n_bytes(bytes).in_field("join_split_sig")?;
+
+            (Some(pub_key), Some(sig))
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            tx_in,
+            tx_out,
+            lock_time,
+            join_split,
+            join_split_pub_key,
+            join_split_sig,
+        })
+    }
+}
+
+/// A V3 transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxV3 {
+    /// The version group id.
+    pub group_id: u32,
+    /// The transparent inputs.
+    pub tx_in: Vec<TxIn>,
+    /// The transparent outputs.
+    pub tx_out: Vec<TxOut>,
+    /// The block height or timestamp before which the transaction can't be mined.
+    pub lock_time: u32,
+    /// The block height after which the transaction can't be mined, `0` disables expiry.
+    pub expiry_height: u32,
+    /// The BCTV14 JoinSplit descriptions.
+    pub join_split: Vec<JoinSplit>,
+    /// The key the JoinSplit signature verifies with, present if and only if there are
+    /// JoinSplit descriptions.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub join_split_pub_key: Option<[u8; 32]>,
+    /// The Ed25519 signature over the transaction, present if and only if there are JoinSplit
+    /// descriptions.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub join_split_sig: Option<[u8; 64]>,
+}
+
+impl TxV3 {
+    fn encode_with<B: BufMut>(&self, buffer: &mut B, missing: MissingFields) -> io::Result<()> {
+        buffer.put_u32_le(self.group_id);
+
+        self.tx_in.encode(buffer)?;
+        self.tx_out.encode(buffer)?;
+
+        buffer.put_u32_le(self.lock_time);
+        buffer.put_u32_le(self.expiry_height);
+
+        VarInt(self.join_split.len()).encode(buffer)?;
+        for description in &self.join_split {
+            // Encode join split description.
+            description.encode(buffer)?;
+        }
+
+        if !self.join_split.is_empty() {
+            buffer.put_slice(&missing.bytes(self.join_split_pub_key, "join_split_pub_key")?);
+            buffer.put_slice(&missing.bytes(self.join_split_sig, "join_split_sig")?);
+        }
+
+        Ok(())
+    }
+}
+
+impl Codec for TxV3 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.encode_with(buffer, MissingFields::Reject)
+    }
+
+    fn encoded_len(&self) -> usize {
+        4 + self.tx_in.encoded_len()
+            + self.tx_out.encoded_len()
+            + 4
+            + 4
+            + join_split_encoded_len(&self.join_split)
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let group_id = u32::from_le_bytes(read_n_bytes(bytes).in_field("group_id")?);
+
+        let tx_in = Vec::<TxIn>::decode(bytes).in_field("tx_in")?;
+        let tx_out = Vec::<TxOut>::decode(bytes).in_field("tx_out")?;
+        let lock_time = u32::from_le_bytes(read_n_bytes(bytes).in_field("lock_time")?);
+        let expiry_height = u32::from_le_bytes(read_n_bytes(bytes).in_field("expiry_height")?);
+
+        let join_split_count = *VarInt::decode(bytes).in_field("join_split")?;
+        let mut join_split = Vec::new();
+
+        for i in 0..join_split_count {
+            let description = JoinSplit::decode_bctv14(bytes)
+                .at_index(i)
+                .in_field("join_split")?;
+            join_split.push(description);
+        }
+
+        let (join_split_pub_key, join_split_sig) = if join_split_count > 0 {
+            let pub_key = read_n_bytes(bytes).in_field("join_split_pub_key")?;
+            let sig = read_n_bytes(bytes).in_field("join_split_sig")?;
+
+            (Some(pub_key), Some(sig))
+        } else {
+            (None, None)
+        };
+
+        Ok(Self {
+            group_id,
+            tx_in,
+            tx_out,
+            lock_time,
+            expiry_height,
+            join_split,
+            join_split_pub_key,
+            join_split_sig,
+        })
+    }
+}
+
+/// A V4 transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxV4 {
+    /// The version group id.
+    pub group_id: u32,
+    /// The transparent inputs.
+    pub tx_in: Vec<TxIn>,
+    /// The transparent outputs.
+    pub tx_out: Vec<TxOut>,
+    /// The block height or timestamp before which the transaction can't be mined.
+    pub lock_time: u32,
+    /// The block height after which the transaction can't be mined, `0` disables expiry.
+    pub expiry_height: u32,
+    /// The net value of the Sapling spends minus outputs.
+    pub value_balance_sapling: i64,
+    /// The Sapling spend descriptions.
+    pub spends_sapling: Vec<SpendDescriptionV4>,
+    /// The Sapling output descriptions.
+    pub outputs_sapling: Vec<OutputDescriptionV4>,
+    /// The Groth16 JoinSplit descriptions.
+    pub join_split: Vec<JoinSplit>,
+    /// The key the JoinSplit signature verifies with, present if and only if there are
+    /// JoinSplit descriptions.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub join_split_pub_key: Option<[u8; 32]>,
+    /// The Ed25519 signature over the transaction, present if and only if there are JoinSplit
+    /// descriptions.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub join_split_sig: Option<[u8; 64]>,
+    /// The Sapling binding signature, present if and only if there are Sapling spends or
+    /// outputs.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub binding_sig_sapling: Option<[u8; 64]>,
+}
+
+impl TxV4 {
+    fn encode_with<B: BufMut>(&self, buffer: &mut B, missing: MissingFields) -> io::Result<()> {
+        buffer.put_u32_le(self.group_id);
+
+        self.tx_in.encode(buffer)?;
+        self.tx_out.encode(buffer)?;
+
+        buffer.put_u32_le(self.lock_time);
+        buffer.put_u32_le(self.expiry_height);
+
+        buffer.put_i64_le(self.value_balance_sapling);
+        self.spends_sapling.encode(buffer)?;
+        self.outputs_sapling.encode(buffer)?;
+
+        VarInt(self.join_split.len()).encode(buffer)?;
+        for description in &self.join_split {
+            // Encode join split description.
+            description.encode(buffer)?;
+        }
+
+        if !self.join_split.is_empty() {
+            buffer.put_slice(&missing.bytes(self.join_split_pub_key, "join_split_pub_key")?);
+            buffer.put_slice(&missing.bytes(self.join_split_sig, "join_split_sig")?);
+        }
+
+        if !self.spends_sapling.is_empty() || !self.outputs_sapling.is_empty() {
+            buffer.put_slice(&missing.bytes(self.binding_sig_sapling, "binding_sig_sapling")?);
+        }
+
+        Ok(())
+    }
+}
+
+impl Codec for TxV4 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.encode_with(buffer, MissingFields::Reject)
+    }
+
+    fn encoded_len(&self) -> usize {
+        let binding_sig_len = if !self.spends_sapling.is_empty() || !self.outputs_sapling.is_empty()
+        {
+            64
+        } else {
+            0
+        };
+
+        4 + self.tx_in.encoded_len()
+            + self.tx_out.encoded_len()
+            + 4
+            + 4
+            + 8
+            + self.spends_sapling.encoded_len()
+            + self.outputs_sapling.encoded_len()
+            + join_split_encoded_len(&self.join_split)
+            + binding_sig_len
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let group_id = u32::from_le_bytes(read_n_bytes(bytes).in_field("group_id")?);
+
+        let tx_in = Vec::<TxIn>::decode(bytes).in_field("tx_in")?;
+        let tx_out = Vec::<TxOut>::decode(bytes).in_field("tx_out")?;
+        let lock_time = u32::from_le_bytes(read_n_bytes(bytes).in_field("lock_time")?);
+        let expiry_height = u32::from_le_bytes(read_n_bytes(bytes).in_field("expiry_height")?);
+
+        let value_balance_sapling =
+            i64::from_le_bytes(read_n_bytes(bytes).in_field("value_balance_sapling")?);
+        let spends_sapling = Vec::<SpendDescriptionV4>::decode(bytes).in_field("spends_sapling")?;
+        let outputs_sapling =
+            Vec::<OutputDescriptionV4>::decode(bytes).in_field("outputs_sapling")?;
+
+        let join_split_count = VarInt::decode(bytes).in_field("join_split")?;
+        let mut join_split = Vec::new();
+
+        for i in 0..*join_split_count {
+            let description = JoinSplit::decode_groth16(bytes)
+                .at_index(i)
+                .in_field("join_split")?;
+            join_split.push(description);
+        }
+
+        let (join_split_pub_key, join_split_sig) = if *join_split_count > 0 {
+            let pub_key = read_n_bytes(bytes).in_field("join_split_pub_key")?;
+            let sig = read_n_bytes(bytes).in_field("join_split_sig")?;
+
+            (Some(pub_key), Some(sig))
+        } else {
+            (None, None)
+        };
+
+        let binding_sig_sapling = if !spends_sapling.is_empty() || !outputs_sapling.is_empty() {
+            Some(read_n_bytes(bytes).in_field("binding_sig_sapling")?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            group_id,
+            tx_in,
+            tx_out,
+            lock_time,
+            expiry_height,
+            value_balance_sapling,
+            spends_sapling,
+            outputs_sapling,
+            join_split,
+            join_split_pub_key,
+            join_split_sig,
+            binding_sig_sapling,
+        })
+    }
+}
+
+/// A V5 transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxV5 {
+    /// The version group id.
+    pub group_id: u32,
+    /// The consensus branch id of the network upgrade the transaction is valid for.
+    pub consensus_branch: u32,
+    /// The block height or timestamp before which the transaction can't be mined.
+    pub lock_time: u32,
+    /// The block height after which the transaction can't be mined, `0` disables expiry.
+    pub expiry_height: u32,
+    /// The transparent inputs.
+    pub tx_in: Vec<TxIn>,
+    /// The transparent outputs.
+    pub tx_out: Vec<TxOut>,
+
+    /// The Sapling spend descriptions.
+    pub spends_sapling: Vec<SpendDescriptionV5>,
+    /// The Sapling output descriptions.
+    pub outputs_sapling: Vec<OutputDescriptionV5>,
+    /// The net value of the Sapling spends minus outputs, present if and only if there are
+    /// Sapling spends or outputs.
+    pub value_balance_sapling: Option<i64>,
+    /// The Sapling note commitment tree root shared by the spends, present if and only if there
+    /// are Sapling spends.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub anchor_sapling: Option<[u8; 32]>,
+    /// The Groth16 proofs of the Sapling spends, one per spend.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::seq"))]
+    pub spend_proofs_sapling: Vec<[u8; 192]>,
+    /// The spend authorization signatures of the Sapling spends, one per spend.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::seq"))]
+    pub spend_auth_sigs_sapling: Vec<[u8; 64]>,
+    /// The Groth16 proofs of the Sapling outputs, one per output.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::seq"))]
+    pub output_proofs_sapling: Vec<[u8; 192]>,
+    /// The Sapling binding signature, present if and only if there are Sapling spends or
+    /// outputs.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub binding_sig_sapling: Option<[u8; 64]>,
+
+    /// The Orchard action descriptions.
+    pub actions_orchard: Vec<ActionDescription>,
+    /// The Orchard flags, present if and only if there are Orchard actions, as are all of the
+    /// following Orchard fields.
+    pub flags_orchard: Option<u8>,
+    /// The net value of the Orchard spends minus outputs.
+    pub value_balance_orchard: Option<i64>,
+    /// The Orchard note commitment tree root.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub anchor_orchard: Option<[u8; 32]>,
+    /// The aggregated Halo 2 proof of the actions.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub proofs_orchard: Option<Vec<u8>>,
+    /// The spend authorization signatures of the actions, one per action.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option_seq"))]
+    pub auth_sigs_orchard: Option<Vec<[u8; 64]>>,
+    /// The Orchard binding signature.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes::option"))]
+    pub binding_sig_orchard: Option<[u8; 64]>,
+}
+
+impl TxV5 {
+    fn encode_with<B: BufMut>(&self, buffer: &mut B, missing: MissingFields) -> io::Result<()> {
+        buffer.put_u32_le(self.group_id);
+        buffer.put_u32_le(self.consensus_branch);
+        buffer.put_u32_le(self.lock_time);
+        buffer.put_u32_le(self.expiry_height);
+
+        self.tx_in.encode(buffer)?;
+        self.tx_out.encode(buffer)?;
+
+        self.spends_sapling.encode(buffer)?;
+        self.outputs_sapling.encode(buffer)?;
+
+        if self.spends_sapling.len() + self.outputs_sapling.len() > 0 {
+            buffer.put_i64_le(missing.field(self.value_balance_sapling, "value_balance_sapling")?);
+        }
+
+        if !self.spends_sapling.is_empty() {
+            buffer.put_slice(&missing.bytes(self.anchor_sapling, "anchor_sapling")?);
+        }
+
+        // Manually encode the contents of the `Vec` as it doesn't need the lenght prepended.
+        for proof in &self.spend_proofs_sapling {
+            buffer.put_slice(proof);
+        }
+
+        for auth_sig in &self.spend_auth_sigs_sapling {
+            buffer.put_slice(auth_sig);
+        }
+
+        for proof in &self.output_proofs_sapling {
+            buffer.put_slice(proof);
+        }
+
+        if self.spends_sapling.len() + self.outputs_sapling.len() > 0 {
+            buffer.put_slice(&missing.bytes(self.binding_sig_sapling, "binding_sig_sapling")?);
+        }
+
+        self.actions_orchard.encode(buffer)?;
+
+        if !self.actions_orchard.is_empty() {
+            buffer.put_u8(missing.field(self.flags_orchard, "flags_orchard")?);
+            buffer.put_i64_le(missing.field(self.value_balance_orchard, "value_balance_orchard")?);
+            buffer.put_slice(&missing.bytes(self.anchor_orchard, "anchor_orchard")?);
+
+            let proofs_orchard = missing.field(self.proofs_orchard.as_deref(), "proofs_orchard")?;
+            VarInt(proofs_orchard.len()).encode(buffer)?;
+            buffer.put_slice(proofs_orchard);
+
+            for auth_sig in missing.field(self.auth_sigs_orchard.as_deref(), "auth_sigs_orchard")? {
+                buffer.put_slice(auth_sig)
+            }
+
+            buffer.put_slice(&missing.bytes(self.binding_sig_orchard, "binding_sig_orchard")?);
+        }
+
+        Ok(())
+    }
+}
+
+impl Codec for TxV5 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.encode_with(buffer, MissingFields::Reject)
+    }
+
+    fn encoded_len(&self) -> usize {
+        let mut len = 4 + 4 + 4 + 4;
+
+        len += self.tx_in.encoded_len() + self.tx_out.encoded_len();
+        len += self.spends_sapling.encoded_len() + self.outputs_sapling.encoded_len();
+
+        if self.spends_sapling.len() + self.outputs_sapling.len() > 0 {
+            // The value balance and the binding signature.
+            len += 8 + 64;
+        }
+
+        if !self.spends_sapling.is_empty() {
+            // The anchor.
+            len += 32;
+        }
+
+        len += self.spend_proofs_sapling.len() * 192
+            + self.spend_auth_sigs_sapling.len() * 64
+            + self.output_proofs_sapling.len() * 192;
+
+        len += self.actions_orchard.encoded_len();
+
+        if !self.actions_orchard.is_empty() {
+            let proofs_len = self.proofs_orchard.as_ref().map_or(0, Vec::len);
+            let auth_sigs_len = self.auth_sigs_orchard.as_ref().map_or(0, Vec::len);
+
+            // The flags, value balance, anchor, proofs, signatures and binding signature.
+            len += 1 + 8 + 32;
+            len += VarInt(proofs_len).encoded_len() + proofs_len;
+            len += auth_sigs_len * 64 + 64;
+        }
+
+        len
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let group_id = u32::from_le_bytes(read_n_bytes(bytes).in_field("group_id")?);
+        let consensus_branch =
+            u32::from_le_bytes(read_n_bytes(bytes).in_field("consensus_branch")?);
+        let lock_time = u32::from_le_bytes(read_n_bytes(bytes).in_field("lock_time")?);
+        let expiry_height = u32::from_le_bytes(read_n_bytes(bytes).in_field("expiry_height")?);
+
+        let tx_in = Vec::<TxIn>::decode(bytes).in_field("tx_in")?;
+        let tx_out = Vec::<TxOut>::decode(bytes).in_field("tx_out")?;
+
+        let spends_sapling = Vec::<SpendDescriptionV5>::decode(bytes).in_field("spends_sapling")?;
+        let outputs_sapling =
+            Vec::<OutputDescriptionV5>::decode(bytes).in_field("outputs_sapling")?;
+
+        let value_balance_sapling = if spends_sapling.len() + outputs_sapling.len() > 0 {
+            Some(i64::from_le_bytes(
+                read_n_bytes(bytes).in_field("value_balance_sapling")?,
+            ))
+        } else {
+            None
+        };
+
+        let anchor_sapling = if !spends_sapling.is_empty() {
+            Some(read_n_bytes(bytes).in_field("anchor_sapling")?)
+        } else {
+            None
+        };
+
+        // Decode spend proofs sapling.
+        let mut spend_proofs_sapling = Vec::new();
+        for i in 0..spends_sapling.len() {
+            spend_proofs_sapling.push(
+                read_n_bytes(bytes)
+                    .at_index(i)
+                    .in_field("spend_proofs_sapling")?,
+            );
+        }
+
+        // Decode spend auth sigs.
+        let mut spend_auth_sigs_sapling = Vec::new();
+        for i in 0..spends_sapling.len() {
+            spend_auth_sigs_sapling.push(
+                read_n_bytes(bytes)
+                    .at_index(i)
+                    .in_field("spend_auth_sigs_sapling")?,
+            );
+        }
+
+        // Decode output proofs.
+        let mut output_proofs_sapling = Vec::new();
+        for i in 0..outputs_sapling.len() {
+            output_proofs_sapling.push(
+                read_n_bytes(bytes)
+                    .at_index(i)
+                    .in_field("output_proofs_sapling")?,
+            );
+        }
+
+        let binding_sig_sapling = if spends_sapling.len() + outputs_sapling.len() > 0 {
+            Some(read_n_bytes(bytes).in_field("binding_sig_sapling")?)
+        } else {
+            None
+        };
+
+        let actions_orchard =
+            Vec::<ActionDescription>::decode(bytes).in_field("actions_orchard")?;
+
+        let (
+            flags_orchard,
+            value_balance_orchard,
+            anchor_orchard,
+            proofs_orchard,
+            auth_sigs_orchard,
+            binding_sig_orchard,
+        ) = if !actions_orchard.is_empty() {
+            // Decode the orchard flags.
+            let flags_orchard = u8::from_le_bytes(read_n_bytes(bytes).in_field("flags_orchard")?);
+
+            // Decode the value balance.
+            let value_balance_orchard =
+                i64::from_le_bytes(read_n_bytes(bytes).in_field("value_balance_orchard")?);
+            let anchor_orchard = read_n_bytes(bytes).in_field("anchor_orchard")?;
+
+            // Decode the orchard proofs.
+            let n_proofs_orchard = VarInt::decode(bytes).in_field("proofs_orchard")?;
+
+            if bytes.remaining() < *n_proofs_orchard {
+                return Err(DecodeError::truncated(*n_proofs_orchard, bytes)
+                    .in_field("proofs_orchard")
+                    .into());
+            }
+
+            let mut proofs_orchard = Vec::new();
+            for _ in 0..*n_proofs_orchard {
+                proofs_orchard.push(bytes.get_u8());
+            }
+
+            // Decode orchard auth sigs.
+            let mut auth_sigs_orchard = Vec::new();
+            for i in 0..actions_orchard.len() {
+                auth_sigs_orchard.push(
+                    read_n_bytes(bytes)
+                        .at_index(i)
+                        .in_field("auth_sigs_orchard")?,
+                );
+            }
+
+            let binding_sig_orchard = read_n_bytes(bytes).in_field("binding_sig_orchard")?;
+
+            (
+                Some(flags_orchard),
+                Some(value_balance_orchard),
+                Some(anchor_orchard),
+                Some(proofs_orchard),
+                Some(auth_sigs_orchard),
+                Some(binding_sig_orchard),
+            )
+        } else {
+            (None, None, None, None, None, None)
+        };
+
+        Ok(Self {
+            group_id,
+            consensus_branch,
+            lock_time,
+            expiry_height,
+            tx_in,
+            tx_out,
+            spends_sapling,
+            outputs_sapling,
+            value_balance_sapling,
+            anchor_sapling,
+            spend_proofs_sapling,
+            spend_auth_sigs_sapling,
+            output_proofs_sapling,
+            binding_sig_sapling,
+            actions_orchard,
+            flags_orchard,
+            value_balance_orchard,
+            anchor_orchard,
+            proofs_orchard,
+            auth_sigs_orchard,
+            binding_sig_orchard,
+        })
+    }
+}
+
+/// A transparent input, spending the output of a previous transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "crate::protocol::payload::serialization::TxInFields")
+)]
+pub struct TxIn {
+    // Outpoint object (previous output transaction reference).
+    prev_out_hash: Hash,
+    prev_out_index: u32,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    script_len: VarInt,
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    script: Vec<u8>,
+
+    // Is currently unused in bitcoin, not sure about Zcash.
+    sequence: u32,
+}
+
+impl TxIn {
+    /// Creates an input spending output `prev_out_index` of the transaction `prev_out_hash`.
+    pub fn new(prev_out_hash: Hash, prev_out_index: u32, script: Vec<u8>, sequence: u32) -> Self {
+        Self {
+            prev_out_hash,
+            prev_out_index,
+            script_len: VarInt::new(script.len()),
+            script,
+            sequence,
+        }
+    }
+
+    /// Returns the txid of the transaction whose output is spent.
+    pub fn prev_out_hash(&self) -> Hash {
+        self.prev_out_hash
+    }
+
+    /// Returns the index of the spent output.
+    pub fn prev_out_index(&self) -> u32 {
+        self.prev_out_index
+    }
+
+    /// Returns the signature script.
+    pub fn script(&self) -> &[u8] {
+        &self.script
+    }
+
+    /// Returns the sequence number.
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    /// Replaces the signature script.
+    pub fn set_script(&mut self, script: Vec<u8>) {
+        self.script_len = VarInt::new(script.len());
+        self.script = script;
+    }
+}
+
+impl Codec for TxIn {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        self.prev_out_hash.encode(buffer)?;
+        buffer.put_u32_le(self.prev_out_index);
+
+        self.script_len.encode(buffer)?;
+        buffer.put_slice(&self.script);
+
+        buffer.put_u32_le(self.sequence);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.prev_out_hash.encoded_len() + 4 + self.script_len.encoded_len() + self.script.len() + 4
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let prev_out_hash = Hash::decode(bytes).in_field("prev_out_hash")?;
+        let prev_out_index = u32::from_le_bytes(read_n_bytes(bytes).in_field("prev_out_index")?);
+
+        let script_len = VarInt::decode(bytes).in_field("script_len")?;
+
+        if bytes.remaining() < script_len.0 {
+            return Err(DecodeError::truncated(script_len.0, bytes)
+                .in_field("script")
+                .into());
+        }
+
+        let mut script = vec![0u8; script_len.0];
+        bytes.copy_to_slice(&mut script);
+
+        let sequence = u32::from_le_bytes(read_n_bytes(bytes).in_field("sequence")?);
+
+        Ok(Self {
+            prev_out_hash,
+            prev_out_index,
+            script_len,
+            script,
+            sequence,
+        })
+    }
+}
+
+/// A transparent output.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(from = "crate::protocol::payload::serialization::TxOutFields")
+)]
+pub struct TxOut {
+    value: i64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pk_script_len: VarInt,
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pk_script: Vec<u8>,
+}
+
+impl TxOut {
+    /// Creates an output paying `value` zatoshis to the `pk_script`.
+    pub fn new(value: i64, pk_script: Vec<u8>) -> Self {
+        Self {
+            value,
+            pk_script_len: VarInt::new(pk_script.len()),
+            pk_script,
+        }
+    }
+
+    /// Returns the value in zatoshis.
+    pub fn value(&self) -> i64 {
+        self.value
+    }
+
+    /// Returns the public key script.
+    pub fn pk_script(&self) -> &[u8] {
+        &self.pk_script
+    }
+}
+
+impl Codec for TxOut {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_i64_le(self.value);
+        self.pk_script_len.encode(buffer)?;
+        buffer.put_slice(&self.pk_script);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        8 + self.pk_script_len.encoded_len() + self.pk_script.len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let value = i64::from_le_bytes(read_n_bytes(bytes).in_field("value")?);
+        let pk_script_len = VarInt::decode(bytes).in_field("pk_script_len")?;
+
+        if bytes.remaining() < pk_script_len.0 {
+            return Err(DecodeError::truncated(pk_script_len.0, bytes)
+                .in_field("pk_script")
+                .into());
+        }
+
+        let mut pk_script = vec![0u8; pk_script_len.0];
+        bytes.copy_to_slice(&mut pk_script);
+
+        Ok(Self {
+            value,
+            pk_script_len,
+            pk_script,
+        })
+    }
+}
+
+/// Returns the encoded length of the JoinSplit descriptions, along with the public key and
+/// signature which follow them when there are any.
+fn join_split_encoded_len(join_split: &[JoinSplit]) -> usize {
+    let len = VarInt(join_split.len()).encoded_len()
+        + join_split.iter().map(JoinSplit::encoded_len).sum::<usize>();
+
+    if join_split.is_empty() {
+        len
+    } else {
+        len + 32 + 64
+    }
+}
+
+/// A Sprout JoinSplit description.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct JoinSplit {
+    /// The value removed from the transparent value pool.
+    pub pub_old: u64,
+    /// The value added to the transparent value pool.
+    pub pub_new: u64,
+    /// The Sprout note commitment tree root.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub anchor: [u8; 32],
+    /// Two nullifiers, each 32 bytes long.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub nullifiers: [u8; 64],
+    /// Two note commitments, each 32 bytes long.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub commitments: [u8; 64],
+    /// The key used to derive the ciphertexts' encryption keys.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub ephemeral_key: [u8; 32],
+    /// The seed used to derive the signing key.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub random_seed: [u8; 32],
+    /// Two message authentication tags, each 32 bytes long.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub vmacs: [u8; 64],
+    /// BCTV14 or Groth16, depending on the transaction version.
+    pub zkproof: Zkproof,
+    /// Two ciphertexts, each 601 bytes long.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub enc_ciphertexts: [u8; 1202],
+}
+
+impl JoinSplit {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_u64_le(self.pub_old);
+        buffer.put_u64_le(self.pub_new);
+
+        buffer.put_slice(&self.anchor);
+        buffer.put_slice(&self.nullifiers);
+        buffer.put_slice(&self.commitments);
+        buffer.put_slice(&self.ephemeral_key);
+        buffer.put_slice(&self.random_seed);
+        buffer.put_slice(&self.vmacs);
+
+        self.zkproof.encode(buffer)?;
+        buffer.put_slice(&self.enc_ciphertexts);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        8 + 8
+            + self.anchor.len()
+            + self.nullifiers.len()
+            + self.commitments.len()
+            + self.ephemeral_key.len()
+            + self.random_seed.len()
+            + self.vmacs.len()
+            + self.zkproof.encoded_len()
+            + self.enc_ciphertexts.len()
+    }
+
+    fn decode_bctv14<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        // TODO: deduplicate (might require generics).
+        let pub_old = u64::from_le_bytes(read_n_bytes(bytes).in_field("pub_old")?);
+        let pub_new = u64::from_le_bytes(read_n_bytes(bytes).in_field("pub_new")?);
+
+        let anchor = read_n_bytes(bytes).in_field("anchor")?;
+        let nullifiers = read_n_bytes(bytes).in_field("nullifiers")?;
+        let commitments = read_n_bytes(bytes).in_field("commitments")?;
+        let ephemeral_key = read_n_bytes(bytes).in_field("ephemeral_key")?;
+        let random_seed = read_n_bytes(bytes).in_field("random_seed")?;
+        let vmacs = read_n_bytes(bytes).in_field("vmacs")?;
+
+        let zkproof = Zkproof::BCTV14(read_n_bytes(bytes).in_field("zkproof")?);
+        let enc_ciphertexts = read_n_bytes(bytes).in_field("enc_ciphertexts")?;
+
+        Ok(Self {
+            pub_old,
+            pub_new,
+            anchor,
+            nullifiers,
+            commitments,
+            ephemeral_key,
+            random_seed,
+            vmacs,
+            zkproof,
+            enc_ciphertexts,
+        })
+    }
+
+    fn decode_groth16<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let pub_old = u64::from_le_bytes(read_n_bytes(bytes).in_field("pub_old")?);
+        let pub_new = u64::from_le_bytes(read_n_bytes(bytes).in_field("pub_new")?);
+
+        let anchor = read_n_bytes(bytes).in_field("anchor")?;
+        let nullifiers = read_n_bytes(bytes).in_field("nullifiers")?;
+        let commitments = read_n_bytes(bytes).in_field("commitments")?;
+        let ephemeral_key = read_n_bytes(bytes).in_field("ephemeral_key")?;
+        let random_seed = read_n_bytes(bytes).in_field("random_seed")?;
+        let vmacs = read_n_bytes(bytes).in_field("vmacs")?;
+
+        let zkproof = Zkproof::Groth16(read_n_bytes(bytes).in_field("zkproof")?);
+        let enc_ciphertexts = read_n_bytes(bytes).in_field("enc_ciphertexts")?;
+
+        Ok(Self {
+            pub_old,
+            pub_new,
+            anchor,
+            nullifiers,
+            commitments,
+            ephemeral_key,
+            random_seed,
+            vmacs,
+            zkproof,
+            enc_ciphertexts,
+        })
+    }
+}
+
+/// The zero-knowledge proof of a [`JoinSplit`].
+// TODO: rethink abstraction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Zkproof {
+    /// Used by V2 and V3 transactions.
+    BCTV14(#[cfg_attr(feature = "serde", serde(with = "hex_bytes"))] [u8; 296]),
+    /// Used by V4 transactions.
+    Groth16(#[cfg_attr(feature = "serde", serde(with = "hex_bytes"))] [u8; 192]),
+}
+
+impl Zkproof {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        match self {
+            Self::BCTV14(bytes) => buffer.put_slice(bytes),
+            Self::Groth16(bytes) => buffer.put_slice(bytes),
+        }
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        match self {
+            Self::BCTV14(bytes) => bytes.len(),
+            Self::Groth16(bytes) => bytes.len(),
+        }
+    }
+}
+
+/// A Sapling spend description of a V4 transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpendDescriptionV4 {
+    /// The value commitment of the spent note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cv: [u8; 32],
+    /// The Sapling note commitment tree root.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub anchor: [u8; 32],
+    /// The nullifier of the spent note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub nullifier: [u8; 32],
+    /// The randomized public key for the spend authorization signature.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub rk: [u8; 32],
+    /// The Groth16 proof.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub zkproof: [u8; 192],
+    /// The spend authorization signature.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub spend_auth_sig: [u8; 64],
+}
+
+impl Codec for SpendDescriptionV4 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_slice(&self.cv);
+        buffer.put_slice(&self.anchor);
+        buffer.put_slice(&self.nullifier);
+        buffer.put_slice(&self.rk);
+        buffer.put_slice(&self.zkproof);
+        buffer.put_slice(&self.spend_auth_sig);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.cv.len()
+            + self.anchor.len()
+            + self.nullifier.len()
+            + self.rk.len()
+            + self.zkproof.len()
+            + self.spend_auth_sig.len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let cv = read_n_bytes(bytes).in_field("cv")?;
+        let anchor = read_n_bytes(bytes).in_field("anchor")?;
+        let nullifier = read_n_bytes(bytes).in_field("nullifier")?;
+        let rk = read_n_bytes(bytes).in_field("rk")?;
+        let zkproof = read_n_bytes(bytes).in_field("zkproof")?;
+        let spend_auth_sig = read_n_bytes(bytes).in_field("spend_auth_sig")?;
+
+        Ok(Self {
+            cv,
+            anchor,
+            nullifier,
+            rk,
+            zkproof,
+            spend_auth_sig,
+        })
+    }
+}
+
+/// A Sapling spend description of a V5 transaction, whose anchor, proof and signature are stored
+/// in the transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpendDescriptionV5 {
+    /// The value commitment of the spent note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cv: [u8; 32],
+    /// The nullifier of the spent note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub nullifier: [u8; 32],
+    /// The randomized public key for the spend authorization signature.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub rk: [u8; 32],
+}
+
+impl Codec for SpendDescriptionV5 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_slice(&self.cv);
+        buffer.put_slice(&self.nullifier);
+        buffer.put_slice(&self.rk);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.cv.len() + self.nullifier.len() + self.rk.len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let cv = read_n_bytes(bytes).in_field("cv")?;
+        let nullifier = read_n_bytes(bytes).in_field("nullifier")?;
+        let rk = read_n_bytes(bytes).in_field("rk")?;
+
+        Ok(Self { cv, nullifier, rk })
+    }
+}
+
+/// A Sapling output description of a V4 transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OutputDescriptionV4 {
+    /// The value commitment of the output note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cv: [u8; 32],
+    /// The commitment of the output note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cmu: [u8; 32],
+    /// The key used to derive the ciphertexts' encryption keys.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub ephemeral_key: [u8; 32],
+    /// The encrypted note plaintext.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub enc_ciphertext: [u8; 580],
+    /// The ciphertext allowing the sender to recover the note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub out_ciphertext: [u8; 80],
+    /// The Groth16 proof.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub zkproof: [u8; 192],
+}
+
+impl Codec for OutputDescriptionV4 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_slice(&self.cv);
+        buffer.put_slice(&self.cmu);
+        buffer.put_slice(&self.ephemeral_key);
+        buffer.put_slice(&self.enc_ciphertext);
+        buffer.put_slice(&self.out_ciphertext);
+        buffer.put_slice(&self.zkproof);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.cv.len()
+            + self.cmu.len()
+            + self.ephemeral_key.len()
+            + self.enc_ciphertext.len()
+            + self.out_ciphertext.len()
+            + self.zkproof.len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let cv = read_n_bytes(bytes).in_field("cv")?;
+        let cmu = read_n_bytes(bytes).in_field("cmu")?;
+        let ephemeral_key = read_n_bytes(bytes).in_field("ephemeral_key")?;
+        let enc_ciphertext = read_n_bytes(bytes).in_field("enc_ciphertext")?;
+        let out_ciphertext = read_n_bytes(bytes).in_field("out_ciphertext")?;
+        let zkproof = read_n_bytes(bytes).in_field("zkproof")?;
+
+        Ok(Self {
+            cv,
+            cmu,
+            ephemeral_key,
+            enc_ciphertext,
+            out_ciphertext,
+            zkproof,
+        })
+    }
+}
+
+/// A Sapling output description of a V5 transaction, whose proof is stored in the transaction.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OutputDescriptionV5 {
+    /// The value commitment of the output note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cv: [u8; 32],
+    /// The commitment of the output note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cmu: [u8; 32],
+    /// The key used to derive the ciphertexts' encryption keys.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub ephemeral_key: [u8; 32],
+    /// The encrypted note plaintext.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub enc_ciphertext: [u8; 580],
+    /// The ciphertext allowing the sender to recover the note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub out_ciphertext: [u8; 80],
+}
+
+impl Codec for OutputDescriptionV5 {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_slice(&self.cv);
+        buffer.put_slice(&self.cmu);
+        buffer.put_slice(&self.ephemeral_key);
+        buffer.put_slice(&self.enc_ciphertext);
+        buffer.put_slice(&self.out_ciphertext);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.cv.len()
+            + self.cmu.len()
+            + self.ephemeral_key.len()
+            + self.enc_ciphertext.len()
+            + self.out_ciphertext.len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let cv = read_n_bytes(bytes).in_field("cv")?;
+        let cmu = read_n_bytes(bytes).in_field("cmu")?;
+        let ephemeral_key = read_n_bytes(bytes).in_field("ephemeral_key")?;
+        let enc_ciphertext = read_n_bytes(bytes).in_field("enc_ciphertext")?;
+        let out_ciphertext = read_n_bytes(bytes).in_field("out_ciphertext")?;
+
+        Ok(Self {
+            cv,
+            cmu,
+            ephemeral_key,
+            enc_ciphertext,
+            out_ciphertext,
+        })
+    }
+}
+
+/// An Orchard action description, spending one note and creating another.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ActionDescription {
+    /// The value commitment of the spent note minus the output note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cv: [u8; 32],
+    /// The nullifier of the spent note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub nullifier: [u8; 32],
+    /// The randomized public key for the spend authorization signature.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub rk: [u8; 32],
+    /// The x-coordinate of the output note's commitment.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub cmx: [u8; 32],
+    /// The key used to derive the ciphertexts' encryption keys.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub ephemeral_key: [u8; 32],
+    /// The encrypted note plaintext.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub enc_ciphertext: [u8; 580],
+    /// The ciphertext allowing the sender to recover the note.
+    #[cfg_attr(feature = "serde", serde(with = "hex_bytes"))]
+    pub out_ciphertext: [u8; 80],
+}
+
+impl Codec for ActionDescription {
+    fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
+        buffer.put_slice(&self.cv);
+        buffer.put_slice(&self.nullifier);
+        buffer.put_slice(&self.rk);
+        buffer.put_slice(&self.cmx);
+        buffer.put_slice(&self.ephemeral_key);
+        buffer.put_slice(&self.enc_ciphertext);
+        buffer.put_slice(&self.out_ciphertext);
+
+        Ok(())
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.cv.len()
+            + self.nullifier.len()
+            + self.rk.len()
+            + self.cmx.len()
+            + self.ephemeral_key.len()
+            + self.enc_ciphertext.len()
+            + self.out_ciphertext.len()
+    }
+
+    fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
+        let cv = read_n_bytes(bytes).in_field("cv")?;
+        let nullifier = read_n_bytes(bytes).in_field("nullifier")?;
+        let rk = read_n_bytes(bytes).in_field("rk")?;
+        let cmx = read_n_bytes(bytes).in_field("cmx")?;
+        let ephemeral_key = read_n_bytes(bytes).in_field("ephemeral_key")?;
+        let enc_ciphertext = read_n_bytes(bytes).in_field("enc_ciphertext")?;
+        let out_ciphertext = read_n_bytes(bytes).in_field("out_ciphertext")?;
+
+        Ok(Self {
+            cv,
+            nullifier,
+            rk,
+            cmx,
+            ephemeral_key,
+            enc_ciphertext,
+            out_ciphertext,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use io::Cursor;
+
+    use super::*;
+
+    #[test]
+    #[ignore]
+    fn empty_transaction_v1_round_trip() {
+        let tx_v1 = Tx::V1(TxV1 {
+            tx_in: Vec::new(),
+            tx_out: Vec::new(),
+            lock_time: 500_000_000,
+        });
+
+        let mut bytes = Vec::new();
+        tx_v1.encode(&mut bytes).unwrap();
+
+        assert_eq!(tx_v1, Tx::decode(&mut Cursor::new(&bytes)).unwrap());
+    }
+
+    #[test]
+    #[ignore]
+    fn empty_transaction_v2_round_trip() {
+        let tx_v2 = Tx::V2(TxV2 {
+            tx_in: Vec::new(),
+            tx_out: Vec::new(),
+            lock_time: 500_000_000,
+            join_split: Vec::new(),
+            join_split_pub_key: None,
+            join_split_sig: None,
+        });
+
+        let mut bytes = Vec::new();
+        tx_v2.encode(&mut bytes).unwrap();
+
+        assert_eq!(tx_v2, Tx::decode(&mut Cursor::new(&bytes)).unwrap());
+    }
+
+    #[test]
+    #[ignore]
+    fn empty_transaction_v3_round_trip() {
+        let tx_v3 = Tx::V3(TxV3 {
+            group_id: 0,
+            tx_in: Vec::new(),
+            tx_out: Vec::new(),
+            lock_time: 500_000_000,
+            expiry_height: 500_000_000,
+            join_split: Vec::new(),
+            join_split_pub_key: None,
+            join_split_sig: None,
+        });
+
+        let mut bytes = Vec::new();
+        tx_v3.encode(&mut bytes).unwrap();
+
+        assert_eq!(tx_v3, Tx::decode(&mut Cursor::new(&bytes)).unwrap());
+    }
+
+    #[test]
+    #[ignore]
+    fn empty_transaction_v4_round_trip() {
+        let tx_v4 = Tx::V4(TxV4 {
+            group_id: 0,
+            tx_in: Vec::new(),
+            tx_out: Vec::new(),
+            lock_time: 500_000_000,
+            expiry_height: 500_000_000,
+            value_balance_sapling: 0,
+            spends_sapling: Vec::new(),
+            outputs_sapling: Vec::new(),
+            join_split: Vec::new(),
+            join_split_pub_key: None,
+            join_split_sig: None,
+            binding_sig_sapling: None,
+        });
+
+        let mut bytes = Vec::new();
+        tx_v4.encode(&mut bytes).unwrap();
+
+        assert_eq!(tx_v4, Tx::decode(&mut Cursor::new(&bytes)).unwrap());
+    }
+
+    #[test]
+    #[ignore]
+    fn empty_transaction_v5_round_trip() {
+        let tx_v5 = Tx::V5(Box::new(TxV5 {
+            group_id: 0,
+            consensus_branch: 0,
+            lock_time: 500_000_000,
+            expiry_height: 500_000_000,
+            tx_in: Vec::new(),
+            tx_out: Vec::new(),
+            spends_sapling: Vec::new(),
+            outputs_sapling: Vec::new(),
+            value_balance_sapling: None,
+            anchor_sapling: None,
+            spend_proofs_sapling: Vec::new(),
+            spend_auth_sigs_sapling: Vec::new(),
+            output_proofs_sapling: Vec::new(),
+            binding_sig_sapling: None,
+            actions_orchard: Vec::new(),
+            flags_orchard: None,
+            value_balance_orchard: None,
+            anchor_orchard: None,
+            proofs_orchard: None,
+            auth_sigs_orchard: None,
+            binding_sig_orchard: None,
+        }));
+
+        let mut bytes = Vec::new();
+        tx_v5.encode(&mut bytes).unwrap();
+
+        assert_eq!(tx_v5, Tx::decode(&mut Cursor::new(&bytes)).unwrap());
+    }
+
+    #[test]
+    fn transaction_built_field_by_field_round_trip() {
+        let tx_v4 = Tx::V4(TxV4 {
+            group_id: 0x892f_2085,
+            tx_in: vec![TxIn::new(Hash::new([1; 32]), 0, vec![0x51], u32::MAX)],
+            tx_out: vec![TxOut::new(50_000, vec![0x76, 0xa9])],
+            lock_time: 0,
+            expiry_height: 100,
+            value_balance_sapling: -10,
+            spends_sapling: Vec::new(),
+            outputs_sapling: vec![OutputDescriptionV4 {
+                cv: [2; 32],
+                cmu: [3; 32],
+                ephemeral_key: [4; 32],
+                enc_ciphertext: [5; 580],
+                out_ciphertext: [6; 80],
+                zkproof: [7; 192],
+            }],
+            join_split: Vec::new(),
+            join_split_pub_key: None,
+            join_split_sig: None,
+            binding_sig_sapling: Some([8; 64]),
+        });
+
+        let mut bytes = Vec::new();
+        tx_v4.encode(&mut bytes).unwrap();
+        let decoded = Tx::decode(&mut Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoded, tx_v4);
+
+        assert_eq!(decoded.version(), 4);
+        assert_eq!(decoded.expiry_height(), Some(100));
+        assert_eq!(decoded.tx_in()[0].prev_out_hash(), Hash::new([1; 32]));
+        assert_eq!(decoded.tx_in()[0].script(), &[0x51]);
+        assert_eq!(decoded.tx_out()[0].value(), 50_000);
+        assert_eq!(decoded.tx_out()[0].pk_script(), &[0x76, 0xa9]);
+    }
+
+    #[test]
+    fn join_split_signature_is_64_bytes() {
+        let tx_v2 = Tx::V2(TxV2 {
+            tx_in: Vec::new(),
+            tx_out: Vec::new(),
+            lock_time: 0,
+            join_split: vec![JoinSplit {
+                pub_old: 1,
+                pub_new: 2,
+                anchor: [0; 32],
+                nullifiers: [1; 64],
+                commitments: [2; 64],
+                ephemeral_key: [3; 32],
+                random_seed: [4; 32],
+                vmacs: [5; 64],
+                zkproof: Zkproof::BCTV14([6; 296]),
+                enc_ciphertexts: [7; 1202],
+            }],
+            join_split_pub_key: Some([8; 32]),
+            join_split_sig: Some([9; 64]),
+        });
+
+        let mut bytes = Vec::new();
+        tx_v2.encode(&mut bytes).unwrap();
+        // header, tx_in, tx_out, lock_time, join_split count, description, key and signature.
+        assert_eq!(bytes.len(), 4 + 1 + 1 + 4 + 1 + 1802 + 32 + 64);
+        assert_eq!(tx_v2, Tx::decode(&mut Cursor::new(&bytes)).unwrap());
+    }
+
+    #[test]
+    fn missing_fields_fail_to_encode() {
+        let missing = |tx: &Tx| {
+            let err = tx.encode(&mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            err.to_string()
+        };
+
+        let mut v2 = TxBuilder::new(2).with_join_splits(1).build().unwrap();
+        let mut v4 = TxBuilder::new(4).with_join_splits(1).build().unwrap();
+        let mut v4_sapling = TxBuilder::new(4).with_sapling_outputs(1).build().unwrap();
+        let mut v5 = TxBuilder::new(5).with_orchard_actions(1).build().unwrap();
+
+        if let Tx::V2(tx) = &mut v2 {
+            tx.join_split_pub_key = Some([0; 32]);
+            tx.join_split_sig = Some([0; 64]);
+        }
+        if let Tx::V4(tx) = &mut v4 {
+            tx.join_split_sig = Some([0; 64]);
+        }
+        if let Tx::V4(tx) = &mut v4_sapling {
+            tx.binding_sig_sapling = Some([0; 64]);
+        }
+        if let Tx::V5(tx) = &mut v5 {
+            tx.proofs_orchard = Some(Vec::new());
+        }
+        let zeroed = [v2, v4, v4_sapling, v5];
+
+        let mut broken = zeroed.clone();
+        if let Tx::V2(tx) = &mut broken[0] {
+            tx.join_split_pub_key = None;
+        }
+        if let Tx::V4(tx) = &mut broken[1] {
+            tx.join_split_sig = None;
+        }
+        if let Tx::V4(tx) = &mut broken[2] {
+            tx.binding_sig_sapling = None;
+        }
+        if let Tx::V5(tx) = &mut broken[3] {
+            tx.proofs_orchard = None;
+        }
+
+        assert_eq!(missing(&broken[0]), "missing join_split_pub_key");
+        assert_eq!(missing(&broken[1]), "missing join_split_sig");
+        assert_eq!(missing(&broken[2]), "missing binding_sig_sapling");
+        assert_eq!(missing(&broken[3]), "missing proofs_orchard");
+
+        // The fields left out are hashed and counted as zeros.
+        for (broken, zeroed) in broken.iter().zip(&zeroed) {
+            let mut bytes = Vec::new();
+            broken
+                .encode_with(&mut bytes, MissingFields::ZeroFill)
+                .unwrap();
+            assert_eq!(broken.encoded_len(), bytes.len());
+
+            assert_eq!(broken.txid(), zeroed.txid());
+            assert_eq!(broken.inv_hash(), zeroed.inv_hash());
+        }
+    }
+
+    #[test]
+    fn v5_coinbase_txid_is_the_merkle_root() {
+        use crate::{protocol::payload::block::Block, vectors::*};
+
+        // These blocks only contain their coinbase transaction, whose txid is the merkle root.
+        for block_bytes in [
+            &BLOCK_TESTNET_1_599_200_BYTES[..],
+            &BLOCK_TESTNET_1_599_201_BYTES[..],
+        ] {
+            let block = Block::decode(&mut Cursor::new(block_bytes)).unwrap();
+
+            assert_eq!(block.txs.len(), 1);
+            assert!(matches!(block.txs[0], Tx::V5(_)));
+            assert_eq!(block.txs[0].txid(), block.header.merkle_root);
+            assert_ne!(block.txs[0].txid(), block.txs[0].double_sha256().unwrap());
+        }
+    }
+
+    #[test]
+    fn inv_hash_uses_wtxid_for_v5() {
+        use crate::{protocol::payload::block::Block, vectors::*};
+
+        let block = Block::decode(&mut Cursor::new(&BLOCK_TESTNET_1_599_200_BYTES[..])).unwrap();
+        let tx = &block.txs[0];
+
+        let mut bytes = Vec::new();
+        tx.inv_hash().encode(&mut bytes).unwrap();
+
+        let mut expected = vec![5, 0, 0, 0];
+        tx.txid().encode(&mut expected).unwrap();
+        tx.auth_digest().encode(&mut expected).unwrap();
+        assert_eq!(bytes, expected);
+
+        let tx = &Block::testnet_1().txs[0];
+        assert_eq!(tx.inv_hash(), InvHash::new(ObjectKind::Tx, tx.txid()));
+        assert_eq!(tx.auth_digest(), Hash::new([0xff; 32]));
+    }
+}
@@ -0,0 +1,160 @@
+//! Signature digests for V4 transactions, see [ZIP-243](https://zips.z.cash/zip-0243).
+
+use crate::protocol::payload::{
+    tx::{
+        zip244::{encoded, finalize, hasher, personalization},
+        SigHashType, SignableInput, TxOut, TxV4,
+    },
+    Hash, VarInt,
+};
+
+/// The BLAKE2b-256 personalization prefix for the signature digest, followed by the consensus
+/// branch id.
+const SIGHASH_PERSONALIZATION_PREFIX: &[u8; 12] = b"ZcashSigHash";
+
+const PREVOUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZcashPrevoutHash";
+const SEQUENCE_HASH_PERSONALIZATION: &[u8; 16] = b"ZcashSequencHash";
+const OUTPUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZcashOutputsHash";
+const JOIN_SPLITS_HASH_PERSONALIZATION: &[u8; 16] = b"ZcashJSplitsHash";
+const SHIELDED_SPENDS_HASH_PERSONALIZATION: &[u8; 16] = b"ZcashSSpendsHash";
+const SHIELDED_OUTPUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZcashSOutputHash";
+
+impl TxV4 {
+    /// Computes the signature digest of the transparent `input`, or of the shielded components if
+    /// it's `None`. V4 transactions don't encode the consensus branch id they're valid in, it has
+    /// to be passed in.
+    pub(super) fn signature_digest(
+        &self,
+        hash_type: SigHashType,
+        input: Option<&SignableInput>,
+        spent_outputs: &[TxOut],
+        consensus_branch: u32,
+    ) -> Hash {
+        let anyone_can_pay = hash_type.is_anyone_can_pay();
+        let base = hash_type.base();
+
+        let mut h = hasher(&personalization(
+            SIGHASH_PERSONALIZATION_PREFIX,
+            consensus_branch,
+        ));
+        // The version field includes the overwintered flag.
+        h.update(&(4u32 | 1 << 31).to_le_bytes());
+        h.update(&self.group_id.to_le_bytes());
+
+        // Omitted parts are committed to as all zeroes.
+        h.update(&if anyone_can_pay {
+            [0; 32]
+        } else {
+            self.prevouts_hash()
+        });
+
+        h.update(&if anyone_can_pay || base != SigHashType::ALL {
+            [0; 32]
+        } else {
+            self.sequence_hash()
+        });
+
+        h.update(&match (base, input) {
+            (SigHashType::ALL, _) => outputs_hash(&self.tx_out),
+            (SigHashType::SINGLE, Some(input)) if input.index < self.tx_out.len() => {
+                outputs_hash(&self.tx_out[input.index..=input.index])
+            }
+            _ => [0; 32],
+        });
+
+        h.update(&self.join_splits_hash());
+        h.update(&self.shielded_spends_hash());
+        h.update(&self.shielded_outputs_hash());
+        h.update(&self.lock_time.to_le_bytes());
+        h.update(&self.expiry_height.to_le_bytes());
+        h.update(&self.value_balance_sapling.to_le_bytes());
+        h.update(&u32::from(hash_type.0).to_le_bytes());
+
+        if let Some(input) = input {
+            let tx_in = &self.tx_in[input.index];
+            h.update(&tx_in.prev_out_hash.0);
+            h.update(&tx_in.prev_out_index.to_le_bytes());
+            h.update(&encoded(&VarInt::new(input.script_code.len())));
+            h.update(input.script_code);
+            h.update(&spent_outputs[input.index].value.to_le_bytes());
+            h.update(&tx_in.sequence.to_le_bytes());
+        }
+
+        Hash::new(finalize(h))
+    }
+
+    fn prevouts_hash(&self) -> [u8; 32] {
+        let mut h = hasher(PREVOUTS_HASH_PERSONALIZATION);
+        for tx_in in &self.tx_in {
+            h.update(&tx_in.prev_out_hash.0);
+            h.update(&tx_in.prev_out_index.to_le_bytes());
+        }
+
+        finalize(h)
+    }
+
+    fn sequence_hash(&self) -> [u8; 32] {
+        let mut h = hasher(SEQUENCE_HASH_PERSONALIZATION);
+        for tx_in in &self.tx_in {
+            h.update(&tx_in.sequence.to_le_bytes());
+        }
+
+        finalize(h)
+    }
+
+    fn join_splits_hash(&self) -> [u8; 32] {
+        if self.join_split.is_empty() {
+            return [0; 32];
+        }
+
+        let mut h = hasher(JOIN_SPLITS_HASH_PERSONALIZATION);
+        for description in &self.join_split {
+            let mut buffer = Vec::new();
+            description.encode(&mut buffer).unwrap();
+            h.update(&buffer);
+        }
+        h.update(&self.join_split_pub_key.unwrap_or_default());
+
+        finalize(h)
+    }
+
+    fn shielded_spends_hash(&self) -> [u8; 32] {
+        if self.spends_sapling.is_empty() {
+            return [0; 32];
+        }
+
+        // The spend authorization signatures are left out.
+        let mut h = hasher(SHIELDED_SPENDS_HASH_PERSONALIZATION);
+        for spend in &self.spends_sapling {
+            h.update(&spend.cv);
+            h.update(&spend.anchor);
+            h.update(&spend.nullifier);
+            h.update(&spend.rk);
+            h.update(&spend.zkproof);
+        }
+
+        finalize(h)
+    }
+
+    fn shielded_outputs_hash(&self) -> [u8; 32] {
+        if self.outputs_sapling.is_empty() {
+            return [0; 32];
+        }
+
+        let mut h = hasher(SHIELDED_OUTPUTS_HASH_PERSONALIZATION);
+        for output in &self.outputs_sapling {
+            h.update(&encoded(output));
+        }
+
+        finalize(h)
+    }
+}
+
+fn outputs_hash(tx_out: &[TxOut]) -> [u8; 32] {
+    let mut h = hasher(OUTPUTS_HASH_PERSONALIZATION);
+    for tx_out in tx_out {
+        h.update(&encoded(tx_out));
+    }
+
+    finalize(h)
+}
@@ -0,0 +1,377 @@
+//! Transaction id, signature and authorizing data commitment digests for V5 transactions, see
+//! [ZIP-244](https://zips.z.cash/zip-0244).
+
+use std::convert::TryInto;
+
+use blake2b_simd::{Params, State};
+
+use crate::protocol::payload::{
+    codec::Codec,
+    tx::{is_coinbase, SigHashType, SignableInput, TxOut, TxV5},
+    Hash, VarInt,
+};
+
+/// The BLAKE2b-256 personalization prefix for the txid, followed by the consensus branch id.
+const TX_HASH_PERSONALIZATION_PREFIX: &[u8; 12] = b"ZcashTxHash_";
+/// The BLAKE2b-256 personalization prefix for the auth digest, followed by the consensus
+/// branch id.
+const TX_AUTH_PERSONALIZATION_PREFIX: &[u8; 12] = b"ZTxAuthHash_";
+
+const HEADERS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdHeadersHash";
+const TRANSPARENT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdTranspaHash";
+const PREVOUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdPrevoutHash";
+const SEQUENCE_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSequencHash";
+const OUTPUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdOutputsHash";
+const AMOUNTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxTrAmountsHash";
+const SCRIPTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxTrScriptsHash";
+const TX_IN_HASH_PERSONALIZATION: &[u8; 16] = b"Zcash___TxInHash";
+
+const SAPLING_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSaplingHash";
+const SAPLING_SPENDS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSSpendsHash";
+const SAPLING_SPENDS_COMPACT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSSpendCHash";
+const SAPLING_SPENDS_NONCOMPACT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSSpendNHash";
+const SAPLING_OUTPUTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSOutputHash";
+const SAPLING_OUTPUTS_COMPACT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSOutC__Hash";
+const SAPLING_OUTPUTS_MEMOS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSOutM__Hash";
+const SAPLING_OUTPUTS_NONCOMPACT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdSOutN__Hash";
+
+const ORCHARD_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdOrchardHash";
+const ORCHARD_ACTIONS_COMPACT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdOrcActCHash";
+const ORCHARD_ACTIONS_MEMOS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdOrcActMHash";
+const ORCHARD_ACTIONS_NONCOMPACT_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxIdOrcActNHash";
+
+const TRANSPARENT_SCRIPTS_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxAuthTransHash";
+const SAPLING_AUTH_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxAuthSapliHash";
+const ORCHARD_AUTH_HASH_PERSONALIZATION: &[u8; 16] = b"ZTxAuthOrchaHash";
+
+// Note plaintext ciphertexts are split into their compact (first 52 bytes), memo (next 512 bytes)
+// and remaining parts.
+const COMPACT_NOTE_SIZE: usize = 52;
+const MEMO_END: usize = COMPACT_NOTE_SIZE + 512;
+
+impl TxV5 {
+    /// Computes the transaction id.
+    pub(super) fn txid(&self) -> Hash {
+        let mut h = hasher(&personalization(
+            TX_HASH_PERSONALIZATION_PREFIX,
+            self.consensus_branch,
+        ));
+        h.update(&self.header_digest());
+        h.update(&self.transparent_digest());
+        h.update(&self.sapling_digest());
+        h.update(&self.orchard_digest());
+
+        Hash::new(finalize(h))
+    }
+
+    /// Computes the signature digest of the transparent `input`, or of the shielded components if
+    /// it's `None`. The `spent_outputs` are those spent by each transparent input.
+    pub(super) fn signature_digest(
+        &self,
+        hash_type: SigHashType,
+        input: Option<&SignableInput>,
+        spent_outputs: &[TxOut],
+    ) -> Hash {
+        let mut h = hasher(&personalization(
+            TX_HASH_PERSONALIZATION_PREFIX,
+            self.consensus_branch,
+        ));
+        h.update(&self.header_digest());
+        h.update(&self.transparent_sig_digest(hash_type, input, spent_outputs));
+        h.update(&self.sapling_digest());
+        h.update(&self.orchard_digest());
+
+        Hash::new(finalize(h))
+    }
+
+    /// Computes the authorizing data commitment.
+    pub(super) fn auth_digest(&self) -> Hash {
+        let mut h = hasher(&personalization(
+            TX_AUTH_PERSONALIZATION_PREFIX,
+            self.consensus_branch,
+        ));
+        h.update(&self.transparent_scripts_digest());
+        h.update(&self.sapling_auth_digest());
+        h.update(&self.orchard_auth_digest());
+
+        Hash::new(finalize(h))
+    }
+
+    fn header_digest(&self) -> [u8; 32] {
+        let mut h = hasher(HEADERS_HASH_PERSONALIZATION);
+        // The version field includes the overwintered flag.
+        h.update(&(5u32 | 1 << 31).to_le_bytes());
+        h.update(&self.group_id.to_le_bytes());
+        h.update(&self.consensus_branch.to_le_bytes());
+        h.update(&self.lock_time.to_le_bytes());
+        h.update(&self.expiry_height.to_le_bytes());
+
+        finalize(h)
+    }
+
+    fn transparent_digest(&self) -> [u8; 32] {
+        let mut h = hasher(TRANSPARENT_HASH_PERSONALIZATION);
+        if self.tx_in.is_empty() && self.tx_out.is_empty() {
+            return finalize(h);
+        }
+
+        h.update(&self.prevouts_digest());
+        h.update(&self.sequence_digest());
+        h.update(&outputs_digest(&self.tx_out));
+
+        finalize(h)
+    }
+
+    fn transparent_sig_digest(
+        &self,
+        hash_type: SigHashType,
+        input: Option<&SignableInput>,
+        spent_outputs: &[TxOut],
+    ) -> [u8; 32] {
+        // Without inputs to sign, the digest is the same as the txid's.
+        if self.tx_in.is_empty() || is_coinbase(&self.tx_in) {
+            return self.transparent_digest();
+        }
+
+        let mut amounts = hasher(AMOUNTS_HASH_PERSONALIZATION);
+        let mut scripts = hasher(SCRIPTS_HASH_PERSONALIZATION);
+        let (prevouts, sequence) = if hash_type.is_anyone_can_pay() {
+            (
+                finalize(hasher(PREVOUTS_HASH_PERSONALIZATION)),
+                finalize(hasher(SEQUENCE_HASH_PERSONALIZATION)),
+            )
+        } else {
+            for spent_output in spent_outputs {
+                amounts.update(&spent_output.value.to_le_bytes());
+                scripts.update(&encoded(&VarInt::new(spent_output.pk_script.len())));
+                scripts.update(&spent_output.pk_script);
+            }
+
+            (self.prevouts_digest(), self.sequence_digest())
+        };
+
+        let outputs = match (hash_type.base(), input) {
+            (SigHashType::ALL, _) => outputs_digest(&self.tx_out),
+            (SigHashType::SINGLE, Some(input)) if input.index < self.tx_out.len() => {
+                outputs_digest(&self.tx_out[input.index..=input.index])
+            }
+            _ => outputs_digest(&[]),
+        };
+
+        let mut tx_in_digest = hasher(TX_IN_HASH_PERSONALIZATION);
+        if let Some(input) = input {
+            let tx_in = &self.tx_in[input.index];
+            let spent_output = &spent_outputs[input.index];
+            tx_in_digest.update(&tx_in.prev_out_hash.0);
+            tx_in_digest.update(&tx_in.prev_out_index.to_le_bytes());
+            tx_in_digest.update(&spent_output.value.to_le_bytes());
+            tx_in_digest.update(&encoded(&VarInt::new(spent_output.pk_script.len())));
+            tx_in_digest.update(&spent_output.pk_script);
+            tx_in_digest.update(&tx_in.sequence.to_le_bytes());
+        }
+
+        let mut h = hasher(TRANSPARENT_HASH_PERSONALIZATION);
+        h.update(&[hash_type.0]);
+        h.update(&prevouts);
+        h.update(&finalize(amounts));
+        h.update(&finalize(scripts));
+        h.update(&sequence);
+        h.update(&outputs);
+        h.update(&finalize(tx_in_digest));
+
+        finalize(h)
+    }
+
+    fn prevouts_digest(&self) -> [u8; 32] {
+        let mut h = hasher(PREVOUTS_HASH_PERSONALIZATION);
+        for tx_in in &self.tx_in {
+            h.update(&tx_in.prev_out_hash.0);
+            h.update(&tx_in.prev_out_index.to_le_bytes());
+        }
+
+        finalize(h)
+    }
+
+    fn sequence_digest(&self) -> [u8; 32] {
+        let mut h = hasher(SEQUENCE_HASH_PERSONALIZATION);
+        for tx_in in &self.tx_in {
+            h.update(&tx_in.sequence.to_le_bytes());
+        }
+
+        finalize(h)
+    }
+
+    fn sapling_digest(&self) -> [u8; 32] {
+        let mut h = hasher(SAPLING_HASH_PERSONALIZATION);
+        if self.spends_sapling.is_empty() && self.outputs_sapling.is_empty() {
+            return finalize(h);
+        }
+
+        let mut spends = hasher(SAPLING_SPENDS_HASH_PERSONALIZATION);
+        if !self.spends_sapling.is_empty() {
+            let mut compact = hasher(SAPLING_SPENDS_COMPACT_HASH_PERSONALIZATION);
+            let mut noncompact = hasher(SAPLING_SPENDS_NONCOMPACT_HASH_PERSONALIZATION);
+            // The anchor is shared by all spends.
+            let anchor = self.anchor_sapling.unwrap_or_default();
+            for spend in &self.spends_sapling {
+                compact.update(&spend.nullifier);
+                noncompact.update(&spend.cv);
+                noncompact.update(&anchor);
+                noncompact.update(&spend.rk);
+            }
+
+            spends.update(&finalize(compact));
+            spends.update(&finalize(noncompact));
+        }
+
+        let mut outputs = hasher(SAPLING_OUTPUTS_HASH_PERSONALIZATION);
+        if !self.outputs_sapling.is_empty() {
+            let mut compact = hasher(SAPLING_OUTPUTS_COMPACT_HASH_PERSONALIZATION);
+            let mut memos = hasher(SAPLING_OUTPUTS_MEMOS_HASH_PERSONALIZATION);
+            let mut noncompact = hasher(SAPLING_OUTPUTS_NONCOMPACT_HASH_PERSONALIZATION);
+            for output in &self.outputs_sapling {
+                compact.update(&output.cmu);
+                compact.update(&output.ephemeral_key);
+                compact.update(&output.enc_ciphertext[..COMPACT_NOTE_SIZE]);
+
+                memos.update(&output.enc_ciphertext[COMPACT_NOTE_SIZE..MEMO_END]);
+
+                noncompact.update(&output.cv);
+                noncompact.update(&output.enc_ciphertext[MEMO_END..]);
+                noncompact.update(&output.out_ciphertext);
+            }
+
+            outputs.update(&finalize(compact));
+            outputs.update(&finalize(memos));
+            outputs.update(&finalize(noncompact));
+        }
+
+        h.update(&finalize(spends));
+        h.update(&finalize(outputs));
+        h.update(&self.value_balance_sapling.unwrap_or_default().to_le_bytes());
+
+        finalize(h)
+    }
+
+    fn orchard_digest(&self) -> [u8; 32] {
+        let mut h = hasher(ORCHARD_HASH_PERSONALIZATION);
+        if self.actions_orchard.is_empty() {
+            return finalize(h);
+        }
+
+        let mut compact = hasher(ORCHARD_ACTIONS_COMPACT_HASH_PERSONALIZATION);
+        let mut memos = hasher(ORCHARD_ACTIONS_MEMOS_HASH_PERSONALIZATION);
+        let mut noncompact = hasher(ORCHARD_ACTIONS_NONCOMPACT_HASH_PERSONALIZATION);
+        for action in &self.actions_orchard {
+            compact.update(&action.nullifier);
+            compact.update(&action.cmx);
+            compact.update(&action.ephemeral_key);
+            compact.update(&action.enc_ciphertext[..COMPACT_NOTE_SIZE]);
+
+            memos.update(&action.enc_ciphertext[COMPACT_NOTE_SIZE..MEMO_END]);
+
+            noncompact.update(&action.cv);
+            noncompact.update(&action.rk);
+            noncompact.update(&action.enc_ciphertext[MEMO_END..]);
+            noncompact.update(&action.out_ciphertext);
+        }
+
+        h.update(&finalize(compact));
+        h.update(&finalize(memos));
+        h.update(&finalize(noncompact));
+        h.update(&[self.flags_orchard.unwrap_or_default()]);
+        h.update(&self.value_balance_orchard.unwrap_or_default().to_le_bytes());
+        h.update(&self.anchor_orchard.unwrap_or_default());
+
+        finalize(h)
+    }
+
+    fn transparent_scripts_digest(&self) -> [u8; 32] {
+        let mut h = hasher(TRANSPARENT_SCRIPTS_HASH_PERSONALIZATION);
+        for tx_in in &self.tx_in {
+            h.update(&encoded(&VarInt::new(tx_in.script.len())));
+            h.update(&tx_in.script);
+        }
+
+        finalize(h)
+    }
+
+    fn sapling_auth_digest(&self) -> [u8; 32] {
+        let mut h = hasher(SAPLING_AUTH_HASH_PERSONALIZATION);
+        if self.spends_sapling.is_empty() && self.outputs_sapling.is_empty() {
+            return finalize(h);
+        }
+
+        for proof in &self.spend_proofs_sapling {
+            h.update(proof);
+        }
+
+        for auth_sig in &self.spend_auth_sigs_sapling {
+            h.update(auth_sig);
+        }
+
+        for proof in &self.output_proofs_sapling {
+            h.update(proof);
+        }
+
+        h.update(&self.binding_sig_sapling.unwrap_or([0; 64]));
+
+        finalize(h)
+    }
+
+    fn orchard_auth_digest(&self) -> [u8; 32] {
+        let mut h = hasher(ORCHARD_AUTH_HASH_PERSONALIZATION);
+        if self.actions_orchard.is_empty() {
+            return finalize(h);
+        }
+
+        if let Some(proofs) = &self.proofs_orchard {
+            h.update(proofs);
+        }
+
+        for auth_sig in self.auth_sigs_orchard.iter().flatten() {
+            h.update(auth_sig);
+        }
+
+        h.update(&self.binding_sig_orchard.unwrap_or([0; 64]));
+
+        finalize(h)
+    }
+}
+
+fn outputs_digest(tx_out: &[TxOut]) -> [u8; 32] {
+    let mut h = hasher(OUTPUTS_HASH_PERSONALIZATION);
+    for tx_out in tx_out {
+        h.update(&encoded(tx_out));
+    }
+
+    finalize(h)
+}
+
+/// Returns a BLAKE2b-256 state with the given personalization.
+pub(super) fn hasher(personalization: &[u8; 16]) -> State {
+    Params::new()
+        .hash_length(32)
+        .personal(personalization)
+        .to_state()
+}
+
+pub(super) fn finalize(state: State) -> [u8; 32] {
+    state.finalize().as_bytes().try_into().unwrap()
+}
+
+/// Appends the little-endian consensus branch id to the personalization prefix.
+pub(super) fn personalization(prefix: &[u8; 12], consensus_branch: u32) -> [u8; 16] {
+    let mut personalization = [0u8; 16];
+    personalization[..12].copy_from_slice(prefix);
+    personalization[12..].copy_from_slice(&consensus_branch.to_le_bytes());
+
+    personalization
+}
+
+pub(super) fn encoded<T: Codec>(value: &T) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    value.encode(&mut buffer).unwrap();
+
+    buffer
+}
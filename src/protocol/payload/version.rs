@@ -6,18 +6,26 @@ use bytes::{Buf, BufMut};
 use time::OffsetDateTime;
 
 use crate::protocol::payload::{
-    addr::NetworkAddr, codec::Codec, read_n_bytes, read_timestamp, Nonce, ProtocolVersion, VarStr,
+    addr::NetworkAddr,
+    codec::{Codec, DecodeContext},
+    read_n_bytes, Nonce, ProtocolVersion, Services, VarStr,
 };
 
+#[cfg(feature = "serde")]
+use crate::protocol::payload::serialization::unix_timestamp;
+
 /// A version payload.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Version {
     /// The protocol version of the sender.
     pub version: ProtocolVersion,
     /// The services supported by the sender.
-    pub services: u64,
-    /// The timestamp of the message.
-    pub timestamp: OffsetDateTime,
+    pub services: Services,
+    /// The timestamp of the message, in seconds since the Unix epoch. Kept as the raw value so
+    /// out-of-range timestamps can be both sent and received.
+    #[cfg_attr(feature = "serde", serde(with = "unix_timestamp"))]
+    pub timestamp: i64,
     /// The receiving address of the message.
     pub addr_recv: NetworkAddr,
     /// The sender of the message.
@@ -28,35 +36,31 @@ pub struct Version {
     pub user_agent: VarStr,
     /// The start last block received by the sender.
     pub start_height: u32,
-    /// Specifies if the receiver should relay transactions.
-    pub relay: bool,
+    /// Specifies if the receiver should relay transactions, `None` if the field is omitted as
+    /// done by peers predating [BIP-37](https://github.com/bitcoin/bips/blob/master/bip-0037.mediawiki).
+    pub relay: Option<bool>,
 }
 
 impl Version {
     /// Constructs a `Version`, where `addr_recv` is the remote `zcashd`/`zebra` node address and
     /// `addr_from` is our local node address.
     pub fn new(addr_recv: SocketAddr, addr_from: SocketAddr) -> Self {
-        Self {
-            version: ProtocolVersion::current(),
-            services: 1,
-            timestamp: OffsetDateTime::now_utc(),
-            addr_recv: NetworkAddr {
-                last_seen: None,
-                services: 1,
-                addr: addr_recv,
-            },
-            addr_from: NetworkAddr {
-                last_seen: None,
-                services: 1,
-                addr: addr_from,
-            },
-            nonce: Nonce::default(),
-            user_agent: VarStr(String::from("")),
-            start_height: 0,
-            relay: false,
+        VersionBuilder::new(addr_recv, addr_from).build()
+    }
+
+    /// Returns a [`VersionBuilder`] initialised with this message's fields, useful for deriving a
+    /// message from a template.
+    pub fn to_builder(&self) -> VersionBuilder {
+        VersionBuilder {
+            version: self.clone(),
         }
     }
 
+    /// Returns the timestamp as a date, `None` if it's out of range.
+    pub fn timestamp_utc(&self) -> Option<OffsetDateTime> {
+        OffsetDateTime::from_unix_timestamp(self.timestamp).ok()
+    }
+
     /// Sets the protocol version.
     pub fn with_version(mut self, version: u32) -> Self {
         self.version = ProtocolVersion(version);
@@ -67,8 +71,8 @@ impl Version {
 impl Codec for Version {
     fn encode<B: BufMut>(&self, buffer: &mut B) -> io::Result<()> {
         self.version.encode(buffer)?;
-        buffer.put_u64_le(self.services);
-        buffer.put_i64_le(self.timestamp.unix_timestamp());
+        buffer.put_u64_le(self.services.bits());
+        buffer.put_i64_le(self.timestamp);
 
         self.addr_recv.encode_without_timestamp(buffer)?;
         self.addr_from.encode_without_timestamp(buffer)?;
@@ -76,24 +80,45 @@ impl Codec for Version {
         self.nonce.encode(buffer)?;
         self.user_agent.encode(buffer)?;
         buffer.put_u32_le(self.start_height);
-        buffer.put_u8(self.relay as u8);
+        if let Some(relay) = self.relay {
+            buffer.put_u8(relay as u8);
+        }
 
         Ok(())
     }
 
+    fn encoded_len(&self) -> usize {
+        self.version.encoded_len()
+            + 8
+            + 8
+            + self.addr_recv.encoded_len_without_timestamp()
+            + self.addr_from.encoded_len_without_timestamp()
+            + self.nonce.encoded_len()
+            + self.user_agent.encoded_len()
+            + 4
+            + self.relay.map_or(0, |_| 1)
+    }
+
     fn decode<B: Buf>(bytes: &mut B) -> io::Result<Self> {
-        let version = ProtocolVersion::decode(bytes)?;
-        let services = u64::from_le_bytes(read_n_bytes(bytes)?);
-        let timestamp = read_timestamp(bytes)?;
+        let version = ProtocolVersion::decode(bytes).in_field("version")?;
+        let services = Services::from_bits_retain(u64::from_le_bytes(
+            read_n_bytes(bytes).in_field("services")?,
+        ));
+        let timestamp = i64::from_le_bytes(read_n_bytes(bytes).in_field("timestamp")?);
 
-        let addr_recv = NetworkAddr::decode_without_timestamp(bytes)?;
-        let addr_from = NetworkAddr::decode_without_timestamp(bytes)?;
+        let addr_recv = NetworkAddr::decode_without_timestamp(bytes).in_field("addr_recv")?;
+        let addr_from = NetworkAddr::decode_without_timestamp(bytes).in_field("addr_from")?;
 
-        let nonce = Nonce::decode(bytes)?;
-        let user_agent = VarStr::decode(bytes)?;
+        let nonce = Nonce::decode(bytes).in_field("nonce")?;
+        let user_agent = VarStr::decode(bytes).in_field("user_agent")?;
 
-        let start_height = u32::from_le_bytes(read_n_bytes(bytes)?);
-        let relay = u8::from_le_bytes(read_n_bytes(bytes)?) != 0;
+        let start_height = u32::from_le_bytes(read_n_bytes(bytes).in_field("start_height")?);
+        // Legacy peers end the message after the start height.
+        let relay = if bytes.has_remaining() {
+            Some(u8::from_le_bytes(read_n_bytes(bytes).in_field("relay")?) != 0)
+        } else {
+            None
+        };
 
         Ok(Self {
             version,
@@ -108,3 +133,163 @@ impl Codec for Version {
         })
     }
 }
+
+/// Builds a [`Version`] field by field, including values a well-behaved node would never send.
+#[derive(Debug, Clone)]
+pub struct VersionBuilder {
+    version: Version,
+}
+
+impl VersionBuilder {
+    /// Starts a builder with the same defaults as [`Version::new`]: the current protocol version,
+    /// `NODE_NETWORK` services, the current time, a random nonce, an empty user agent, a start
+    /// height of 0 and `relay` set to `false`.
+    pub fn new(addr_recv: SocketAddr, addr_from: SocketAddr) -> Self {
+        Self {
+            version: Version {
+                version: ProtocolVersion::current(),
+                services: Services::NODE_NETWORK,
+                timestamp: OffsetDateTime::now_utc().unix_timestamp(),
+                addr_recv: NetworkAddr {
+                    last_seen: None,
+                    services: Services::NODE_NETWORK,
+                    addr: addr_recv,
+                },
+                addr_from: NetworkAddr {
+                    last_seen: None,
+                    services: Services::NODE_NETWORK,
+                    addr: addr_from,
+                },
+                nonce: Nonce::default(),
+                user_agent: VarStr(String::new()),
+                start_height: 0,
+                relay: Some(false),
+            },
+        }
+    }
+
+    /// Sets the protocol version.
+    pub fn version(mut self, version: u32) -> Self {
+        self.version.version = ProtocolVersion(version);
+        self
+    }
+
+    /// Sets the services advertised for the sender, in both `services` and `addr_from`.
+    pub fn services(mut self, services: Services) -> Self {
+        self.version.services = services;
+        self.version.addr_from.services = services;
+        self
+    }
+
+    /// Sets the timestamp.
+    pub fn timestamp(mut self, timestamp: OffsetDateTime) -> Self {
+        self.version.timestamp = timestamp.unix_timestamp();
+        self
+    }
+
+    /// Sets the raw timestamp, which may be outside of the range of a valid date.
+    pub fn unix_timestamp(mut self, timestamp: i64) -> Self {
+        self.version.timestamp = timestamp;
+        self
+    }
+
+    /// Sets the receiving address.
+    pub fn addr_recv(mut self, addr_recv: NetworkAddr) -> Self {
+        self.version.addr_recv = addr_recv;
+        self
+    }
+
+    /// Sets the sender address.
+    pub fn addr_from(mut self, addr_from: NetworkAddr) -> Self {
+        self.version.addr_from = addr_from;
+        self
+    }
+
+    /// Sets the nonce, e.g. to reuse one from another message.
+    pub fn nonce(mut self, nonce: Nonce) -> Self {
+        self.version.nonce = nonce;
+        self
+    }
+
+    /// Sets the user agent.
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.version.user_agent = VarStr(user_agent.into());
+        self
+    }
+
+    /// Sets the height of the last block received by the sender.
+    pub fn start_height(mut self, start_height: u32) -> Self {
+        self.version.start_height = start_height;
+        self
+    }
+
+    /// Sets the relay flag, `None` omits it from the message as legacy peers do.
+    pub fn relay(mut self, relay: Option<bool>) -> Self {
+        self.version.relay = relay;
+        self
+    }
+
+    /// Returns the built [`Version`].
+    pub fn build(self) -> Version {
+        self.version
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs() -> (SocketAddr, SocketAddr) {
+        (
+            "127.0.0.1:8233".parse().unwrap(),
+            "127.0.0.1:18233".parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn builder_round_trip() {
+        let (addr_recv, addr_from) = addrs();
+        let version = VersionBuilder::new(addr_recv, addr_from)
+            .version(170_002)
+            .services(Services::empty())
+            .unix_timestamp(i64::MAX)
+            .nonce(Nonce::new(42))
+            .user_agent("/test:0.1/")
+            .start_height(1_000_000)
+            .relay(Some(true))
+            .build();
+
+        assert_eq!(version.timestamp_utc(), None);
+        assert_eq!(version.addr_from.services, Services::empty());
+
+        let mut bytes = Vec::new();
+        version.encode(&mut bytes).unwrap();
+        assert_eq!(Version::decode(&mut bytes.as_slice()).unwrap(), version);
+    }
+
+    #[test]
+    fn decode_without_relay() {
+        let (addr_recv, addr_from) = addrs();
+        let version = VersionBuilder::new(addr_recv, addr_from)
+            .relay(None)
+            .build();
+
+        let mut bytes = Vec::new();
+        version.encode(&mut bytes).unwrap();
+        let relay_len = bytes.len();
+
+        let decoded = Version::decode(&mut bytes.as_slice()).unwrap();
+        assert_eq!(decoded.relay, None);
+        assert_eq!(decoded, version);
+
+        // The same message with the flag is one byte longer.
+        let mut bytes = Vec::new();
+        version
+            .to_builder()
+            .relay(Some(false))
+            .build()
+            .encode(&mut bytes)
+            .unwrap();
+        assert_eq!(bytes.len(), relay_len + 1);
+    }
+}
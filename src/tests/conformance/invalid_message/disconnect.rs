@@ -26,6 +26,7 @@ use crate::{
             constants::{ADDR_COMMAND, HEADER_LEN},
             Message, MessageHeader,
         },
+        network::Network,
         payload::{addr::NetworkAddr, block::Block, codec::Codec, Addr, Inv, Nonce, VarInt},
     },
     setup::node::{Action, Node},
@@ -136,7 +137,7 @@ async fn addr_without_timestamp() {
     // Create a Addr message and encode it. This encoding includes the timestamp.
     let message = Message::Addr(Addr::new(net_addrs));
     let mut payload = Default::default();
-    message.encode(&mut payload).unwrap();
+    message.encode(Network::Testnet, &mut payload).unwrap();
     let mut payload = payload.to_vec();
 
     // Remove the timestamp bytes. The length of the timestamp field is four 4 bytes (u32).p
@@ -147,7 +148,7 @@ async fn addr_without_timestamp() {
     // Note that we cannot use the header from `message.encode()` as it would be generated
     // from the incorrect payload (pre-timestamp removal). Specifically the check-sum would
     // be incorrect.
-    let header = MessageHeader::new(ADDR_COMMAND, &payload);
+    let header = MessageHeader::new(Network::Testnet, ADDR_COMMAND, &payload);
     let mut buffer = Vec::with_capacity(HEADER_LEN + payload.len());
     header.encode(&mut buffer).unwrap();
     buffer.append(&mut payload);
@@ -157,7 +158,7 @@ async fn addr_without_timestamp() {
 
 async fn run_test_case_message(message: Message) -> io::Result<()> {
     let mut buffer = Default::default();
-    message.encode(&mut buffer)?;
+    message.encode(Network::Testnet, &mut buffer)?;
     run_test_case_bytes(buffer.to_vec()).await
 }
 
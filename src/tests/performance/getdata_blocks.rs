@@ -5,7 +5,7 @@ use tokio::time::Duration;
 use crate::{
     protocol::{
         message::Message,
-        payload::{block::Block, Inv},
+        payload::{block::Block, Inv, LazyBlock},
     },
     setup::node::{Action, Node},
     tools::{
@@ -110,13 +110,14 @@ async fn throughput() {
             // We want different blocks for consecutive requests, in order to determine if the node
             // has skipped a request or to tell if the reply is in response to a timed out request.
             //
-            // We also store the Block, in order to compare to the reply.
+            // We also store the Block, in order to compare to the reply. Replies are received as
+            // lazy blocks, so comparing them doesn't cost a full decode.
             let requests = Block::initial_testnet_blocks()
-                .into_iter()
+                .iter()
                 .map(|block| {
                     (
                         Message::GetData(Inv::new(vec![block.inv_hash()])),
-                        Box::new(block),
+                        LazyBlock::from(block),
                     )
                 })
                 .collect::<VecDeque<_>>();
@@ -125,6 +126,7 @@ async fn throughput() {
                 let mut synth_node = SyntheticNode::builder()
                     .with_full_handshake()
                     .with_all_auto_reply()
+                    .with_lazy_blocks()
                     .build()
                     .await
                     .unwrap();
@@ -137,7 +139,7 @@ async fn throughput() {
                     let now = tokio::time::Instant::now();
                     match synth_node.recv_message_timeout(REQUEST_TIMEOUT).await {
                         Err(_timeout) => break,
-                        Ok((_, Message::Block(block))) if &block == expected => {
+                        Ok((_, Message::LazyBlock(block))) if &block == expected => {
                             metrics::histogram!(METRIC_LATENCY, duration_as_ms(now.elapsed()));
                         }
                         Ok((_, bad_reply)) => {
@@ -2,7 +2,7 @@ use assert_matches::assert_matches;
 use rand::prelude::SliceRandom;
 
 use crate::{
-    protocol::message::Message,
+    protocol::{message::Message, network::Network},
     setup::node::{Action, Node},
     tests::resistance::{DISCONNECT_TIMEOUT, ITERATIONS},
     tools::{
@@ -33,7 +33,7 @@ async fn instead_of_version_when_node_receives_connection() {
 
     for _ in 0..ITERATIONS {
         let message = test_messages.choose(&mut rng).unwrap();
-        let payload = encode_message_with_corrupt_checksum(&mut rng, message);
+        let payload = encode_message_with_corrupt_checksum(&mut rng, Network::Testnet, message);
 
         let mut synth_node = SyntheticNode::builder()
             .with_all_auto_reply()
@@ -72,7 +72,7 @@ async fn instead_of_verack_when_node_receives_connection() {
 
     for _ in 0..ITERATIONS {
         let message = test_messages.choose(&mut rng).unwrap();
-        let payload = encode_message_with_corrupt_checksum(&mut rng, message);
+        let payload = encode_message_with_corrupt_checksum(&mut rng, Network::Testnet, message);
 
         let mut synth_node = SyntheticNode::builder()
             .with_all_auto_reply()
@@ -104,7 +104,12 @@ async fn instead_of_version_when_node_initiates_connection() {
 
     let test_messages = default_fuzz_messages();
 
-    let mut payloads = encode_messages_with_corrupt_checksum(&mut rng, ITERATIONS, &test_messages);
+    let mut payloads = encode_messages_with_corrupt_checksum(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &test_messages,
+    );
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -164,7 +169,12 @@ async fn instead_of_verack_when_node_initiates_connection() {
 
     let test_messages = default_fuzz_messages();
 
-    let mut payloads = encode_messages_with_corrupt_checksum(&mut rng, ITERATIONS, &test_messages);
+    let mut payloads = encode_messages_with_corrupt_checksum(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &test_messages,
+    );
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -232,7 +242,7 @@ async fn post_handshake() {
 
     for _ in 0..ITERATIONS {
         let message = test_messages.choose(&mut rng).unwrap();
-        let payload = encode_message_with_corrupt_checksum(&mut rng, message);
+        let payload = encode_message_with_corrupt_checksum(&mut rng, Network::Testnet, message);
 
         let mut synth_node = SyntheticNode::builder()
             .with_full_handshake()
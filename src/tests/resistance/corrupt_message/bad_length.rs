@@ -2,7 +2,7 @@ use assert_matches::assert_matches;
 use rand::prelude::SliceRandom;
 
 use crate::{
-    protocol::message::Message,
+    protocol::{message::Message, network::Network},
     setup::node::{Action, Node},
     tests::resistance::{DISCONNECT_TIMEOUT, ITERATIONS},
     tools::{
@@ -40,7 +40,7 @@ async fn instead_of_version_when_node_receives_connection() {
         synth_node.connect(node.addr()).await.unwrap();
 
         let message = test_messages.choose(&mut rng).unwrap();
-        let payload = encode_message_with_corrupt_body_length(&mut rng, message);
+        let payload = encode_message_with_corrupt_body_length(&mut rng, Network::Testnet, message);
 
         synth_node.send_direct_bytes(node.addr(), payload).unwrap();
 
@@ -80,7 +80,7 @@ async fn instead_of_verack_when_node_receives_connection() {
         synth_node.connect(node.addr()).await.unwrap();
 
         let message = test_messages.choose(&mut rng).unwrap();
-        let payload = encode_message_with_corrupt_body_length(&mut rng, message);
+        let payload = encode_message_with_corrupt_body_length(&mut rng, Network::Testnet, message);
 
         synth_node.send_direct_bytes(node.addr(), payload).unwrap();
 
@@ -104,8 +104,12 @@ async fn instead_of_version_when_node_initiates_connection() {
 
     let test_messages = default_fuzz_messages();
 
-    let mut payloads =
-        encode_messages_with_corrupt_body_length(&mut rng, ITERATIONS, &test_messages);
+    let mut payloads = encode_messages_with_corrupt_body_length(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &test_messages,
+    );
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -167,8 +171,12 @@ async fn instead_of_verack_when_node_initiates_connection() {
 
     let test_messages = default_fuzz_messages();
 
-    let mut payloads =
-        encode_messages_with_corrupt_body_length(&mut rng, ITERATIONS, &test_messages);
+    let mut payloads = encode_messages_with_corrupt_body_length(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &test_messages,
+    );
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -244,7 +252,7 @@ async fn post_handshake() {
         synth_node.connect(node.addr()).await.unwrap();
 
         let message = test_messages.choose(&mut rng).unwrap();
-        let payload = encode_message_with_corrupt_body_length(&mut rng, message);
+        let payload = encode_message_with_corrupt_body_length(&mut rng, Network::Testnet, message);
 
         synth_node.send_direct_bytes(node.addr(), payload).unwrap();
 
@@ -5,7 +5,7 @@ pub mod random_payload;
 use assert_matches::assert_matches;
 
 use crate::{
-    protocol::message::Message,
+    protocol::{message::Message, network::Network},
     setup::node::{Action, Node},
     tests::resistance::{DISCONNECT_TIMEOUT, ITERATIONS},
     tools::{
@@ -24,7 +24,8 @@ async fn instead_of_version_when_node_receives_connection() {
     let test_messages = default_fuzz_messages();
 
     let mut rng = seeded_rng();
-    let payloads = encode_slightly_corrupted_messages(&mut rng, ITERATIONS, &test_messages);
+    let payloads =
+        encode_slightly_corrupted_messages(&mut rng, Network::Testnet, ITERATIONS, &test_messages);
 
     let mut node = Node::new().unwrap();
     node.initial_action(Action::WaitForConnection)
@@ -59,7 +60,8 @@ async fn instead_of_verack_when_node_receives_connection() {
     let test_messages = default_fuzz_messages();
 
     let mut rng = seeded_rng();
-    let payloads = encode_slightly_corrupted_messages(&mut rng, ITERATIONS, &test_messages);
+    let payloads =
+        encode_slightly_corrupted_messages(&mut rng, Network::Testnet, ITERATIONS, &test_messages);
 
     let mut node = Node::new().unwrap();
     node.initial_action(Action::WaitForConnection)
@@ -99,7 +101,8 @@ async fn instead_of_version_when_node_initiates_connection() {
     let test_messages = default_fuzz_messages();
 
     let mut rng = seeded_rng();
-    let mut payloads = encode_slightly_corrupted_messages(&mut rng, ITERATIONS, &test_messages);
+    let mut payloads =
+        encode_slightly_corrupted_messages(&mut rng, Network::Testnet, ITERATIONS, &test_messages);
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -160,7 +163,8 @@ async fn instead_of_verack_when_node_initiates_connection() {
     let test_messages = default_fuzz_messages();
 
     let mut rng = seeded_rng();
-    let mut payloads = encode_slightly_corrupted_messages(&mut rng, ITERATIONS, &test_messages);
+    let mut payloads =
+        encode_slightly_corrupted_messages(&mut rng, Network::Testnet, ITERATIONS, &test_messages);
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -220,7 +224,8 @@ async fn post_handshake() {
     let test_messages = default_fuzz_messages();
 
     let mut rng = seeded_rng();
-    let payloads = encode_slightly_corrupted_messages(&mut rng, ITERATIONS, &test_messages);
+    let payloads =
+        encode_slightly_corrupted_messages(&mut rng, Network::Testnet, ITERATIONS, &test_messages);
 
     let mut node = Node::new().unwrap();
     node.initial_action(Action::WaitForConnection)
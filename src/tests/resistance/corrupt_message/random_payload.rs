@@ -1,7 +1,7 @@
 use assert_matches::assert_matches;
 
 use crate::{
-    protocol::message::Message,
+    protocol::{message::Message, network::Network},
     setup::node::{Action, Node},
     tests::resistance::{DISCONNECT_TIMEOUT, ITERATIONS},
     tools::{
@@ -20,7 +20,12 @@ async fn instead_of_version_when_node_receives_connection() {
 
     // Payloadless messages are omitted.
     let mut rng = seeded_rng();
-    let payloads = metadata_compliant_random_bytes(&mut rng, ITERATIONS, &COMMANDS_WITH_PAYLOADS);
+    let payloads = metadata_compliant_random_bytes(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &COMMANDS_WITH_PAYLOADS,
+    );
 
     let mut node = Node::new().unwrap();
     node.initial_action(Action::WaitForConnection)
@@ -56,7 +61,12 @@ async fn instead_of_verack_when_node_receives_connection() {
 
     // Payloadless messages are omitted.
     let mut rng = seeded_rng();
-    let payloads = metadata_compliant_random_bytes(&mut rng, ITERATIONS, &COMMANDS_WITH_PAYLOADS);
+    let payloads = metadata_compliant_random_bytes(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &COMMANDS_WITH_PAYLOADS,
+    );
 
     let mut node = Node::new().unwrap();
     node.initial_action(Action::WaitForConnection)
@@ -95,8 +105,12 @@ async fn instead_of_version_when_node_initiates_connection() {
 
     // Payloadless messages are omitted.
     let mut rng = seeded_rng();
-    let mut payloads =
-        metadata_compliant_random_bytes(&mut rng, ITERATIONS, &COMMANDS_WITH_PAYLOADS);
+    let mut payloads = metadata_compliant_random_bytes(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &COMMANDS_WITH_PAYLOADS,
+    );
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -156,8 +170,12 @@ async fn instead_of_verack_when_node_initiates_connection() {
 
     // Payloadless messages are omitted.
     let mut rng = seeded_rng();
-    let mut payloads =
-        metadata_compliant_random_bytes(&mut rng, ITERATIONS, &COMMANDS_WITH_PAYLOADS);
+    let mut payloads = metadata_compliant_random_bytes(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &COMMANDS_WITH_PAYLOADS,
+    );
 
     // create peers (we need their ports to give to the node)
     let (synth_nodes, synth_addrs) = SyntheticNode::builder()
@@ -215,7 +233,12 @@ async fn post_handshake() {
 
     // Payloadless messages are omitted.
     let mut rng = seeded_rng();
-    let payloads = metadata_compliant_random_bytes(&mut rng, ITERATIONS, &COMMANDS_WITH_PAYLOADS);
+    let payloads = metadata_compliant_random_bytes(
+        &mut rng,
+        Network::Testnet,
+        ITERATIONS,
+        &COMMANDS_WITH_PAYLOADS,
+    );
 
     let mut node = Node::new().unwrap();
     node.initial_action(Action::WaitForConnection)
@@ -6,7 +6,8 @@ use tabled::{Table, Tabled};
 
 use crate::{
     protocol::{
-        message::{constants::MAGIC, Message, MessageHeader},
+        message::{Message, MessageHeader},
+        network::Network,
         payload::{
             block::{Block, Headers, LocatorHashes},
             codec::Codec,
@@ -389,22 +390,26 @@ fn generate_corrupt_messages(rng: &mut ChaCha8Rng, n: usize) -> Vec<Vec<u8>> {
     possible_payloads.append(&mut zeroes(rng, n));
     possible_payloads.append(&mut encode_slightly_corrupted_messages(
         rng,
+        Network::Testnet,
         n,
         &message_pool,
     ));
     possible_payloads.append(&mut encode_messages_with_corrupt_checksum(
         rng,
+        Network::Testnet,
         n,
         &message_pool,
     ));
     possible_payloads.append(&mut encode_messages_with_corrupt_body_length(
         rng,
+        Network::Testnet,
         n,
         &message_pool,
     ));
     possible_payloads.append(&mut random_bytes(rng, n));
     possible_payloads.append(&mut metadata_compliant_random_bytes(
         rng,
+        Network::Testnet,
         n,
         &COMMANDS_WITH_PAYLOADS,
     ));
@@ -422,7 +427,7 @@ fn is_valid_message_bytes(bytes: &mut std::io::Cursor<&[u8]>) -> bool {
     };
 
     // check magic
-    if header.magic != MAGIC {
+    if header.magic != Network::Testnet.magic() {
         return false;
     }
 
@@ -0,0 +1,456 @@
+//! An in-memory chain, served to peers by a [`SyntheticNode`].
+//!
+//! [`SyntheticNode`]: crate::tools::synthetic_node::SyntheticNode
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{self, Error, ErrorKind},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::broadcast;
+
+use crate::{
+    protocol::{
+        message::Message,
+        payload::{
+            block::{Block, Headers, LocatorHashes},
+            inv::{InvHash, ObjectKind},
+            Hash, Inv, LazyBlock,
+        },
+    },
+    tools::message_handler::{get_data_replies, MessageHandler, Response},
+};
+
+/// The maximum number of headers sent in reply to a [`Message::GetHeaders`].
+pub const MAX_HEADERS_RESULTS: usize = 2000;
+/// The maximum number of block hashes sent in reply to a [`Message::GetBlocks`].
+pub const MAX_BLOCKS_INV: usize = 500;
+
+// The number of announcements a lagging subscriber may fall behind by.
+const ANNOUNCEMENT_CAPACITY: usize = 100;
+
+/// A chain of blocks, shared between clones, which answers block queries the way `zcashd` does.
+///
+/// Registered with [`SyntheticNodeBuilder::with_block_store`], the node replies to:
+///
+/// - [`Message::GetHeaders`] with the headers following the fork point of the locator, up to
+///   and including `hash_stop`, at most [`MAX_HEADERS_RESULTS`] of them. An empty locator asks
+///   for the `hash_stop` header alone, nothing is sent if it's unknown.
+/// - [`Message::GetBlocks`] with an [`Inv`] of the blocks following the fork point, up to but
+///   excluding `hash_stop`, at most [`MAX_BLOCKS_INV`] of them. Like `zcashd`'s `hashContinue`,
+///   once the peer requests the last block of a full inventory, the block is followed by an
+///   [`Inv`] of the tip so the peer asks for more.
+/// - [`Message::GetData`] with the requested blocks, followed by a [`Message::NotFound`] for
+///   the rest of the inventory.
+///
+/// The fork point is the first locator hash found in the chain, or the root block if there is
+/// none. Blocks added with [`BlockStore::push`] are announced to every connected peer.
+///
+/// [`SyntheticNodeBuilder::with_block_store`]: crate::tools::synthetic_node::SyntheticNodeBuilder::with_block_store
+#[derive(Clone)]
+pub struct BlockStore {
+    chain: Arc<RwLock<Chain>>,
+    // The last block of the full inventory most recently sent to each peer.
+    hash_continue: Arc<Mutex<HashMap<SocketAddr, Hash>>>,
+    announcements: broadcast::Sender<InvHash>,
+}
+
+#[derive(Default)]
+struct Chain {
+    blocks: Vec<LazyBlock>,
+    hashes: Vec<Hash>,
+    heights: HashMap<Hash, usize>,
+}
+
+impl Chain {
+    fn push(&mut self, block: LazyBlock) -> io::Result<Hash> {
+        if let Some(tip) = self.hashes.last() {
+            if block.header().prev_block != *tip {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    "the block doesn't extend the tip of the chain",
+                ));
+            }
+        }
+
+        let hash = block.double_sha256()?;
+        self.heights.insert(hash, self.blocks.len());
+        self.hashes.push(hash);
+        self.blocks.push(block);
+
+        Ok(hash)
+    }
+
+    /// Returns the height of the first locator hash in the chain, falling back to the root.
+    fn fork_point(&self, locator_hashes: &LocatorHashes) -> usize {
+        locator_hashes
+            .block_locator_hashes
+            .iter()
+            .find_map(|hash| self.heights.get(hash).copied())
+            .unwrap_or(0)
+    }
+}
+
+impl BlockStore {
+    /// Creates a store holding the `blocks`, each extending the previous one. The first block is
+    /// the root of the chain, its parent isn't checked.
+    pub fn new(blocks: &[Block]) -> io::Result<Self> {
+        if blocks.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "the chain needs a root block",
+            ));
+        }
+
+        let mut chain = Chain::default();
+        for block in blocks {
+            chain.push(LazyBlock::from(block))?;
+        }
+
+        let (announcements, _) = broadcast::channel(ANNOUNCEMENT_CAPACITY);
+
+        Ok(Self {
+            chain: Arc::new(RwLock::new(chain)),
+            hash_continue: Default::default(),
+            announcements,
+        })
+    }
+
+    /// Creates a store holding [`Block::initial_testnet_blocks`].
+    pub fn initial_testnet() -> Self {
+        Self::new(&Block::initial_testnet_blocks()).unwrap()
+    }
+
+    /// Appends the block to the chain and announces it to the peers of the nodes serving the
+    /// store. Errors if the block doesn't extend the tip.
+    pub fn push(&self, block: &Block) -> io::Result<Hash> {
+        let hash = self.chain.write().push(LazyBlock::from(block))?;
+        // No node serving the store is fine.
+        let _ = self
+            .announcements
+            .send(InvHash::new(ObjectKind::Block, hash));
+
+        Ok(hash)
+    }
+
+    /// Returns the number of blocks in the chain, including the root.
+    pub fn block_count(&self) -> usize {
+        self.chain.read().blocks.len()
+    }
+
+    /// Returns the hash of the last block in the chain.
+    pub fn tip(&self) -> Hash {
+        *self.chain.read().hashes.last().unwrap()
+    }
+
+    /// Returns the height of the block, counted from the root.
+    pub fn height(&self, hash: &Hash) -> Option<usize> {
+        self.chain.read().heights.get(hash).copied()
+    }
+
+    /// Returns the block with the hash.
+    pub fn get(&self, hash: &Hash) -> Option<LazyBlock> {
+        let chain = self.chain.read();
+        chain
+            .heights
+            .get(hash)
+            .map(|height| chain.blocks[*height].clone())
+    }
+
+    /// Returns the headers to reply to a [`Message::GetHeaders`] with, `None` if the locator is
+    /// empty and `hash_stop` is unknown, which isn't answered.
+    pub fn headers(&self, locator_hashes: &LocatorHashes) -> Option<Headers> {
+        let chain = self.chain.read();
+
+        if locator_hashes.block_locator_hashes.is_empty() {
+            return chain
+                .heights
+                .get(&locator_hashes.hash_stop)
+                .map(|height| Headers::new(vec![chain.blocks[*height].header().clone()]));
+        }
+
+        let start = chain.fork_point(locator_hashes) + 1;
+        let mut headers = Vec::new();
+        for (block, hash) in chain.blocks[start..].iter().zip(&chain.hashes[start..]) {
+            headers.push(block.header().clone());
+            if headers.len() == MAX_HEADERS_RESULTS || *hash == locator_hashes.hash_stop {
+                break;
+            }
+        }
+
+        Some(Headers::new(headers))
+    }
+
+    /// Returns the inventory to reply to a [`Message::GetBlocks`] from `source` with. If the
+    /// inventory is full, its last block is recorded as the peer's `hashContinue`.
+    pub fn blocks_inv(&self, source: SocketAddr, locator_hashes: &LocatorHashes) -> Inv {
+        let chain = self.chain.read();
+        let start = chain.fork_point(locator_hashes) + 1;
+        let inventory: Vec<_> = chain.hashes[start..]
+            .iter()
+            .take_while(|hash| **hash != locator_hashes.hash_stop)
+            .take(MAX_BLOCKS_INV)
+            .map(|hash| InvHash::new(ObjectKind::Block, *hash))
+            .collect();
+
+        if inventory.len() == MAX_BLOCKS_INV {
+            let last = inventory.last().unwrap().hash();
+            self.hash_continue.lock().insert(source, last);
+        }
+
+        Inv::new(inventory)
+    }
+
+    /// Returns the messages to reply to a [`Message::GetData`] from `source` with: the known
+    /// blocks, in the requested order, then a [`Message::NotFound`] for the remaining inventory,
+    /// if any.
+    pub fn get_data(&self, source: SocketAddr, inv: &Inv) -> Vec<Message> {
+        get_data_replies(inv, |inv_hash| self.find(source, inv_hash))
+    }
+
+    /// Returns the block the inventory refers to, followed by an [`Inv`] of the tip if it's the
+    /// peer's `hashContinue`. Empty if the block is unknown.
+    pub(crate) fn find(&self, source: SocketAddr, inv_hash: &InvHash) -> Vec<Message> {
+        if inv_hash.kind() != ObjectKind::Block {
+            return Vec::new();
+        }
+        let Some(block) = self.get(&inv_hash.hash()) else {
+            return Vec::new();
+        };
+
+        let mut replies = vec![Message::LazyBlock(block)];
+        let mut hash_continue = self.hash_continue.lock();
+        if hash_continue.get(&source) == Some(&inv_hash.hash()) {
+            hash_continue.remove(&source);
+            let tip = InvHash::new(ObjectKind::Block, self.tip());
+            replies.push(Message::Inv(Inv::new(vec![tip])));
+        }
+
+        replies
+    }
+
+    /// Subscribes to the inventory of the blocks pushed from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<InvHash> {
+        self.announcements.subscribe()
+    }
+}
+
+impl MessageHandler for BlockStore {
+    fn handle(&self, source: SocketAddr, message: &Message) -> Response {
+        match message {
+            Message::GetHeaders(locator_hashes) => match self.headers(locator_hashes) {
+                Some(headers) => Response::reply(Message::Headers(headers)),
+                None => Response::ignore(),
+            },
+            Message::GetBlocks(locator_hashes) => {
+                let inv = self.blocks_inv(source, locator_hashes);
+                if inv.inventory.is_empty() {
+                    Response::ignore()
+                } else {
+                    Response::reply(Message::Inv(inv))
+                }
+            }
+            Message::GetData(inv) => Response::Reply(self.get_data(source, inv)),
+            _ => Response::Forward,
+        }
+    }
+}
+
+impl fmt::Debug for BlockStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let chain = self.chain.read();
+
+        f.debug_struct("BlockStore")
+            .field("len", &chain.blocks.len())
+            .field("tip", chain.hashes.last().unwrap())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tools::{synthetic_node::SyntheticNode, LONG_TIMEOUT},
+        wait_until,
+    };
+
+    /// Returns the testnet genesis block followed by `len` blocks extending it.
+    fn chain(len: usize) -> Vec<Block> {
+        let mut blocks = vec![Block::testnet_genesis()];
+        for _ in 0..len {
+            let mut block = Block::testnet_genesis();
+            block.header.prev_block = blocks.last().unwrap().double_sha256().unwrap();
+            blocks.push(block);
+        }
+
+        blocks
+    }
+
+    fn hashes(blocks: &[Block]) -> Vec<Hash> {
+        blocks
+            .iter()
+            .map(|block| block.double_sha256().unwrap())
+            .collect()
+    }
+
+    fn source() -> SocketAddr {
+        "127.0.0.1:8233".parse().unwrap()
+    }
+
+    #[test]
+    fn headers_follow_the_fork_point() {
+        let blocks = chain(2100);
+        let hashes = hashes(&blocks);
+        let store = BlockStore::new(&blocks).unwrap();
+        assert_eq!(store.block_count(), 2101);
+        assert_eq!(store.tip(), hashes[2100]);
+
+        // Unknown hashes are skipped, the limit applies from the fork point.
+        let locator =
+            LocatorHashes::new(vec![Hash::zeroed(), hashes[50], hashes[0]], Hash::zeroed());
+        let headers = store.headers(&locator).unwrap().headers;
+        assert_eq!(headers.len(), MAX_HEADERS_RESULTS);
+        assert_eq!(headers[0], blocks[51].header);
+
+        // The stop hash is included in the headers, but not in the inventory.
+        let locator = LocatorHashes::new(vec![hashes[0]], hashes[10]);
+        let headers = store.headers(&locator).unwrap().headers;
+        assert_eq!(headers.len(), 10);
+        assert_eq!(headers.last(), Some(&blocks[10].header));
+        let inv = store.blocks_inv(source(), &locator).inventory;
+        assert_eq!(inv.len(), 9);
+        assert_eq!(inv[0].hash(), hashes[1]);
+
+        let locator = LocatorHashes::new(vec![hashes[1000]], Hash::zeroed());
+        let inv = store.blocks_inv(source(), &locator);
+        assert_eq!(inv.inventory.len(), MAX_BLOCKS_INV);
+
+        // An empty locator asks for the stop header alone, unknown ones aren't answered.
+        let locator = LocatorHashes::new(Vec::new(), hashes[7]);
+        assert_eq!(
+            store.headers(&locator).unwrap().headers,
+            vec![blocks[7].header.clone()]
+        );
+        let locator = LocatorHashes::new(Vec::new(), Hash::zeroed());
+        assert!(store.headers(&locator).is_none());
+        assert_eq!(
+            store.handle(source(), &Message::GetHeaders(locator)),
+            Response::ignore()
+        );
+
+        // Nothing follows the tip.
+        let locator = LocatorHashes::new(vec![hashes[2100]], Hash::zeroed());
+        assert!(store.headers(&locator).unwrap().headers.is_empty());
+        assert!(store.blocks_inv(source(), &locator).inventory.is_empty());
+
+        // Only blocks extending the tip are accepted.
+        assert!(store.push(&blocks[5]).is_err());
+    }
+
+    #[test]
+    fn get_data_replies_with_not_found() {
+        let blocks = Block::initial_testnet_blocks();
+        let store = BlockStore::initial_testnet();
+
+        let missing = InvHash::new(ObjectKind::Block, Hash::zeroed());
+        let tx = blocks[1].txs[0].inv_hash();
+        let inv = Inv::new(vec![
+            blocks[2].inv_hash(),
+            missing,
+            blocks[1].inv_hash(),
+            tx,
+        ]);
+
+        assert_eq!(
+            store.get_data(source(), &inv),
+            vec![
+                Message::LazyBlock(LazyBlock::from(&blocks[2])),
+                Message::LazyBlock(LazyBlock::from(&blocks[1])),
+                Message::NotFound(Inv::new(vec![missing, tx])),
+            ]
+        );
+    }
+
+    #[test]
+    fn full_inventory_continues_with_the_tip() {
+        let blocks = chain(MAX_BLOCKS_INV + 10);
+        let hashes = hashes(&blocks);
+        let store = BlockStore::new(&blocks).unwrap();
+        let other: SocketAddr = "127.0.0.1:8234".parse().unwrap();
+
+        let locator = LocatorHashes::new(vec![hashes[0]], Hash::zeroed());
+        let inv = store.blocks_inv(source(), &locator);
+        let last = *inv.inventory.last().unwrap();
+        assert_eq!(last.hash(), hashes[MAX_BLOCKS_INV]);
+
+        // Only the peer the inventory was sent to is told about the tip, once.
+        let request = Inv::new(vec![last]);
+        assert_eq!(store.get_data(other, &request).len(), 1);
+        assert_eq!(
+            store.get_data(source(), &request),
+            vec![
+                Message::LazyBlock(LazyBlock::from(&blocks[MAX_BLOCKS_INV])),
+                Message::Inv(Inv::new(vec![InvHash::new(ObjectKind::Block, store.tip())])),
+            ]
+        );
+        assert_eq!(store.get_data(source(), &request).len(), 1);
+
+        // A partial inventory doesn't set it.
+        let locator = LocatorHashes::new(vec![hashes[MAX_BLOCKS_INV]], Hash::zeroed());
+        let inv = store.blocks_inv(source(), &locator);
+        assert_eq!(inv.inventory.len(), 10);
+        let request = Inv::new(vec![*inv.inventory.last().unwrap()]);
+        assert_eq!(store.get_data(source(), &request).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn serves_and_announces_blocks() {
+        let blocks = chain(3);
+        let hashes = hashes(&blocks);
+        let store = BlockStore::new(&blocks[..3]).unwrap();
+
+        let server = SyntheticNode::builder()
+            .with_full_handshake()
+            .with_block_store(store.clone())
+            .build()
+            .await
+            .unwrap();
+        let mut client = SyntheticNode::builder()
+            .with_full_handshake()
+            .build()
+            .await
+            .unwrap();
+        client.connect(server.listening_addr()).await.unwrap();
+        wait_until!(LONG_TIMEOUT, server.num_connected() == 1);
+
+        client
+            .unicast(
+                server.listening_addr(),
+                Message::GetHeaders(LocatorHashes::new(vec![hashes[0]], Hash::zeroed())),
+            )
+            .unwrap();
+        let (_, reply) = client.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(
+            reply,
+            Message::Headers(Headers::new(vec![
+                blocks[1].header.clone(),
+                blocks[2].header.clone()
+            ]))
+        );
+
+        store.push(&blocks[3]).unwrap();
+        let (_, announcement) = client.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(
+            announcement,
+            Message::Inv(Inv::new(vec![blocks[3].inv_hash()]))
+        );
+
+        client.shut_down().await;
+        server.shut_down().await;
+    }
+}
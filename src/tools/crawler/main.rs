@@ -12,10 +12,13 @@ use rand::prelude::IteratorRandom;
 use tokio::time::sleep;
 use tracing::{error, info, warn};
 use tracing_subscriber::filter::{EnvFilter, LevelFilter};
-use ziggurat::{protocol::message::Message, wait_until};
+use ziggurat::{
+    protocol::{message::Message, network::Network},
+    wait_until,
+};
 
 use crate::{
-    metrics::NetworkMetrics,
+    metrics::{log_nodes_to_file, NetworkMetrics},
     network::KnownNode,
     protocol::{Crawler, MAIN_LOOP_INTERVAL, NUM_CONN_ATTEMPTS_PERIODIC, RECONNECT_INTERVAL},
 };
@@ -35,9 +38,9 @@ struct Args {
     seed_addrs: Vec<String>,
     #[clap(short, long, value_parser, default_value_t = MAIN_LOOP_INTERVAL)]
     crawl_interval: u64,
-    // TODO
-    // #[clap(short, long, value_parser, default_value = "testnet")]
-    // network: String,
+    /// One of `mainnet`, `testnet`, `regtest` or custom magic bytes as 8 hex digits.
+    #[clap(short, long, value_parser, default_value_t = Network::Mainnet)]
+    network: Network,
 }
 
 fn start_logger(default_level: LevelFilter) {
@@ -63,7 +66,7 @@ async fn main() {
     let args = Args::parse();
 
     // Create the crawler with the given listener address.
-    let crawler = Crawler::new().await;
+    let crawler = Crawler::new(args.network).await;
 
     let mut network_metrics = NetworkMetrics::default();
 
@@ -151,6 +154,9 @@ async fn main() {
                 if let Err(e) = network_summary.log_to_file() {
                     error!(parent: crawler.node().span(), "Couldn't write summary to file: {}", e);
                 }
+                if let Err(e) = log_nodes_to_file(&crawler) {
+                    error!(parent: crawler.node().span(), "Couldn't write node records to file: {}", e);
+                }
             }
 
             let delta_time =
@@ -1,11 +1,17 @@
 use core::fmt;
 use std::{cmp, collections::HashMap, fs, net::SocketAddr, time::Duration};
 
+use serde::Serialize;
 use spectre::{edge::Edge, graph::Graph};
+use ziggurat::protocol::payload::{addr::NetAddr, ProtocolVersion, Services, VarStr};
 
-use crate::{network::LAST_SEEN_CUTOFF, Crawler};
+use crate::{
+    network::{KnownNode, LAST_SEEN_CUTOFF},
+    Crawler,
+};
 
 const LOG_PATH: &str = "crawler-log.txt";
+const NODES_LOG_PATH: &str = "crawler-nodes.json";
 
 #[derive(Default)]
 pub struct NetworkMetrics {
@@ -31,6 +37,47 @@ impl NetworkMetrics {
     }
 }
 
+/// A structured record of a known node, as written to [`NODES_LOG_PATH`].
+#[derive(Serialize)]
+struct NodeRecord {
+    addr: SocketAddr,
+    /// Seconds since the last successful connection.
+    last_connected_secs: Option<u64>,
+    handshake_time_ms: Option<u128>,
+    protocol_version: Option<ProtocolVersion>,
+    user_agent: Option<VarStr>,
+    services: Option<Services>,
+    connection_failures: u8,
+}
+
+impl NodeRecord {
+    fn new(addr: SocketAddr, node: KnownNode) -> Self {
+        Self {
+            addr,
+            last_connected_secs: node.last_connected.map(|i| i.elapsed().as_secs()),
+            handshake_time_ms: node.handshake_time.map(|d| d.as_millis()),
+            protocol_version: node.protocol_version,
+            user_agent: node.user_agent,
+            services: node.services,
+            connection_failures: node.connection_failures,
+        }
+    }
+}
+
+/// Logs a JSON record of every known node to file.
+pub fn log_nodes_to_file(crawler: &Crawler) -> std::io::Result<()> {
+    let mut records: Vec<_> = crawler
+        .known_network
+        .nodes()
+        .into_iter()
+        .map(|(addr, node)| NodeRecord::new(addr, node))
+        .collect();
+    records.sort_by_key(|record| record.addr);
+
+    fs::write(NODES_LOG_PATH, serde_json::to_vec_pretty(&records)?)?;
+    Ok(())
+}
+
 #[allow(dead_code)]
 pub struct NetworkSummary {
     num_known_nodes: usize,
@@ -39,6 +86,8 @@ pub struct NetworkSummary {
     num_versions: usize,
     protocol_versions: HashMap<u32, usize>,
     user_agents: HashMap<String, usize>,
+    services: HashMap<Services, usize>,
+    overlay_networks: HashMap<&'static str, usize>,
     crawler_runtime: Duration,
     density: f64,
     degree_centrality_delta: f64,
@@ -64,6 +113,7 @@ impl NetworkSummary {
 
         let mut protocol_versions = HashMap::with_capacity(num_known_nodes);
         let mut user_agents = HashMap::with_capacity(num_known_nodes);
+        let mut services = HashMap::with_capacity(num_known_nodes);
 
         for (_, node) in nodes {
             if node.protocol_version.is_some() {
@@ -76,9 +126,24 @@ impl NetworkSummary {
                     .and_modify(|count| *count += 1)
                     .or_insert(1);
             }
+
+            if let Some(node_services) = node.services {
+                *services.entry(node_services).or_insert(0) += 1;
+            }
         }
 
         let num_versions = protocol_versions.values().sum();
+
+        let mut overlay_networks = HashMap::new();
+        for (addr, _) in crawler.known_network.overlay_nodes().keys() {
+            let network = match addr {
+                NetAddr::TorV2(_) | NetAddr::TorV3(_) => "Tor",
+                NetAddr::I2p(_) => "I2P",
+                NetAddr::Cjdns(_) => "CJDNS",
+                _ => "unknown",
+            };
+            *overlay_networks.entry(network).or_insert(0) += 1;
+        }
         let crawler_runtime = crawler.start_time.elapsed();
 
         // Procure metrics from the graph.
@@ -95,6 +160,8 @@ impl NetworkSummary {
             num_versions,
             protocol_versions,
             user_agents,
+            services,
+            overlay_networks,
             crawler_runtime,
             density,
             degree_centrality_delta,
@@ -143,6 +210,10 @@ impl fmt::Display for NetworkSummary {
         print_hashmap(f, &self.protocol_versions)?;
         writeln!(f, "\nUser agents:")?;
         print_hashmap(f, &self.user_agents)?;
+        writeln!(f, "\nServices:")?;
+        print_hashmap(f, &self.services)?;
+        writeln!(f, "\nOverlay network addresses:")?;
+        print_hashmap(f, &self.overlay_networks)?;
 
         writeln!(f, "\nNetwork graph metrics:")?;
         writeln!(f, "Density: {:.4}", self.density)?;
@@ -7,7 +7,7 @@ use std::{
 };
 
 use parking_lot::RwLock;
-use ziggurat::protocol::payload::{ProtocolVersion, VarStr};
+use ziggurat::protocol::payload::{addr::NetAddr, ProtocolVersion, Services, VarStr};
 
 /// The elapsed time before a connection should be regarded as inactive.
 pub const LAST_SEEN_CUTOFF: u64 = 10 * 60;
@@ -24,8 +24,8 @@ pub struct KnownNode {
     pub protocol_version: Option<ProtocolVersion>,
     /// The node's user agent.
     pub user_agent: Option<VarStr>,
-    /// The number of services supported by the node.
-    pub services: Option<u64>,
+    /// The services supported by the node.
+    pub services: Option<Services>,
     /// The number of subsequent connection errors.
     pub connection_failures: u8,
 }
@@ -83,6 +83,9 @@ impl KnownConnection {
 pub struct KnownNetwork {
     pub nodes: RwLock<HashMap<SocketAddr, KnownNode>>,
     pub connections: RwLock<HashSet<KnownConnection>>,
+    /// The addresses and ports of overlay network (Tor, I2P and CJDNS) nodes, which can't be
+    /// connected to, with the last time they were advertised.
+    pub overlay_nodes: RwLock<HashMap<(NetAddr, u16), Instant>>,
 }
 
 impl KnownNetwork {
@@ -98,6 +101,19 @@ impl KnownNetwork {
         self.update_nodes();
     }
 
+    /// Records advertised overlay network addresses.
+    pub fn add_overlay_addrs(&self, addrs: &[(NetAddr, u16)]) {
+        let mut overlay_nodes = self.overlay_nodes.write();
+        for addr in addrs {
+            overlay_nodes.insert(addr.clone(), Instant::now());
+        }
+    }
+
+    /// Returns a snapshot of the known overlay network nodes.
+    pub fn overlay_nodes(&self) -> HashMap<(NetAddr, u16), Instant> {
+        self.overlay_nodes.read().clone()
+    }
+
     /// Returns a snapshot of the known connections.
     pub fn connections(&self) -> HashSet<KnownConnection> {
         self.connections.read().clone()
@@ -10,6 +10,7 @@ use tracing::*;
 use ziggurat::{
     protocol::{
         message::Message,
+        network::Network,
         payload::{block::Headers, Addr, Version},
     },
     tools::synthetic_node::MessageCodec,
@@ -26,6 +27,7 @@ pub const RECONNECT_INTERVAL: u64 = 5 * 60;
 #[derive(Clone)]
 pub struct Crawler {
     node: Pea2PeaNode,
+    network: Network,
     pub known_network: Arc<KnownNetwork>,
     pub start_time: Instant,
 }
@@ -37,8 +39,8 @@ impl Pea2Pea for Crawler {
 }
 
 impl Crawler {
-    /// Creates a new instance of the `Crawler` without starting it.
-    pub async fn new() -> Self {
+    /// Creates a new instance of the `Crawler` for the given network without starting it.
+    pub async fn new(network: Network) -> Self {
         let config = Config {
             name: Some("crawler".into()),
             listener_ip: None,
@@ -48,6 +50,7 @@ impl Crawler {
 
         Self {
             node: Pea2PeaNode::new(config).await.unwrap(),
+            network,
             known_network: Default::default(),
             start_time: Instant::now(),
         }
@@ -108,7 +111,10 @@ impl Handshake for Crawler {
     async fn perform_handshake(&self, mut conn: Connection) -> io::Result<Connection> {
         let conn_addr = conn.addr();
         let own_listening_addr: SocketAddr = ([127, 0, 0, 1], 0).into();
-        let mut framed_stream = Framed::new(self.borrow_stream(&mut conn), MessageCodec::default());
+        let mut framed_stream = Framed::new(
+            self.borrow_stream(&mut conn),
+            MessageCodec::new(self.network),
+        );
 
         let own_version = Message::Version(Version::new(conn_addr, own_listening_addr));
         framed_stream.send(own_version).await?;
@@ -123,7 +129,7 @@ impl Reading for Crawler {
     type Codec = MessageCodec;
 
     fn codec(&self, _addr: SocketAddr, _side: ConnectionSide) -> Self::Codec {
-        Default::default()
+        MessageCodec::new(self.network)
     }
 
     async fn process_message(&self, source: SocketAddr, message: Self::Message) -> io::Result<()> {
@@ -139,6 +145,23 @@ impl Reading for Crawler {
                 self.known_network.add_addrs(source, &listening_addrs);
                 self.node().disconnect(source).await;
             }
+            Message::AddrV2(addr) => {
+                info!(parent: self.node().span(), "got {} v2 address(es) from {}", addr.addrs.len(), source);
+
+                // Overlay network peers can't be crawled, but are recorded.
+                let mut listening_addrs = Vec::with_capacity(addr.addrs.len());
+                let mut overlay_addrs = Vec::new();
+                for addr in addr.addrs {
+                    match addr.socket_addr() {
+                        Some(socket_addr) => listening_addrs.push(socket_addr),
+                        None => overlay_addrs.push((addr.addr, addr.port)),
+                    }
+                }
+
+                self.known_network.add_addrs(source, &listening_addrs);
+                self.known_network.add_overlay_addrs(&overlay_addrs);
+                self.node().disconnect(source).await;
+            }
             Message::Ping(nonce) => {
                 let _ = self.unicast(source, Message::Pong(nonce))?.await;
             }
@@ -161,6 +184,8 @@ impl Reading for Crawler {
                     known_node.services = Some(ver.services);
                 }
 
+                // Ask for addresses of all networks, which the peer may send as AddrV2.
+                let _ = self.unicast(source, Message::SendAddrV2)?.await;
                 let _ = self.unicast(source, Message::Verack)?.await;
             }
             _ => {}
@@ -175,6 +200,6 @@ impl Writing for Crawler {
     type Codec = MessageCodec;
 
     fn codec(&self, _addr: SocketAddr, _side: ConnectionSide) -> Self::Codec {
-        Default::default()
+        MessageCodec::new(self.network)
     }
 }
@@ -15,6 +15,7 @@ use rand_chacha::ChaCha8Rng;
 
 use crate::protocol::{
     message::{constants::*, Message, MessageHeader},
+    network::Network,
     payload::{
         block::{Headers, LocatorHashes},
         codec::Codec,
@@ -99,9 +100,10 @@ pub fn random_bytes(rng: &mut ChaCha8Rng, n: usize) -> Vec<Vec<u8>> {
         .collect()
 }
 
-/// Returns a message with a valid header and payload of random bytes.
+/// Returns a message with a valid header for the network and payload of random bytes.
 pub fn metadata_compliant_random_bytes(
     rng: &mut ChaCha8Rng,
+    network: Network,
     n: usize,
     commands: &[[u8; 12]],
 ) -> Vec<Vec<u8>> {
@@ -111,7 +113,7 @@ pub fn metadata_compliant_random_bytes(
             let mut random_payload: Vec<u8> = rng.sample_iter(Standard).take(random_len).collect();
 
             let command = commands.choose(rng).unwrap();
-            let header = MessageHeader::new(*command, &random_payload);
+            let header = MessageHeader::new(network, *command, &random_payload);
 
             let mut buffer = Vec::with_capacity(HEADER_LEN + random_payload.len());
             header.encode(&mut buffer).unwrap();
@@ -125,20 +127,21 @@ pub fn metadata_compliant_random_bytes(
 /// Corrupts `n` messages from the supplied set by replacing a random number of bytes with random bytes.
 pub fn encode_slightly_corrupted_messages(
     rng: &mut ChaCha8Rng,
+    network: Network,
     n: usize,
     messages: &[Message],
 ) -> Vec<Vec<u8>> {
     (0..n)
         .map(|_| {
             let message = messages.choose(rng).unwrap();
-            corrupt_message(rng, message)
+            corrupt_message(rng, network, message)
         })
         .collect()
 }
 
-fn corrupt_message(rng: &mut ChaCha8Rng, message: &Message) -> Vec<u8> {
+fn corrupt_message(rng: &mut ChaCha8Rng, network: Network, message: &Message) -> Vec<u8> {
     let mut bytes = Default::default();
-    message.encode(&mut bytes).unwrap();
+    message.encode(network, &mut bytes).unwrap();
     let vec: Vec<_> = bytes.to_vec();
     let (valid_header, valid_message) = vec.split_at(HEADER_LEN);
 
@@ -165,9 +168,13 @@ fn corrupt_bytes(rng: &mut ChaCha8Rng, serialized: &[u8]) -> Vec<u8> {
 }
 
 /// Encodes a message and corrupts the body length bytes.
-pub fn encode_message_with_corrupt_body_length(rng: &mut ChaCha8Rng, message: &Message) -> Vec<u8> {
+pub fn encode_message_with_corrupt_body_length(
+    rng: &mut ChaCha8Rng,
+    network: Network,
+    message: &Message,
+) -> Vec<u8> {
     let mut bytes = Default::default();
-    message.encode(&mut bytes).unwrap();
+    message.encode(network, &mut bytes).unwrap();
     let mut vec: Vec<_> = bytes.to_vec();
 
     let invalid_body_length = random_non_valid_u32(rng, (vec.len() - HEADER_LEN) as u32);
@@ -177,9 +184,13 @@ pub fn encode_message_with_corrupt_body_length(rng: &mut ChaCha8Rng, message: &M
 }
 
 /// Encodes a message and corrupts the checksum bytes.
-pub fn encode_message_with_corrupt_checksum(rng: &mut ChaCha8Rng, message: &Message) -> Vec<u8> {
+pub fn encode_message_with_corrupt_checksum(
+    rng: &mut ChaCha8Rng,
+    network: Network,
+    message: &Message,
+) -> Vec<u8> {
     let mut bytes = Default::default();
-    message.encode(&mut bytes).unwrap();
+    message.encode(network, &mut bytes).unwrap();
     let mut vec: Vec<_> = bytes.to_vec();
 
     let valid_checksum = u32::from_le_bytes(vec[20..][..4].try_into().unwrap()); // TODO: constify
@@ -203,6 +214,7 @@ fn random_non_valid_u32(rng: &mut ChaCha8Rng, value: u32) -> u32 {
 /// Picks `n` random messages from `message_pool`, encodes them and corrupts the body length bytes.
 pub fn encode_messages_with_corrupt_body_length(
     rng: &mut ChaCha8Rng,
+    network: Network,
     n: usize,
     message_pool: &[Message],
 ) -> Vec<Vec<u8>> {
@@ -210,7 +222,7 @@ pub fn encode_messages_with_corrupt_body_length(
         .map(|_| {
             let message = message_pool.choose(rng).unwrap();
 
-            encode_message_with_corrupt_body_length(rng, message)
+            encode_message_with_corrupt_body_length(rng, network, message)
         })
         .collect()
 }
@@ -218,6 +230,7 @@ pub fn encode_messages_with_corrupt_body_length(
 /// Picks `n` random messages from `message_pool`, encodes them and corrupts the checksum bytes.
 pub fn encode_messages_with_corrupt_checksum(
     rng: &mut ChaCha8Rng,
+    network: Network,
     n: usize,
     message_pool: &[Message],
 ) -> Vec<Vec<u8>> {
@@ -225,7 +238,7 @@ pub fn encode_messages_with_corrupt_checksum(
         .map(|_| {
             let message = message_pool.choose(rng).unwrap();
 
-            encode_message_with_corrupt_checksum(rng, message)
+            encode_message_with_corrupt_checksum(rng, network, message)
         })
         .collect()
 }
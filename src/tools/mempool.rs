@@ -0,0 +1,305 @@
+//! An in-memory mempool, served to peers by a [`SyntheticNode`].
+//!
+//! [`SyntheticNode`]: crate::tools::synthetic_node::SyntheticNode
+
+use std::{collections::HashMap, fmt, net::SocketAddr, sync::Arc};
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::broadcast;
+
+use crate::{
+    protocol::{
+        message::Message,
+        payload::{
+            inv::{InvHash, ObjectKind},
+            Inv, Tx,
+        },
+    },
+    tools::message_handler::{get_data_replies, MessageHandler, Response},
+};
+
+/// The maximum number of entries in an [`Inv`] sent in reply to a [`Message::MemPool`], larger
+/// pools are split over several messages.
+pub const MAX_INV_SZ: usize = 50_000;
+
+// The number of announcements a lagging subscriber may fall behind by.
+const ANNOUNCEMENT_CAPACITY: usize = 100;
+
+/// A set of transactions, shared between clones, which a node relays the way `zcashd` does.
+///
+/// Registered with [`SyntheticNodeBuilder::with_mempool`], the node replies to:
+///
+/// - [`Message::MemPool`] with an [`Inv`] of every transaction, nothing if the pool is empty.
+/// - [`Message::GetData`] with the requested transactions, followed by a [`Message::NotFound`]
+///   for the rest of the inventory.
+///
+/// Transactions are identified by their [`Tx::inv_hash`], so v5 transactions are only served
+/// when requested by [`ObjectKind::WTx`]. Every transaction requested is recorded, see
+/// [`Mempool::requests`], and transactions added with [`Mempool::insert`] are announced to every
+/// connected peer.
+///
+/// [`SyntheticNodeBuilder::with_mempool`]: crate::tools::synthetic_node::SyntheticNodeBuilder::with_mempool
+#[derive(Clone)]
+pub struct Mempool {
+    pool: Arc<RwLock<Pool>>,
+    requests: Arc<Mutex<Vec<(SocketAddr, InvHash)>>>,
+    announcements: broadcast::Sender<InvHash>,
+}
+
+#[derive(Default)]
+struct Pool {
+    // The inventory in insertion order.
+    inventory: Vec<InvHash>,
+    txs: HashMap<InvHash, Tx>,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        let (announcements, _) = broadcast::channel(ANNOUNCEMENT_CAPACITY);
+
+        Self {
+            pool: Default::default(),
+            requests: Default::default(),
+            announcements,
+        }
+    }
+}
+
+impl Mempool {
+    /// Creates an empty mempool.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a mempool holding the `txs`.
+    pub fn with_txs(txs: impl IntoIterator<Item = Tx>) -> Self {
+        let mempool = Self::new();
+        for tx in txs {
+            mempool.insert(tx);
+        }
+
+        mempool
+    }
+
+    /// Adds the transaction to the pool and announces it to the peers of the nodes serving the
+    /// pool. Returns `false`, without announcing, if the transaction was already present.
+    pub fn insert(&self, tx: Tx) -> bool {
+        let inv_hash = tx.inv_hash();
+
+        {
+            let mut pool = self.pool.write();
+            if pool.txs.contains_key(&inv_hash) {
+                return false;
+            }
+            pool.inventory.push(inv_hash);
+            pool.txs.insert(inv_hash, tx);
+        }
+
+        // No node serving the pool is fine.
+        let _ = self.announcements.send(inv_hash);
+
+        true
+    }
+
+    /// Removes the transaction from the pool, e.g. once it was mined.
+    pub fn remove(&self, inv_hash: &InvHash) -> Option<Tx> {
+        let mut pool = self.pool.write();
+        let tx = pool.txs.remove(inv_hash)?;
+        pool.inventory.retain(|entry| entry != inv_hash);
+
+        Some(tx)
+    }
+
+    /// Returns the transaction with the inventory hash.
+    pub fn get(&self, inv_hash: &InvHash) -> Option<Tx> {
+        self.pool.read().txs.get(inv_hash).cloned()
+    }
+
+    /// Returns `true` if the pool holds the transaction.
+    pub fn contains(&self, inv_hash: &InvHash) -> bool {
+        self.pool.read().txs.contains_key(inv_hash)
+    }
+
+    /// Returns the number of transactions in the pool.
+    pub fn len(&self) -> usize {
+        self.pool.read().inventory.len()
+    }
+
+    /// Returns `true` if the pool holds no transactions.
+    pub fn is_empty(&self) -> bool {
+        self.pool.read().inventory.is_empty()
+    }
+
+    /// Returns the inventory of the pool, in insertion order.
+    pub fn inventory(&self) -> Vec<InvHash> {
+        self.pool.read().inventory.clone()
+    }
+
+    /// Returns the transactions requested with [`Message::GetData`] so far, along with the
+    /// address of the peer requesting them. Requests for transactions missing from the pool are
+    /// included.
+    pub fn requests(&self) -> Vec<(SocketAddr, InvHash)> {
+        self.requests.lock().clone()
+    }
+
+    /// Returns the messages to reply to a [`Message::MemPool`] with.
+    pub fn mempool_inv(&self) -> Vec<Message> {
+        self.pool
+            .read()
+            .inventory
+            .chunks(MAX_INV_SZ)
+            .map(|chunk| Message::Inv(Inv::new(chunk.to_vec())))
+            .collect()
+    }
+
+    /// Returns the messages to reply to a [`Message::GetData`] from `source` with, recording the
+    /// transactions requested.
+    pub fn get_data(&self, source: SocketAddr, inv: &Inv) -> Vec<Message> {
+        get_data_replies(inv, |inv_hash| {
+            self.find(source, inv_hash).into_iter().collect()
+        })
+    }
+
+    /// Returns the transaction the inventory refers to, recording the request.
+    pub(crate) fn find(&self, source: SocketAddr, inv_hash: &InvHash) -> Option<Message> {
+        if !matches!(inv_hash.kind(), ObjectKind::Tx | ObjectKind::WTx) {
+            return None;
+        }
+
+        self.requests.lock().push((source, *inv_hash));
+        self.get(inv_hash).map(Message::Tx)
+    }
+
+    /// Subscribes to the inventory of the transactions inserted from now on.
+    pub(crate) fn subscribe(&self) -> broadcast::Receiver<InvHash> {
+        self.announcements.subscribe()
+    }
+}
+
+impl MessageHandler for Mempool {
+    fn handle(&self, source: SocketAddr, message: &Message) -> Response {
+        match message {
+            Message::MemPool => Response::Reply(self.mempool_inv()),
+            Message::GetData(inv) => Response::Reply(self.get_data(source, inv)),
+            _ => Response::Forward,
+        }
+    }
+}
+
+impl fmt::Debug for Mempool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mempool")
+            .field("len", &self.len())
+            .field("requests", &self.requests.lock().len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        protocol::payload::{block::Block, codec::Codec, Hash},
+        tools::{
+            block_store::BlockStore, synthetic_node::SyntheticNode, LONG_TIMEOUT, RECV_TIMEOUT,
+        },
+        vectors::BLOCK_TESTNET_1_599_200_BYTES,
+        wait_until,
+    };
+
+    fn txs() -> Vec<Tx> {
+        let block = Block::decode(&mut Cursor::new(&BLOCK_TESTNET_1_599_200_BYTES[..])).unwrap();
+        let mut txs = block.txs;
+        txs.push(Block::testnet_genesis().txs.remove(0));
+
+        txs
+    }
+
+    #[test]
+    fn get_data_records_requests() {
+        let txs = txs();
+        let mempool = Mempool::with_txs(txs.clone());
+        assert_eq!(mempool.len(), txs.len());
+        assert!(!mempool.insert(txs[0].clone()));
+
+        let source = "127.0.0.1:8233".parse().unwrap();
+        let missing = InvHash::new(ObjectKind::Tx, Hash::zeroed());
+        let block = Block::testnet_genesis().inv_hash();
+        let inv = Inv::new(vec![txs[1].inv_hash(), missing, block, txs[0].inv_hash()]);
+
+        assert_eq!(
+            mempool.get_data(source, &inv),
+            vec![
+                Message::Tx(txs[1].clone()),
+                Message::Tx(txs[0].clone()),
+                Message::NotFound(Inv::new(vec![missing, block])),
+            ]
+        );
+        assert_eq!(
+            mempool.requests(),
+            vec![
+                (source, txs[1].inv_hash()),
+                (source, missing),
+                (source, txs[0].inv_hash())
+            ]
+        );
+
+        assert_eq!(mempool.remove(&txs[0].inv_hash()), Some(txs[0].clone()));
+        assert_eq!(
+            mempool.mempool_inv(),
+            vec![Message::Inv(Inv::new(mempool.inventory()))]
+        );
+        assert!(Mempool::new().mempool_inv().is_empty());
+    }
+
+    #[tokio::test]
+    async fn serves_and_announces_txs() {
+        let txs = txs();
+        let mempool = Mempool::with_txs(txs[..1].to_vec());
+
+        let server = SyntheticNode::builder()
+            .with_full_handshake()
+            .with_mempool(mempool.clone())
+            .with_block_store(BlockStore::initial_testnet())
+            .build()
+            .await
+            .unwrap();
+        let mut client = SyntheticNode::builder()
+            .with_full_handshake()
+            .build()
+            .await
+            .unwrap();
+        client.connect(server.listening_addr()).await.unwrap();
+        wait_until!(LONG_TIMEOUT, server.num_connected() == 1);
+        let addr = server.listening_addr();
+
+        client.unicast(addr, Message::MemPool).unwrap();
+        let (_, reply) = client.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(reply, Message::Inv(Inv::new(vec![txs[0].inv_hash()])));
+
+        // Blocks and transactions are served together.
+        let genesis = Block::testnet_genesis();
+        let inv = Inv::new(vec![txs[0].inv_hash(), genesis.inv_hash()]);
+        client.unicast(addr, Message::GetData(inv)).unwrap();
+        let (_, reply) = client.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(reply, Message::Tx(txs[0].clone()));
+        let (_, reply) = client.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(reply, Message::Block(Box::new(genesis)));
+        assert!(client.recv_message_timeout(RECV_TIMEOUT).await.is_err());
+
+        mempool.insert(txs[1].clone());
+        let (_, announcement) = client.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(
+            announcement,
+            Message::Inv(Inv::new(vec![txs[1].inv_hash()]))
+        );
+
+        let client_addr = server.connected_peers()[0];
+        assert_eq!(mempool.requests(), vec![(client_addr, txs[0].inv_hash())]);
+
+        client.shut_down().await;
+        server.shut_down().await;
+    }
+}
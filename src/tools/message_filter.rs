@@ -1,8 +1,13 @@
 //! Message filtering types and utilities.
 
-use crate::protocol::{
-    message::Message,
-    payload::{block::Headers, Addr},
+use std::net::SocketAddr;
+
+use crate::{
+    protocol::{
+        message::Message,
+        payload::{block::Headers, Addr},
+    },
+    tools::message_handler::{MessageHandler, Response},
 };
 
 /// Controls the filter response of [`MessageFilter`] to messages it receives.
@@ -27,6 +32,9 @@ pub enum Filter {
 /// - [`GetAddr`]
 /// - [`GetData`]
 ///
+/// Any other message can be handled with a [`MessageHandler`], which the filter is itself the
+/// fallback of.
+///
 /// [`Ping`]: Message::Ping
 /// [`GetHeaders`]: Message::GetHeaders
 /// [`GetAddr`]: Message::GetAddr
@@ -37,9 +45,6 @@ pub struct MessageFilter {
     getheaders: Filter,
     getaddr: Filter,
     getdata: Filter,
-    // todo: inv
-    // todo: getblocks
-    // todo: mempool
 }
 
 impl MessageFilter {
@@ -133,3 +138,13 @@ impl MessageFilter {
         }
     }
 }
+
+impl MessageHandler for MessageFilter {
+    fn handle(&self, _source: SocketAddr, message: &Message) -> Response {
+        match self.message_filter_type(message) {
+            Filter::AutoReply => Response::reply(self.reply_message(message)),
+            Filter::Disabled => Response::Forward,
+            Filter::Enabled => Response::ignore(),
+        }
+    }
+}
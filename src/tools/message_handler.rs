@@ -0,0 +1,218 @@
+//! Pluggable handling of the messages a [`SyntheticNode`] receives.
+//!
+//! [`SyntheticNode`]: crate::tools::synthetic_node::SyntheticNode
+
+use std::{collections::HashMap, fmt, net::SocketAddr, sync::Arc};
+
+use crate::protocol::{
+    message::Message,
+    payload::{inv::InvHash, Inv},
+};
+
+/// What a [`MessageHandler`] decided to do with a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    /// Sends the messages back to the source, in order. No messages drops the message silently.
+    Reply(Vec<Message>),
+    /// Disconnects from the source.
+    Disconnect,
+    /// Forwards the message to the node's inbound queue, to be received with
+    /// [`SyntheticNode::recv_message`].
+    ///
+    /// [`SyntheticNode::recv_message`]: crate::tools::synthetic_node::SyntheticNode::recv_message
+    Forward,
+}
+
+impl Response {
+    /// Replies with a single message.
+    pub fn reply(message: Message) -> Self {
+        Self::Reply(vec![message])
+    }
+
+    /// Drops the message without replying.
+    pub fn ignore() -> Self {
+        Self::Reply(Vec::new())
+    }
+}
+
+/// Handles the messages a [`SyntheticNode`] receives, inside its read loop.
+///
+/// Handlers are registered per message command with
+/// [`SyntheticNodeBuilder::with_message_handler`], or for every other message with
+/// [`SyntheticNodeBuilder::with_catch_all_handler`]. Messages without a handler are passed on to
+/// the node's [`MessageFilter`].
+///
+/// Closures taking the source address and the message implement this trait, state shared with
+/// the test can be captured behind an `Arc`.
+///
+/// [`SyntheticNode`]: crate::tools::synthetic_node::SyntheticNode
+/// [`SyntheticNodeBuilder::with_message_handler`]: crate::tools::synthetic_node::SyntheticNodeBuilder::with_message_handler
+/// [`SyntheticNodeBuilder::with_catch_all_handler`]: crate::tools::synthetic_node::SyntheticNodeBuilder::with_catch_all_handler
+/// [`MessageFilter`]: crate::tools::message_filter::MessageFilter
+pub trait MessageHandler: Send + Sync + 'static {
+    /// Decides what to do with the `message` received from `source`.
+    fn handle(&self, source: SocketAddr, message: &Message) -> Response;
+}
+
+impl<F> MessageHandler for F
+where
+    F: Fn(SocketAddr, &Message) -> Response + Send + Sync + 'static,
+{
+    fn handle(&self, source: SocketAddr, message: &Message) -> Response {
+        self(source, message)
+    }
+}
+
+/// Builds the replies to a [`Message::GetData`]: the messages `find` returns for each object, in
+/// the requested order, then a [`Message::NotFound`] for the objects it returned none for.
+pub(crate) fn get_data_replies(
+    inv: &Inv,
+    mut find: impl FnMut(&InvHash) -> Vec<Message>,
+) -> Vec<Message> {
+    let mut replies = Vec::new();
+    let mut not_found = Vec::new();
+
+    for inv_hash in &inv.inventory {
+        let found = find(inv_hash);
+        if found.is_empty() {
+            not_found.push(*inv_hash);
+        }
+        replies.extend(found);
+    }
+
+    if !not_found.is_empty() {
+        replies.push(Message::NotFound(Inv::new(not_found)));
+    }
+
+    replies
+}
+
+/// The handlers registered with a node, keyed by message command.
+#[derive(Clone, Default)]
+pub(crate) struct MessageHandlers {
+    by_command: HashMap<[u8; 12], Arc<dyn MessageHandler>>,
+    catch_all: Option<Arc<dyn MessageHandler>>,
+}
+
+impl MessageHandlers {
+    pub(crate) fn insert(&mut self, command: [u8; 12], handler: Arc<dyn MessageHandler>) {
+        self.by_command.insert(command, handler);
+    }
+
+    pub(crate) fn set_catch_all(&mut self, handler: Arc<dyn MessageHandler>) {
+        self.catch_all = Some(handler);
+    }
+
+    /// Returns the handler for the message's command, falling back to the catch-all one.
+    pub(crate) fn get(&self, message: &Message) -> Option<&dyn MessageHandler> {
+        self.by_command
+            .get(&message.command())
+            .or(self.catch_all.as_ref())
+            .map(|handler| &**handler)
+    }
+}
+
+impl fmt::Debug for MessageHandlers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<_> = self
+            .by_command
+            .keys()
+            .map(|command| {
+                String::from_utf8_lossy(command)
+                    .trim_end_matches('\0')
+                    .to_owned()
+            })
+            .collect();
+
+        f.debug_struct("MessageHandlers")
+            .field("commands", &commands)
+            .field("catch_all", &self.catch_all.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        protocol::{
+            message::constants::GETBLOCKS_COMMAND,
+            payload::{block::LocatorHashes, Inv, Nonce},
+        },
+        tools::{synthetic_node::SyntheticNode, LONG_TIMEOUT, RECV_TIMEOUT},
+        wait_until,
+    };
+
+    #[tokio::test]
+    async fn handlers_take_precedence_over_the_filter() {
+        let responder = SyntheticNode::builder()
+            .with_full_handshake()
+            .with_all_auto_reply()
+            .with_message_handler(GETBLOCKS_COMMAND, |_, _: &Message| {
+                Response::reply(Message::Inv(Inv::empty()))
+            })
+            .build()
+            .await
+            .unwrap();
+        let mut initiator = SyntheticNode::builder()
+            .with_full_handshake()
+            .build()
+            .await
+            .unwrap();
+        initiator.connect(responder.listening_addr()).await.unwrap();
+
+        initiator
+            .unicast(
+                responder.listening_addr(),
+                Message::GetBlocks(LocatorHashes::empty()),
+            )
+            .unwrap();
+        let (_, reply) = initiator.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(reply, Message::Inv(Inv::empty()));
+
+        // Messages without a handler still go through the filter.
+        let nonce = Nonce::default();
+        initiator
+            .unicast(responder.listening_addr(), Message::Ping(nonce))
+            .unwrap();
+        let (_, reply) = initiator.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(reply, Message::Pong(nonce));
+
+        initiator.shut_down().await;
+        responder.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn catch_all_forwards_and_disconnects() {
+        let mut responder = SyntheticNode::builder()
+            .with_full_handshake()
+            .with_catch_all_handler(|_, message: &Message| match message {
+                Message::MemPool => Response::Disconnect,
+                Message::GetAddr => Response::ignore(),
+                _ => Response::Forward,
+            })
+            .build()
+            .await
+            .unwrap();
+        let initiator = SyntheticNode::builder()
+            .with_full_handshake()
+            .build()
+            .await
+            .unwrap();
+        initiator.connect(responder.listening_addr()).await.unwrap();
+        let addr = responder.listening_addr();
+
+        initiator.unicast(addr, Message::GetAddr).unwrap();
+        let ping = Message::Ping(Nonce::default());
+        initiator.unicast(addr, ping.clone()).unwrap();
+        let (_, forwarded) = responder.recv_message_timeout(LONG_TIMEOUT).await.unwrap();
+        assert_eq!(forwarded, ping);
+
+        initiator.unicast(addr, Message::MemPool).unwrap();
+        wait_until!(LONG_TIMEOUT, !initiator.is_connected(addr));
+        assert!(responder.recv_message_timeout(RECV_TIMEOUT).await.is_err());
+
+        initiator.shut_down().await;
+        responder.shut_down().await;
+    }
+}
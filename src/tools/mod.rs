@@ -1,7 +1,10 @@
 //! Utilities for network testing.
 
+pub mod block_store;
 pub mod fuzzing;
+pub mod mempool;
 pub mod message_filter;
+pub mod message_handler;
 pub mod metrics;
 pub mod synthetic_node;
 
@@ -1,20 +1,27 @@
 //! A lightweight node implementation to be used as peers in tests.
 
 use std::{
+    collections::HashSet,
     io::{self, Error, ErrorKind},
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
     time::Duration,
 };
 
-use assert_matches::assert_matches;
 use bytes::{BufMut, BytesMut};
 use futures_util::{sink::SinkExt, TryStreamExt};
+use parking_lot::Mutex;
 use pea2pea::{
     protocols::{Handshake, Reading, Writing},
     Config as NodeConfig, Connection, ConnectionSide, KnownPeers, Node, Pea2Pea,
 };
 use tokio::{
-    sync::mpsc::{self, Receiver, Sender},
+    net::TcpStream,
+    sync::{
+        broadcast::{self, error::RecvError},
+        mpsc::{self, Receiver, Sender},
+    },
+    task::JoinHandle,
     time::timeout,
 };
 use tokio_util::codec::{Decoder, Encoder, Framed, LengthDelimitedCodec};
@@ -22,10 +29,23 @@ use tracing::*;
 
 use crate::{
     protocol::{
-        message::{Message, MessageHeader},
-        payload::{codec::Codec, Nonce, Version},
+        message::{
+            checksum,
+            constants::{
+                BLOCK_COMMAND, GETBLOCKS_COMMAND, GETDATA_COMMAND, GETHEADERS_COMMAND, HEADER_LEN,
+                MAX_MESSAGE_LEN, MEMPOOL_COMMAND,
+            },
+            Message, MessageHeader,
+        },
+        network::Network,
+        payload::{codec::Codec, inv::InvHash, Inv, LazyBlock, Nonce, Version},
+    },
+    tools::{
+        block_store::BlockStore,
+        mempool::Mempool,
+        message_filter::MessageFilter,
+        message_handler::{get_data_replies, MessageHandler, MessageHandlers, Response},
     },
-    tools::message_filter::{Filter, MessageFilter},
 };
 
 /// An [`Error`](std::error::Error) type for [`SyntheticNode::ping_pong_timeout`]
@@ -110,12 +130,176 @@ pub enum HandshakeKind {
     VersionOnly,
 }
 
+/// A step of a [`HandshakeScript`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeStep {
+    /// Sends the node's [`Version`], see [`SyntheticNodeBuilder::with_version_template`].
+    SendVersion,
+    /// Sends [`Message::Verack`], preceded by [`Message::SendAddrV2`] if enabled.
+    SendVerack,
+    /// Sends the message as is.
+    Send(Box<Message>),
+    /// Receives the peer's [`Message::Version`].
+    ReceiveVersion,
+    /// Receives the peer's [`Message::Verack`], which may be preceded by [`Message::SendAddrV2`].
+    ReceiveVerack,
+    /// Waits before performing the next step.
+    Delay(Duration),
+}
+
+/// A handshake as a sequence of [`HandshakeStep`]s, used to deviate from the expected message
+/// exchange in a controlled way.
+///
+/// The constructors describe common deviations from the initiator's side of the
+/// [`HandshakeKind::Full`] handshake, [`HandshakeScript::then`] appends any other step. Messages
+/// received out of script fail the handshake with a [`HandshakeError`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HandshakeScript {
+    steps: Vec<HandshakeStep>,
+}
+
+impl HandshakeScript {
+    /// Creates an empty script, completing the handshake without exchanging any messages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The [`HandshakeKind::Full`] handshake, waiting for `delay` before sending
+    /// [`Message::Verack`].
+    pub fn delayed_verack(delay: Duration) -> Self {
+        Self::new()
+            .then(HandshakeStep::SendVersion)
+            .then(HandshakeStep::ReceiveVersion)
+            .then(HandshakeStep::Delay(delay))
+            .then(HandshakeStep::SendVerack)
+            .then(HandshakeStep::ReceiveVerack)
+    }
+
+    /// The [`HandshakeKind::Full`] handshake, sending the `messages` between the
+    /// [`Message::Version`] exchange and [`Message::Verack`].
+    pub fn messages_before_verack(messages: Vec<Message>) -> Self {
+        let script = Self::new()
+            .then(HandshakeStep::SendVersion)
+            .then(HandshakeStep::ReceiveVersion);
+
+        messages
+            .into_iter()
+            .fold(script, |script, message| {
+                script.then(HandshakeStep::Send(Box::new(message)))
+            })
+            .then(HandshakeStep::SendVerack)
+            .then(HandshakeStep::ReceiveVerack)
+    }
+
+    /// The [`HandshakeKind::Full`] handshake, sending [`Message::Verack`] before
+    /// [`Message::Version`].
+    pub fn verack_first() -> Self {
+        Self::new()
+            .then(HandshakeStep::SendVerack)
+            .then(HandshakeStep::SendVersion)
+            .then(HandshakeStep::ReceiveVersion)
+            .then(HandshakeStep::ReceiveVerack)
+    }
+
+    /// The [`HandshakeKind::Full`] handshake, sending [`Message::Version`] a second time once
+    /// the peer's was received.
+    pub fn repeated_version() -> Self {
+        Self::new()
+            .then(HandshakeStep::SendVersion)
+            .then(HandshakeStep::ReceiveVersion)
+            .then(HandshakeStep::SendVersion)
+            .then(HandshakeStep::SendVerack)
+            .then(HandshakeStep::ReceiveVerack)
+    }
+
+    /// Appends the step to the script.
+    pub fn then(mut self, step: HandshakeStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Returns the steps of the script.
+    pub fn steps(&self) -> &[HandshakeStep] {
+        &self.steps
+    }
+
+    /// The steps of the [`HandshakeKind::Full`] and [`HandshakeKind::VersionOnly`] handshakes,
+    /// as performed by the node on `side`.
+    fn of(kind: HandshakeKind, side: ConnectionSide) -> Self {
+        use HandshakeStep::*;
+
+        let steps = match (kind, side) {
+            (HandshakeKind::Full, ConnectionSide::Initiator) => {
+                vec![SendVersion, ReceiveVersion, SendVerack, ReceiveVerack]
+            }
+            (HandshakeKind::Full, ConnectionSide::Responder) => {
+                vec![ReceiveVersion, SendVersion, ReceiveVerack, SendVerack]
+            }
+            (HandshakeKind::VersionOnly, ConnectionSide::Initiator) => {
+                vec![SendVersion, ReceiveVersion]
+            }
+            (HandshakeKind::VersionOnly, ConnectionSide::Responder) => {
+                vec![ReceiveVersion, SendVersion]
+            }
+        };
+
+        Self { steps }
+    }
+}
+
+/// The peer deviated from the handshake.
+///
+/// The handshake fails with an [`io::Error`] wrapping this error, which can be recovered with
+/// [`io::Error::get_ref`] and `downcast_ref`. Its kind is [`ErrorKind::ConnectionRefused`] if
+/// the peer closed the connection the node initiated, [`ErrorKind::InvalidData`] otherwise. The
+/// errors are also recorded, see [`SyntheticNode::handshake_errors`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandshakeError {
+    /// The message expected from the peer, e.g. `"Verack"`.
+    pub expected: &'static str,
+    /// The message received instead, `None` if the peer closed the connection.
+    pub received: Option<Box<Message>>,
+}
+
+impl std::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.received {
+            Some(message) => write!(f, "expected {}, received {}", self.expected, message),
+            None => write!(f, "expected {}, the connection was closed", self.expected),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+impl HandshakeError {
+    /// Wraps the error to fail the handshake of the node on `side` with.
+    fn into_io_error(self, side: ConnectionSide) -> io::Error {
+        let kind = match (&self.received, side) {
+            // Connection was refused by the peer, quietly abort the handshake.
+            (None, ConnectionSide::Initiator) => ErrorKind::ConnectionRefused,
+            _ => ErrorKind::InvalidData,
+        };
+
+        Error::new(kind, self)
+    }
+}
+
 /// A builder for [`SyntheticNode`].
 #[derive(Debug, Clone)]
 pub struct SyntheticNodeBuilder {
     network_config: NodeConfig,
+    network: Network,
+    codec_mode: CodecMode,
+    lazy_blocks: bool,
     handshake: Option<HandshakeKind>,
+    handshake_script: Option<HandshakeScript>,
+    addrv2: bool,
+    version_template: Option<Version>,
     message_filter: MessageFilter,
+    message_handlers: MessageHandlers,
+    block_store: Option<BlockStore>,
+    mempool: Option<Mempool>,
 }
 
 impl Default for SyntheticNodeBuilder {
@@ -126,8 +310,17 @@ impl Default for SyntheticNodeBuilder {
                 listener_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
                 ..Default::default()
             },
+            network: Network::default(),
+            codec_mode: CodecMode::default(),
+            lazy_blocks: false,
             handshake: None,
+            handshake_script: None,
+            addrv2: false,
+            version_template: None,
             message_filter: MessageFilter::with_all_disabled(),
+            message_handlers: MessageHandlers::default(),
+            block_store: None,
+            mempool: None,
         }
     }
 }
@@ -140,16 +333,24 @@ impl SyntheticNodeBuilder {
 
         // Inbound channel size of 100 messages.
         let (tx, rx) = mpsc::channel(100);
-        let inner_node =
-            InnerNode::new(node, tx, self.message_filter.clone(), self.handshake).await;
+        let inner_node = InnerNode::new(node, tx, self).await;
 
         // Enable the read and write protocols
         inner_node.enable_reading().await;
         inner_node.enable_writing().await;
 
+        let announcers = self
+            .block_store
+            .iter()
+            .map(BlockStore::subscribe)
+            .chain(self.mempool.iter().map(Mempool::subscribe))
+            .map(|announcements| inner_node.spawn_announcer(announcements))
+            .collect();
+
         Ok(SyntheticNode {
             inner_node,
             inbound_rx: rx,
+            announcers,
         })
     }
 
@@ -166,7 +367,28 @@ impl SyntheticNodeBuilder {
         Ok((nodes, addrs))
     }
 
+    /// Sets the [`Network`] the node communicates on, defaults to [`Network::Testnet`].
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Sets the [`CodecMode`] used to decode inbound frames, defaults to [`CodecMode::Strict`].
+    pub fn with_codec_mode(mut self, mode: CodecMode) -> Self {
+        self.codec_mode = mode;
+        self
+    }
+
+    /// Receives blocks as [`Message::LazyBlock`] instead of [`Message::Block`], so that peers
+    /// sending many large blocks don't tie up the node decoding them.
+    pub fn with_lazy_blocks(mut self) -> Self {
+        self.lazy_blocks = true;
+        self
+    }
+
     /// Sets the node's [`MessageFilter`] to [`Filter::AutoReply`].
+    ///
+    /// [`Filter::AutoReply`]: crate::tools::message_filter::Filter::AutoReply
     pub fn with_all_auto_reply(mut self) -> Self {
         self.message_filter = MessageFilter::with_all_auto_reply();
         self
@@ -175,12 +397,42 @@ impl SyntheticNodeBuilder {
     /// Enables handshaking with [`HandshakeKind::Full`].
     pub fn with_full_handshake(mut self) -> Self {
         self.handshake = Some(HandshakeKind::Full);
+        self.handshake_script = None;
         self
     }
 
     /// Enables handshaking with [`HandshakeKind::VersionOnly`].
     pub fn with_version_exchange_handshake(mut self) -> Self {
         self.handshake = Some(HandshakeKind::VersionOnly);
+        self.handshake_script = None;
+        self
+    }
+
+    /// Enables handshaking with the steps of the [`HandshakeScript`], performed in order
+    /// whichever side of the connection the node is on.
+    pub fn with_scripted_handshake(mut self, script: HandshakeScript) -> Self {
+        self.handshake = None;
+        self.handshake_script = Some(script);
+        self
+    }
+
+    /// Sends [`SendAddrV2`] before [`Verack`] in the [`HandshakeKind::Full`] handshake, see
+    /// [`SyntheticNode::is_addrv2_negotiated`].
+    ///
+    /// [`SendAddrV2`]: enum@crate::protocol::message::Message::SendAddrV2
+    /// [`Verack`]: enum@crate::protocol::message::Message::Verack
+    pub fn with_addrv2(mut self) -> Self {
+        self.addrv2 = true;
+        self
+    }
+
+    /// Sets the [`Version`] sent during the handshake, built with [`VersionBuilder`]. Only the
+    /// addresses are replaced to match each connection, every other field, including the nonce
+    /// and timestamp, is sent as is. Defaults to [`Version::new`].
+    ///
+    /// [`VersionBuilder`]: crate::protocol::payload::VersionBuilder
+    pub fn with_version_template(mut self, version: Version) -> Self {
+        self.version_template = Some(version);
         self
     }
 
@@ -189,12 +441,85 @@ impl SyntheticNodeBuilder {
         self.message_filter = filter;
         self
     }
+
+    /// Handles the messages sent with `command`, e.g. [`GETBLOCKS_COMMAND`], with the
+    /// [`MessageHandler`] instead of the [`MessageFilter`]. Replaces any handler previously set
+    /// for the command.
+    ///
+    /// [`GETBLOCKS_COMMAND`]: crate::protocol::message::constants::GETBLOCKS_COMMAND
+    pub fn with_message_handler(mut self, command: [u8; 12], handler: impl MessageHandler) -> Self {
+        self.message_handlers.insert(command, Arc::new(handler));
+        self
+    }
+
+    /// Handles the messages without a handler for their command with the [`MessageHandler`],
+    /// instead of the [`MessageFilter`].
+    pub fn with_catch_all_handler(mut self, handler: impl MessageHandler) -> Self {
+        self.message_handlers.set_catch_all(Arc::new(handler));
+        self
+    }
+
+    /// Serves the chain in the [`BlockStore`], answering [`Message::GetHeaders`],
+    /// [`Message::GetBlocks`] and [`Message::GetData`] from it and announcing the blocks pushed
+    /// to it with [`Message::Inv`]. Replaces any handler previously set for these commands.
+    pub fn with_block_store(mut self, store: BlockStore) -> Self {
+        for command in [GETHEADERS_COMMAND, GETBLOCKS_COMMAND] {
+            self.message_handlers
+                .insert(command, Arc::new(store.clone()));
+        }
+        self.block_store = Some(store);
+        self.insert_get_data_handler();
+        self
+    }
+
+    /// Serves the transactions in the [`Mempool`], answering [`Message::MemPool`] and
+    /// [`Message::GetData`] from it and announcing the transactions inserted into it with
+    /// [`Message::Inv`]. Replaces any handler previously set for these commands.
+    ///
+    /// Can be combined with [`SyntheticNodeBuilder::with_block_store`], [`Message::GetData`] is
+    /// then answered from both.
+    pub fn with_mempool(mut self, mempool: Mempool) -> Self {
+        self.message_handlers
+            .insert(MEMPOOL_COMMAND, Arc::new(mempool.clone()));
+        self.mempool = Some(mempool);
+        self.insert_get_data_handler();
+        self
+    }
+
+    /// Answers [`Message::GetData`] from the block store and the mempool, whichever are set.
+    fn insert_get_data_handler(&mut self) {
+        let block_store = self.block_store.clone();
+        let mempool = self.mempool.clone();
+
+        let handler = move |source, message: &Message| match message {
+            Message::GetData(inv) => Response::Reply(get_data_replies(inv, |inv_hash| {
+                let blocks = block_store
+                    .as_ref()
+                    .map(|store| store.find(source, inv_hash))
+                    .unwrap_or_default();
+                if !blocks.is_empty() {
+                    return blocks;
+                }
+
+                mempool
+                    .as_ref()
+                    .and_then(|mempool| mempool.find(source, inv_hash))
+                    .into_iter()
+                    .collect()
+            })),
+            _ => Response::Forward,
+        };
+
+        self.message_handlers
+            .insert(GETDATA_COMMAND, Arc::new(handler));
+    }
 }
 
 /// Convenient abstraction over a `pea2pea` node.
 pub struct SyntheticNode {
     inner_node: InnerNode,
     inbound_rx: Receiver<(SocketAddr, Message)>,
+    announcers: Vec<JoinHandle<()>>,
 }
 
 impl SyntheticNode {
@@ -203,6 +528,33 @@ impl SyntheticNode {
         SyntheticNodeBuilder::default()
     }
 
+    /// Returns the [`Network`] the node communicates on.
+    pub fn network(&self) -> Network {
+        self.inner_node.network
+    }
+
+    /// Returns the [`FrameError`]s encountered so far while decoding inbound frames, along with
+    /// the address of the peer that sent them.
+    pub fn frame_errors(&self) -> Vec<(SocketAddr, FrameError)> {
+        self.inner_node.frame_errors.lock().clone()
+    }
+
+    /// Returns the [`HandshakeError`]s encountered so far, along with the address of the peer
+    /// that deviated from the handshake.
+    pub fn handshake_errors(&self) -> Vec<(SocketAddr, HandshakeError)> {
+        self.inner_node.handshake_errors.lock().clone()
+    }
+
+    /// Returns `true` if both sides sent [`SendAddrV2`] during the handshake with the peer, so
+    /// addresses are exchanged with [`AddrV2`] instead of [`Addr`].
+    ///
+    /// [`SendAddrV2`]: enum@crate::protocol::message::Message::SendAddrV2
+    /// [`AddrV2`]: enum@crate::protocol::message::Message::AddrV2
+    /// [`Addr`]: enum@crate::protocol::message::Message::Addr
+    pub fn is_addrv2_negotiated(&self, addr: SocketAddr) -> bool {
+        self.inner_node.addrv2_peers.lock().contains(&addr)
+    }
+
     /// Returns the listening address of the node.
     pub fn listening_addr(&self) -> SocketAddr {
         self.inner_node.node().listening_addr().unwrap()
@@ -368,38 +720,196 @@ impl SyntheticNode {
 
     /// Gracefully shuts down the node.
     pub async fn shut_down(&self) {
+        for announcer in &self.announcers {
+            announcer.abort();
+        }
         self.inner_node.node().shut_down().await
     }
 }
 
+impl Drop for SyntheticNode {
+    fn drop(&mut self) {
+        // The announcers keep the node alive otherwise.
+        for announcer in &self.announcers {
+            announcer.abort();
+        }
+    }
+}
+
 #[derive(Clone)]
 struct InnerNode {
     node: Node,
+    network: Network,
+    codec_mode: CodecMode,
+    lazy_blocks: bool,
+    frame_errors: FrameErrorLog,
     handshake: Option<HandshakeKind>,
+    handshake_script: Option<HandshakeScript>,
+    handshake_errors: Arc<Mutex<Vec<(SocketAddr, HandshakeError)>>>,
+    addrv2: bool,
+    addrv2_peers: Arc<Mutex<HashSet<SocketAddr>>>,
+    version_template: Option<Version>,
     inbound_tx: Sender<(SocketAddr, Message)>,
     message_filter: MessageFilter,
+    message_handlers: MessageHandlers,
 }
 
 impl InnerNode {
     async fn new(
         node: Node,
         tx: Sender<(SocketAddr, Message)>,
-        message_filter: MessageFilter,
-        handshake: Option<HandshakeKind>,
+        builder: &SyntheticNodeBuilder,
     ) -> Self {
         let node = Self {
             node,
+            network: builder.network,
+            codec_mode: builder.codec_mode,
+            lazy_blocks: builder.lazy_blocks,
+            frame_errors: Default::default(),
             inbound_tx: tx,
-            message_filter,
-            handshake,
+            message_filter: builder.message_filter.clone(),
+            message_handlers: builder.message_handlers.clone(),
+            handshake: builder.handshake,
+            handshake_script: builder.handshake_script.clone(),
+            handshake_errors: Default::default(),
+            addrv2: builder.addrv2,
+            addrv2_peers: Default::default(),
+            version_template: builder.version_template.clone(),
         };
 
-        if handshake.is_some() {
+        if node.handshake.is_some() || node.handshake_script.is_some() {
             node.enable_handshake().await;
         }
 
         node
     }
+
+    /// Creates a [`MessageCodec`] for the connection to `addr`, recording its frame errors.
+    fn message_codec(&self, addr: SocketAddr) -> MessageCodec {
+        let codec = MessageCodec::new(self.network)
+            .with_mode(self.codec_mode)
+            .with_error_log(addr, self.frame_errors.clone());
+
+        if self.lazy_blocks {
+            codec.with_lazy_blocks()
+        } else {
+            codec
+        }
+    }
+
+    /// Creates the [`Message::Version`] to send, from the template if one is set.
+    fn own_version(&self, addr_recv: SocketAddr, addr_from: SocketAddr) -> Message {
+        let version = match &self.version_template {
+            Some(template) => {
+                let mut version = template.clone();
+                version.addr_recv.addr = addr_recv;
+                version.addr_from.addr = addr_from;
+                version
+            }
+            None => Version::new(addr_recv, addr_from),
+        };
+
+        Message::Version(version)
+    }
+
+    /// Sends [`Message::Verack`], preceded by [`Message::SendAddrV2`] if enabled.
+    async fn send_verack(
+        &self,
+        framed_stream: &mut Framed<&mut TcpStream, MessageCodec>,
+    ) -> io::Result<()> {
+        if self.addrv2 {
+            framed_stream.send(Message::SendAddrV2).await?;
+        }
+
+        framed_stream.send(Message::Verack).await
+    }
+
+    /// Receives [`Message::Version`].
+    async fn receive_version(
+        &self,
+        framed_stream: &mut Framed<&mut TcpStream, MessageCodec>,
+        addr: SocketAddr,
+        side: ConnectionSide,
+    ) -> io::Result<Version> {
+        match framed_stream.try_next().await? {
+            Some(Message::Version(version)) => Ok(version),
+            other => Err(self.handshake_failed(addr, side, "Version", other)),
+        }
+    }
+
+    /// Receives [`Message::Verack`], which the peer may precede with [`Message::SendAddrV2`].
+    async fn receive_verack(
+        &self,
+        framed_stream: &mut Framed<&mut TcpStream, MessageCodec>,
+        addr: SocketAddr,
+        side: ConnectionSide,
+    ) -> io::Result<()> {
+        let mut peer_message = framed_stream.try_next().await?;
+        let peer_addrv2 = matches!(peer_message, Some(Message::SendAddrV2));
+        if peer_addrv2 {
+            peer_message = framed_stream.try_next().await?;
+        }
+
+        if !matches!(peer_message, Some(Message::Verack)) {
+            return Err(self.handshake_failed(addr, side, "Verack", peer_message));
+        }
+
+        // Addresses are reused across connections, so stale entries are removed.
+        let mut addrv2_peers = self.addrv2_peers.lock();
+        if self.addrv2 && peer_addrv2 {
+            addrv2_peers.insert(addr);
+        } else {
+            addrv2_peers.remove(&addr);
+        }
+
+        Ok(())
+    }
+
+    /// Records the [`HandshakeError`] and returns it as an [`io::Error`] to fail the handshake
+    /// of the node on `side` with.
+    fn handshake_failed(
+        &self,
+        addr: SocketAddr,
+        side: ConnectionSide,
+        expected: &'static str,
+        received: Option<Message>,
+    ) -> io::Error {
+        let err = HandshakeError {
+            expected,
+            received: received.map(Box::new),
+        };
+        error!(parent: self.node().span(), "handshake with {} failed: {}", addr, err);
+        self.handshake_errors.lock().push((addr, err.clone()));
+
+        err.into_io_error(side)
+    }
+
+    /// Spawns a task sending [`Message::Inv`] to every connected peer for each announcement.
+    fn spawn_announcer(&self, mut announcements: broadcast::Receiver<InvHash>) -> JoinHandle<()> {
+        let node = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let inv_hash = match announcements.recv().await {
+                    Ok(inv_hash) => inv_hash,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!(parent: node.node().span(), "skipped {} announcements", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                let inv = Message::Inv(Inv::new(vec![inv_hash]));
+                for addr in node.node().connected_addrs() {
+                    if let Err(err) =
+                        node.unicast(addr, MessageOrBytes::Message(inv.clone().into()))
+                    {
+                        warn!(parent: node.node().span(), "couldn't announce to {}: {}", addr, err);
+                    }
+                }
+            }
+        })
+    }
 }
 
 impl Pea2Pea for InnerNode {
@@ -408,23 +918,138 @@ impl Pea2Pea for InnerNode {
     }
 }
 
+/// Selects how strictly a [`MessageCodec`] validates inbound frames.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CodecMode {
+    /// Frames failing any check are rejected with a [`FrameError`].
+    #[default]
+    Strict,
+    /// Frames with a bad magic or checksum are still decoded, the [`FrameError`] is only recorded.
+    ///
+    /// Frames exceeding the maximum length are always rejected.
+    Lenient,
+}
+
+/// The frame check failed by an inbound message.
+///
+/// Rejected frames surface as an [`io::Error`] of kind [`ErrorKind::InvalidData`] wrapping this
+/// error, which can be recovered with [`io::Error::get_ref`] and `downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError {
+    /// The header magic doesn't match the codec's [`Network`].
+    Magic { expected: [u8; 4], actual: [u8; 4] },
+    /// The header checksum doesn't match the body.
+    Checksum { expected: u32, actual: u32 },
+    /// The header body length exceeds the codec's maximum.
+    Length { length: usize, max: usize },
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Magic { expected, actual } => write!(
+                f,
+                "header magic {} doesn't match the expected {}",
+                hex::encode(actual),
+                hex::encode(expected)
+            ),
+            FrameError::Checksum { expected, actual } => write!(
+                f,
+                "header checksum {:#010x} doesn't match the body checksum {:#010x}",
+                actual, expected
+            ),
+            FrameError::Length { length, max } => {
+                write!(f, "body length {} exceeds the maximum of {}", length, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+impl From<FrameError> for io::Error {
+    fn from(err: FrameError) -> Self {
+        Error::new(ErrorKind::InvalidData, err)
+    }
+}
+
+/// A shared record of the [`FrameError`]s encountered by a node's codecs, per peer.
+pub type FrameErrorLog = Arc<Mutex<Vec<(SocketAddr, FrameError)>>>;
+
 // TODO: move to protocol
 pub struct MessageCodec {
     codec: LengthDelimitedCodec,
+    network: Network,
+    mode: CodecMode,
+    max_length: usize,
+    lazy_blocks: bool,
+    error_log: Option<(SocketAddr, FrameErrorLog)>,
 }
 
 impl Default for MessageCodec {
     fn default() -> Self {
+        Self::new(Network::default())
+    }
+}
+
+impl MessageCodec {
+    /// Creates a [`CodecMode::Strict`] codec for messages on the given [`Network`], accepting
+    /// bodies of up to [`MAX_MESSAGE_LEN`] bytes.
+    pub fn new(network: Network) -> Self {
         Self {
+            network,
+            mode: CodecMode::default(),
+            max_length: MAX_MESSAGE_LEN,
+            lazy_blocks: false,
+            error_log: None,
             codec: LengthDelimitedCodec::builder()
-                .length_adjustment(24)
+                .length_adjustment(HEADER_LEN as isize)
                 .length_field_offset(16)
                 .little_endian()
                 .num_skip(0)
-                .max_frame_length(65536) // FIXME
+                .max_frame_length(MAX_MESSAGE_LEN)
                 .new_codec(),
         }
     }
+
+    /// Sets the [`CodecMode`], defaults to [`CodecMode::Strict`].
+    pub fn with_mode(mut self, mode: CodecMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the maximum accepted body length, defaults to [`MAX_MESSAGE_LEN`].
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self.codec.set_max_frame_length(max_length);
+        self
+    }
+
+    /// Decodes blocks into [`Message::LazyBlock`], keeping the frame's bytes instead of decoding
+    /// every transaction.
+    pub fn with_lazy_blocks(mut self) -> Self {
+        self.lazy_blocks = true;
+        self
+    }
+
+    /// Records every [`FrameError`] encountered while decoding frames from `addr` in `log`.
+    pub fn with_error_log(mut self, addr: SocketAddr, log: FrameErrorLog) -> Self {
+        self.error_log = Some((addr, log));
+        self
+    }
+
+    /// Records the error and, in [`CodecMode::Strict`] or if `fatal`, returns it.
+    fn check_failed(&self, err: FrameError, fatal: bool) -> io::Result<()> {
+        if let Some((addr, log)) = &self.error_log {
+            log.lock().push((*addr, err.clone()));
+        }
+
+        if fatal || self.mode == CodecMode::Strict {
+            Err(err.into())
+        } else {
+            Ok(())
+        }
+    }
 }
 
 impl Decoder for MessageCodec {
@@ -432,6 +1057,15 @@ impl Decoder for MessageCodec {
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Reject oversized frames as soon as the header is in, instead of buffering their body.
+        if src.len() >= HEADER_LEN {
+            let length = u32::from_le_bytes(src[16..20].try_into().unwrap()) as usize;
+            if length > self.max_length {
+                let max = self.max_length;
+                self.check_failed(FrameError::Length { length, max }, true)?;
+            }
+        }
+
         let mut bytes = if let Some(bytes) = self.codec.decode(src)? {
             bytes
         } else {
@@ -439,7 +1073,25 @@ impl Decoder for MessageCodec {
         };
 
         let header = MessageHeader::decode(&mut bytes)?;
-        let message = Message::decode(header.command, &mut bytes)?;
+
+        let expected = self.network.magic();
+        if header.magic != expected {
+            let actual = header.magic;
+            self.check_failed(FrameError::Magic { expected, actual }, false)?;
+        }
+
+        let expected = checksum(&bytes);
+        if header.checksum != expected {
+            let actual = header.checksum;
+            self.check_failed(FrameError::Checksum { expected, actual }, false)?;
+        }
+
+        let message = if self.lazy_blocks && header.command == BLOCK_COMMAND {
+            // Splits the block off the frame without copying it.
+            Message::LazyBlock(LazyBlock::decode_detailed(&mut bytes)?)
+        } else {
+            Message::decode(header.command, &mut bytes)?
+        };
 
         Ok(Some(message))
     }
@@ -459,7 +1111,7 @@ impl Encoder<Message> for MessageCodec {
     type Error = io::Error;
 
     fn encode(&mut self, message: Message, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        message.encode(dst)
+        message.encode(self.network, dst)
     }
 }
 
@@ -485,24 +1137,34 @@ impl Reading for InnerNode {
     type Message = Message;
     type Codec = MessageCodec;
 
-    fn codec(&self, _addr: SocketAddr, _side: ConnectionSide) -> Self::Codec {
-        Default::default()
+    fn codec(&self, addr: SocketAddr, _side: ConnectionSide) -> Self::Codec {
+        self.message_codec(addr)
     }
 
     async fn process_message(&self, source: SocketAddr, message: Self::Message) -> io::Result<()> {
         let span = self.node().span().clone();
 
         debug!(parent: span.clone(), "processing {:?}", message);
-        match self.message_filter.message_filter_type(&message) {
-            Filter::AutoReply => {
-                // Autoreply with the appropriate response.
-                let response = self.message_filter.reply_message(&message);
+        // Registered handlers take precedence over the filter.
+        let response = match self.message_handlers.get(&message) {
+            Some(handler) => handler.handle(source, &message),
+            None => self.message_filter.handle(source, &message),
+        };
+
+        match response {
+            Response::Reply(replies) if replies.is_empty() => {
+                // Ignore the message.
+                debug!(parent: span, "message was ignored");
+            }
 
-                debug!(parent: span, "auto replying with {:?}", response);
-                self.unicast(source, MessageOrBytes::Message(response.into()))?;
+            Response::Reply(replies) => {
+                for reply in replies {
+                    debug!(parent: span.clone(), "replying with {:?}", reply);
+                    self.unicast(source, MessageOrBytes::Message(reply.into()))?;
+                }
             }
 
-            Filter::Disabled => {
+            Response::Forward => {
                 // Send the message to the node's inbound queue.
                 debug!(
                     parent: span,
@@ -514,9 +1176,9 @@ impl Reading for InnerNode {
                     .expect("receiver dropped!");
             }
 
-            Filter::Enabled => {
-                // Ignore the message.
-                debug!(parent: span, "message was ignored by the filter");
+            Response::Disconnect => {
+                debug!(parent: span, "disconnecting from {}", source);
+                self.node().disconnect(source).await;
             }
         }
 
@@ -528,8 +1190,8 @@ impl Writing for InnerNode {
     type Message = MessageOrBytes;
     type Codec = MessageCodec;
 
-    fn codec(&self, _addr: SocketAddr, _side: ConnectionSide) -> Self::Codec {
-        Default::default()
+    fn codec(&self, addr: SocketAddr, _side: ConnectionSide) -> Self::Codec {
+        self.message_codec(addr)
     }
 }
 
@@ -539,91 +1201,331 @@ impl Handshake for InnerNode {
         let node_conn_side = !conn.side();
         let conn_addr = conn.addr();
         let own_listening_addr = self.node().listening_addr().unwrap();
-        let mut framed_stream = Framed::new(self.borrow_stream(&mut conn), MessageCodec::default());
-
-        match (self.handshake, node_conn_side) {
-            (Some(HandshakeKind::Full), ConnectionSide::Initiator) => {
-                // Send and receive Version.
-                let own_version = Message::Version(Version::new(conn_addr, own_listening_addr));
-                framed_stream.send(own_version).await?;
-
-                let peer_version = framed_stream.try_next().await?;
-                match peer_version {
-                    Some(Message::Version(_)) => {
-                        // Send and receive Verack.
-                        framed_stream.send(Message::Verack).await?;
-
-                        let peer_verack = framed_stream.try_next().await?;
-                        assert_matches!(peer_verack, Some(Message::Verack));
-                    }
-                    Some(other) => {
-                        let span = self.node().span().clone();
-                        error!(
-                            parent: span,
-                            "received non-version message during handshake: {:?}", other
-                        );
-                        panic!("Expected Version, got {:?}", other);
-                    }
-                    None => {
-                        // Connection was refused by main node, quietly abort handshake.
-                        return Err(io::ErrorKind::ConnectionRefused.into());
+        let mut framed_stream =
+            Framed::new(self.borrow_stream(&mut conn), self.message_codec(conn_addr));
+
+        let script = match (&self.handshake_script, self.handshake) {
+            (Some(script), _) => script.clone(),
+            (None, Some(kind)) => HandshakeScript::of(kind, node_conn_side),
+            (None, None) => HandshakeScript::new(),
+        };
+
+        // The version is addressed to the one the peer advertises, once received.
+        let mut peer_addr = conn_addr;
+        for step in script.steps() {
+            match step {
+                HandshakeStep::SendVersion => {
+                    let own_version = self.own_version(peer_addr, own_listening_addr);
+                    framed_stream.send(own_version).await?;
+                }
+                HandshakeStep::SendVerack => self.send_verack(&mut framed_stream).await?,
+                HandshakeStep::Send(message) => framed_stream.send(*message.clone()).await?,
+                HandshakeStep::ReceiveVersion => {
+                    let version = self
+                        .receive_version(&mut framed_stream, conn_addr, node_conn_side)
+                        .await?;
+                    if node_conn_side == ConnectionSide::Responder {
+                        peer_addr = version.addr_from.addr;
                     }
                 }
+                HandshakeStep::ReceiveVerack => {
+                    self.receive_verack(&mut framed_stream, conn_addr, node_conn_side)
+                        .await?
+                }
+                HandshakeStep::Delay(delay) => tokio::time::sleep(*delay).await,
             }
-            (Some(HandshakeKind::Full), ConnectionSide::Responder) => {
-                // Receive and send Version.
-                let peer_version = framed_stream.try_next().await?;
-                let node_addr = match peer_version {
-                    Some(Message::Version(version)) => version.addr_from.addr,
-                    Some(other) => {
-                        let span = self.node().span().clone();
-                        error!(
-                            parent: span,
-                            "received non-version message during handshake: {:?}", other
-                        );
-                        panic!("Expected Version, got {:?}", other);
-                    }
-                    None => return Err(io::ErrorKind::InvalidData.into()),
-                };
+        }
 
-                let own_version = Message::Version(Version::new(node_addr, own_listening_addr));
-                framed_stream.send(own_version).await?;
+        Ok(conn)
+    }
+}
 
-                // Receive and send Verack.
-                let peer_verack = framed_stream.try_next().await?;
-                assert_matches!(peer_verack, Some(Message::Verack));
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
 
-                framed_stream.send(Message::Verack).await?;
-            }
-            (Some(HandshakeKind::VersionOnly), ConnectionSide::Initiator) => {
-                let own_version = Message::Version(Version::new(conn_addr, own_listening_addr));
-                framed_stream.send(own_version).await?;
+    use super::*;
+    use crate::{protocol::payload::VersionBuilder, tools::LONG_TIMEOUT, wait_until};
 
-                let peer_version = framed_stream.try_next().await?;
-                assert_matches!(peer_version, Some(Message::Version(..)));
-            }
-            (Some(HandshakeKind::VersionOnly), ConnectionSide::Responder) => {
-                // Receive and send Version.
-                let peer_version = framed_stream.try_next().await?;
-                let node_addr = match peer_version {
-                    Some(Message::Version(version)) => version.addr_from.addr,
-                    Some(other) => {
-                        let span = self.node().span().clone();
-                        error!(
-                            parent: span,
-                            "received non-version message during handshake: {:?}", other
-                        );
-                        panic!("Expected Version, got {:?}", other);
-                    }
-                    None => return Err(io::ErrorKind::InvalidData.into()),
-                };
+    fn encoded_ping(network: Network) -> BytesMut {
+        let mut bytes = BytesMut::new();
+        Message::Ping(Nonce::default())
+            .encode(network, &mut bytes)
+            .unwrap();
+        bytes
+    }
+
+    fn frame_error(err: io::Error) -> FrameError {
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        err.get_ref()
+            .and_then(|err| err.downcast_ref::<FrameError>())
+            .cloned()
+            .expect("expected a FrameError")
+    }
 
-                let own_version = Message::Version(Version::new(node_addr, own_listening_addr));
-                framed_stream.send(own_version).await?;
+    #[test]
+    fn strict_rejects_bad_magic() {
+        let mut bytes = encoded_ping(Network::Mainnet);
+        let err = MessageCodec::new(Network::Testnet)
+            .decode(&mut bytes)
+            .unwrap_err();
+
+        assert_eq!(
+            frame_error(err),
+            FrameError::Magic {
+                expected: Network::Testnet.magic(),
+                actual: Network::Mainnet.magic(),
             }
-            (None, _) => {}
+        );
+    }
+
+    #[test]
+    fn strict_rejects_bad_checksum() {
+        let mut bytes = encoded_ping(Network::Testnet);
+        bytes[20] ^= 0xff;
+        let err = MessageCodec::new(Network::Testnet)
+            .decode(&mut bytes)
+            .unwrap_err();
+
+        assert_matches!(frame_error(err), FrameError::Checksum { .. });
+    }
+
+    #[test]
+    fn lenient_records_bad_checksum() {
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 8233));
+        let log = FrameErrorLog::default();
+        let mut codec = MessageCodec::new(Network::Testnet)
+            .with_mode(CodecMode::Lenient)
+            .with_error_log(addr, log.clone());
+
+        let mut bytes = encoded_ping(Network::Testnet);
+        bytes[20] ^= 0xff;
+
+        assert_matches!(codec.decode(&mut bytes), Ok(Some(Message::Ping(_))));
+        let errors = log.lock();
+        assert_eq!(errors.len(), 1);
+        assert_matches!(errors[0], (a, FrameError::Checksum { .. }) if a == addr);
+    }
+
+    #[test]
+    fn length_is_checked_before_the_body_arrives() {
+        let mut bytes = encoded_ping(Network::Testnet);
+        bytes.truncate(HEADER_LEN);
+        let err = MessageCodec::new(Network::Testnet)
+            .with_mode(CodecMode::Lenient)
+            .with_max_length(4)
+            .decode(&mut bytes)
+            .unwrap_err();
+
+        assert_eq!(frame_error(err), FrameError::Length { length: 8, max: 4 });
+    }
+
+    #[test]
+    fn lazy_blocks_keep_the_frame() {
+        let block = crate::protocol::payload::block::Block::testnet_1();
+        let message = Message::Block(Box::new(block.clone()));
+        let mut bytes = BytesMut::new();
+        message.encode(Network::Testnet, &mut bytes).unwrap();
+        let frame = bytes.clone();
+
+        let lazy_block = assert_matches!(
+            MessageCodec::new(Network::Testnet).with_lazy_blocks().decode(&mut bytes),
+            Ok(Some(Message::LazyBlock(lazy_block))) => lazy_block
+        );
+        assert_eq!(lazy_block, LazyBlock::from(&block));
+        assert_eq!(lazy_block.as_bytes()[..], frame[HEADER_LEN..]);
+
+        // Lazy blocks encode back into the same frame.
+        let mut reencoded = BytesMut::new();
+        Message::LazyBlock(lazy_block)
+            .encode(Network::Testnet, &mut reencoded)
+            .unwrap();
+        assert_eq!(reencoded, frame);
+    }
+
+    #[tokio::test]
+    async fn handshake_negotiates_addrv2() {
+        for (initiator_addrv2, responder_addrv2) in [(true, true), (true, false), (false, true)] {
+            let builder = |addrv2| {
+                let builder = SyntheticNode::builder().with_full_handshake();
+                if addrv2 {
+                    builder.with_addrv2()
+                } else {
+                    builder
+                }
+            };
+            let initiator = builder(initiator_addrv2).build().await.unwrap();
+            let responder = builder(responder_addrv2).build().await.unwrap();
+
+            initiator.connect(responder.listening_addr()).await.unwrap();
+            let initiator_addr = responder.wait_for_connection().await;
+
+            let negotiated = initiator_addrv2 && responder_addrv2;
+            assert_eq!(
+                initiator.is_addrv2_negotiated(responder.listening_addr()),
+                negotiated
+            );
+            assert_eq!(responder.is_addrv2_negotiated(initiator_addr), negotiated);
+
+            initiator.shut_down().await;
+            responder.shut_down().await;
         }
+    }
 
-        Ok(conn)
+    #[tokio::test]
+    async fn handshake_sends_version_template() {
+        let template = VersionBuilder::new(([0; 4], 0).into(), ([0; 4], 0).into())
+            .nonce(Nonce::new(7))
+            .user_agent("/template:1.0/")
+            .start_height(100)
+            .relay(None)
+            .build();
+        let node = SyntheticNode::builder()
+            .with_version_exchange_handshake()
+            .with_version_template(template.clone())
+            .build()
+            .await
+            .unwrap();
+
+        let mut stream = TcpStream::connect(node.listening_addr()).await.unwrap();
+        let own_addr = stream.local_addr().unwrap();
+        let mut framed = Framed::new(&mut stream, MessageCodec::new(Network::default()));
+        framed
+            .send(Message::Version(Version::new(
+                node.listening_addr(),
+                own_addr,
+            )))
+            .await
+            .unwrap();
+
+        let version = assert_matches!(
+            framed.try_next().await.unwrap(),
+            Some(Message::Version(version)) => version
+        );
+        assert_eq!(version.addr_recv.addr, own_addr);
+        assert_eq!(version.addr_from.addr, node.listening_addr());
+        assert_eq!(version.nonce, template.nonce);
+        assert_eq!(version.user_agent, template.user_agent);
+        assert_eq!(version.start_height, 100);
+        assert_eq!(version.relay, None);
+
+        node.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn scripted_deviations_are_reported() {
+        let ping = Message::Ping(Nonce::default());
+        let cases = [
+            (HandshakeScript::verack_first(), "Version", Message::Verack),
+            (
+                HandshakeScript::messages_before_verack(vec![ping.clone()]),
+                "Verack",
+                ping,
+            ),
+            (
+                HandshakeScript::repeated_version(),
+                "Verack",
+                Message::Version(Version::new(([0; 4], 0).into(), ([0; 4], 0).into())),
+            ),
+        ];
+
+        for (script, expected, received) in cases {
+            let responder = SyntheticNode::builder()
+                .with_full_handshake()
+                .build()
+                .await
+                .unwrap();
+            let initiator = SyntheticNode::builder()
+                .with_scripted_handshake(script)
+                .build()
+                .await
+                .unwrap();
+
+            assert!(initiator.connect(responder.listening_addr()).await.is_err());
+            wait_until!(LONG_TIMEOUT, !responder.handshake_errors().is_empty());
+
+            let (_, err) = responder.handshake_errors().remove(0);
+            assert_eq!(err.expected, expected);
+            // Versions differ in their nonce and addresses, only the kind is compared.
+            assert_eq!(
+                err.received.map(|message| message.command()),
+                Some(received.command())
+            );
+
+            initiator.shut_down().await;
+            responder.shut_down().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn scripted_handshake_completes() {
+        let responder = SyntheticNode::builder()
+            .with_full_handshake()
+            .build()
+            .await
+            .unwrap();
+        let initiator = SyntheticNode::builder()
+            .with_scripted_handshake(HandshakeScript::delayed_verack(Duration::from_millis(100)))
+            .build()
+            .await
+            .unwrap();
+
+        initiator.connect(responder.listening_addr()).await.unwrap();
+        assert!(initiator.is_connected(responder.listening_addr()));
+
+        // A peer closing the connection is reported as such.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener_addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut framed = Framed::new(&mut stream, MessageCodec::new(Network::default()));
+            assert_matches!(framed.try_next().await, Ok(Some(Message::Version(_))));
+        });
+
+        let err = initiator.connect(listener_addr).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+        let err = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<HandshakeError>())
+            .unwrap();
+        assert_eq!(err.expected, "Version");
+        assert_eq!(err.received, None);
+
+        initiator.shut_down().await;
+        responder.shut_down().await;
+    }
+
+    #[test]
+    fn closed_handshakes_error_by_side() {
+        let closed = HandshakeError {
+            expected: "Verack",
+            received: None,
+        };
+        let unexpected = HandshakeError {
+            expected: "Verack",
+            received: Some(Box::new(Message::MemPool)),
+        };
+
+        let kinds = [
+            (
+                &closed,
+                ConnectionSide::Initiator,
+                ErrorKind::ConnectionRefused,
+            ),
+            (&closed, ConnectionSide::Responder, ErrorKind::InvalidData),
+            (
+                &unexpected,
+                ConnectionSide::Initiator,
+                ErrorKind::InvalidData,
+            ),
+            (
+                &unexpected,
+                ConnectionSide::Responder,
+                ErrorKind::InvalidData,
+            ),
+        ];
+        for (err, side, kind) in kinds {
+            assert_eq!(err.clone().into_io_error(side).kind(), kind);
+        }
     }
 }